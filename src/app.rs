@@ -1,4 +1,4 @@
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use windows::{
     core::Result,
@@ -8,6 +8,10 @@ use windows::{
         TypedEventHandler,
     },
     System::{DispatcherQueue, DispatcherQueueTimer},
+    Win32::UI::Input::KeyboardAndMouse::{
+        VIRTUAL_KEY, VK_ADD, VK_DOWN, VK_END, VK_HOME, VK_LEFT, VK_OEM_COMMA, VK_OEM_MINUS,
+        VK_OEM_PERIOD, VK_OEM_PLUS, VK_RIGHT, VK_SPACE, VK_SUBTRACT, VK_UP,
+    },
     UI::{
         Color,
         Composition::{CompositionStretch, Compositor, ContainerVisual, SpriteVisual},
@@ -15,32 +19,51 @@ use windows::{
 };
 
 use crate::{
-    chart::ChartSurface, perf::PerfTracker, pid::get_name_from_pid, renderer::Renderer,
-    text_block::TextBlock, windows_utils::numerics::ToVector2,
+    chart::ChartSurface,
+    chart_labels::ChartLabels,
+    chart_model::ChartOptions,
+    legend::Legend,
+    metric::{MetricSource, Reading},
+    playback::{apply_action, ClockAction, PlaybackClock, PlaybackCommand},
+    renderer::Renderer,
+    scene::{axis_labels, legend_entries, title, value_label},
+    text_block::TextBlock,
+    windows_utils::{error::to_windows_error, numerics::ToVector2},
 };
 
+const MIN_TICK_INTERVAL: Duration = Duration::from_millis(1);
+
 pub struct App {
     queue: DispatcherQueue,
     renderer: Renderer,
     chart: ChartSurface,
     process_name_text: TextBlock,
     utilization_text: TextBlock,
+    legend: Legend,
+    chart_labels: ChartLabels,
     chart_visual: SpriteVisual,
     info_root: ContainerVisual,
-    perf_tracker: PerfTracker,
+    source: Box<dyn MetricSource>,
+    /// Set when the source plays back a recording.
+    playback: Option<PlaybackClock>,
     timer: DispatcherQueueTimer,
+    last_tick: Instant,
     root: SpriteVisual,
     timer_token: EventRegistrationToken,
 }
 
 impl App {
-    pub fn new(process_id: u32, dpi: u32) -> Result<Box<Self>> {
-        let mut app = Box::new(Self::new_internal(process_id, dpi)?);
+    pub fn new(
+        source: Box<dyn MetricSource>,
+        dpi: u32,
+        options: &ChartOptions,
+    ) -> Result<Box<Self>> {
+        let mut app = Box::new(Self::new_internal(source, dpi, options)?);
         let timer = app.timer.clone();
         let timer_token = timer.Tick(&TypedEventHandler::<_, _>::new({
             // SAFETY: We know that the timer will only tick on the same thread
             // as the dispatcher queue (our UI thread). As long as we remove the tick
-            // handler before the end of the lifetime of our perf tracker object,
+            // handler before the end of the lifetime of our metric source,
             // we should be fine.
             let app_ptr = Box::into_raw(app);
             let app_workaround: u64 = app_ptr as _;
@@ -52,7 +75,8 @@ impl App {
             }
         }))?;
 
-        app.perf_tracker.start()?;
+        app.source.start().map_err(to_windows_error)?;
+        app.last_tick = Instant::now();
         app.timer.Start()?;
         app.timer_token = timer_token;
 
@@ -76,7 +100,7 @@ impl App {
         // on the same thread.
         self.timer.RemoveTick(self.timer_token)?;
         self.timer.Stop()?;
-        self.perf_tracker.close()?;
+        self.source.close().map_err(to_windows_error)?;
         Ok(())
     }
 
@@ -86,29 +110,141 @@ impl App {
 
         self.process_name_text.set_dpi(&self.renderer, dpi)?;
         self.utilization_text.set_dpi(&self.renderer, dpi)?;
+        self.legend
+            .set_dpi(&self.renderer, dpi, self.chart.size().Width as f32)?;
+        let size = self.chart.size();
+        self.chart_labels.set_dpi(
+            &self.renderer,
+            dpi,
+            size.Width as f32,
+            size.Height as f32,
+            axis_labels(self.chart.model(), self.source.unit()),
+        )?;
+
+        self.layout_info()
+    }
+
+    /// Drives playbacks; live charts ignore the keyboard. A command that
+    /// fails, e.g. a seek into a damaged part of a recording, pauses the
+    /// playback and shows why in the title instead of closing the window.
+    pub fn on_key_down(&mut self, key: VIRTUAL_KEY) {
+        if let Err(error) = self.apply_key(key) {
+            self.show_error(&error);
+        }
+    }
+
+    fn apply_key(&mut self, key: VIRTUAL_KEY) -> Result<()> {
+        let Some(clock) = self.playback.as_mut() else {
+            return Ok(());
+        };
+        // The arrow keys page through the recording a chart at a time.
+        let page = self.chart.model().point_count() as i64 - 1;
+        let Some(command) = playback_command(key, page) else {
+            return Ok(());
+        };
+        let action = clock.apply(command);
+        self.timer
+            .SetInterval(clock.tick_interval().max(MIN_TICK_INTERVAL).into())?;
+        self.play(action)
+    }
+
+    fn on_tick(&mut self) -> Result<()> {
+        let now = Instant::now();
+        let elapsed = now - self.last_tick;
+        self.last_tick = now;
+        if let Some(clock) = self.playback.as_mut() {
+            // Nothing moves, and the title already says why.
+            if clock.is_paused() || clock.is_finished() {
+                return Ok(());
+            }
+            let action = clock.advance(elapsed);
+            return self.play(action);
+        }
+        let readings = self.source.sample().map_err(to_windows_error)?;
+        self.chart.add_readings(&readings);
+        self.refresh(Some(&readings))
+    }
+
+    fn play(&mut self, action: ClockAction) -> Result<()> {
+        if action == ClockAction::Nothing {
+            // Pausing or changing speed still shows up in the title.
+            self.update_title()?;
+            return self.layout_info();
+        }
+        let readings = apply_action(action, self.source.as_mut(), self.chart.model_mut())
+            .map_err(to_windows_error)?;
+        self.refresh(readings.as_deref())
+    }
+
+    /// Redraws the chart and everything around it. `readings` are the
+    /// latest ones charted, if any.
+    fn refresh(&mut self, readings: Option<&[Reading]>) -> Result<()> {
+        self.chart.redraw(&self.renderer)?;
+        let unit = self.source.unit();
+        if let Some(readings) = readings {
+            self.utilization_text
+                .set_text(&self.renderer, value_label(unit, readings))?;
+        }
+        self.legend
+            .update(&self.renderer, &legend_entries(self.chart.model(), unit))?;
+        self.chart_labels
+            .update(&self.renderer, axis_labels(self.chart.model(), unit))?;
+        self.update_title()?;
+        self.layout_info()
+    }
+
+    /// Pauses and shows `error` next to the title until the next command.
+    fn show_error(&mut self, error: &windows::core::Error) {
+        if let Some(clock) = self.playback.as_mut() {
+            if !clock.is_paused() {
+                clock.apply(PlaybackCommand::TogglePause);
+            }
+        }
+        let title = format!(
+            "{} - {}",
+            title(self.source.as_ref(), self.playback.as_ref()),
+            error.message()
+        );
+        let shown = self
+            .process_name_text
+            .set_text(&self.renderer, title)
+            .and_then(|_| self.layout_info());
+        if shown.is_err() {
+            eprintln!("{}", error.message());
+        }
+    }
+
+    fn update_title(&mut self) -> Result<()> {
+        let title = title(self.source.as_ref(), self.playback.as_ref());
+        if self.process_name_text.text() != title {
+            self.process_name_text.set_text(&self.renderer, title)?;
+        }
+        Ok(())
+    }
 
-        let info_height = {
+    /// Stacks the title row and the legend above the chart.
+    fn layout_info(&self) -> Result<()> {
+        let title_height = {
             let process_name_height = self.process_name_text.root().Size()?;
             let utilization_height = self.utilization_text.root().Size()?;
             process_name_height.Y.max(utilization_height.Y)
         };
+        self.legend
+            .root()
+            .SetOffset(Vector3::new(0.0, title_height, 0.0))?;
+
+        let info_height = title_height + self.legend.height();
         self.info_root.SetSize(Vector2::new(0.0, info_height))?;
         self.info_root
             .SetOffset(Vector3::new(0.0, -info_height, 0.0))?;
-
-        Ok(())
-    }
-
-    fn on_tick(&mut self) -> Result<()> {
-        let utilization_value = self.perf_tracker.get_current_value()?;
-        self.chart.add_point(utilization_value as f32);
-        self.chart.redraw(&self.renderer)?;
-        self.utilization_text
-            .set_text(&self.renderer, format!("{}%", utilization_value as i32))?;
         Ok(())
     }
 
-    fn new_internal(process_id: u32, dpi: u32) -> Result<Self> {
+    fn new_internal(
+        source: Box<dyn MetricSource>,
+        dpi: u32,
+        options: &ChartOptions,
+    ) -> Result<Self> {
         let queue = DispatcherQueue::GetForCurrentThread()?;
         let renderer = Renderer::new()?;
 
@@ -122,7 +258,8 @@ impl App {
             B: 255,
         })?)?;
 
-        let chart = ChartSurface::new(&renderer, dpi)?;
+        let mut chart = ChartSurface::new(&renderer, dpi)?;
+        chart.set_options(options);
         let chart_visual = compositor.CreateSpriteVisual()?;
         chart_visual.SetSize(chart.size().to_vector2())?;
         chart_visual.SetRelativeOffsetAdjustment(Vector3::new(0.5, 0.5, 0.0))?;
@@ -133,10 +270,13 @@ impl App {
         root.Children()?.InsertAtTop(&chart_visual)?;
         chart.redraw(&renderer)?;
 
-        let process_name = get_name_from_pid(process_id)?;
+        let playback = source
+            .playback()
+            .map(|playback| PlaybackClock::new(playback, chart.model().point_count()));
+
         let process_name_text = TextBlock::new(
             &renderer,
-            process_name,
+            title(source.as_ref(), playback.as_ref()),
             Color {
                 A: 255,
                 R: 0,
@@ -148,7 +288,7 @@ impl App {
 
         let utilization_text = TextBlock::new(
             &renderer,
-            "0%".to_owned(),
+            source.unit().format_value(0.0),
             Color {
                 A: 255,
                 R: 112,
@@ -161,40 +301,71 @@ impl App {
         utilization_text_root.SetAnchorPoint(Vector2::new(1.0, 0.0))?;
         utilization_text_root.SetRelativeOffsetAdjustment(Vector3::new(1.0, 0.0, 0.0))?;
 
-        let info_height = {
-            let process_name_height = process_name_text.root().Size()?;
-            let utilization_height = utilization_text_root.Size()?;
-            process_name_height.Y.max(utilization_height.Y)
-        };
+        let legend = Legend::new(&renderer, dpi, chart.size().Width as f32)?;
+
+        let chart_size = chart.size();
+        let mut chart_labels = ChartLabels::new(
+            &renderer,
+            dpi,
+            chart_size.Width as f32,
+            chart_size.Height as f32,
+        )?;
+        chart_labels.update(&renderer, axis_labels(chart.model(), source.unit()))?;
+        chart_visual.Children()?.InsertAtTop(chart_labels.root())?;
 
         let info_root = compositor.CreateContainerVisual()?;
         info_root.SetRelativeSizeAdjustment(Vector2::new(1.0, 0.0))?;
-        info_root.SetSize(Vector2::new(0.0, info_height))?;
-        info_root.SetOffset(Vector3::new(0.0, -info_height, 0.0))?;
         chart_visual.Children()?.InsertAtTop(&info_root)?;
 
         let info_root_children = info_root.Children()?;
         info_root_children.InsertAtTop(process_name_text.root())?;
         info_root_children.InsertAtTop(utilization_text_root)?;
-
-        let perf_tracker = PerfTracker::new(process_id)?;
+        info_root_children.InsertAtTop(legend.root())?;
 
         let timer = queue.CreateTimer()?;
-        timer.SetInterval(Duration::from_secs(1).into())?;
+        // A zero interval plays back instantly, but still has to give the
+        // rest of the queue a chance to run.
+        let interval = match &playback {
+            Some(clock) => clock.tick_interval(),
+            None => options.time_scale.interval,
+        };
+        timer.SetInterval(interval.max(MIN_TICK_INTERVAL).into())?;
         timer.SetIsRepeating(true)?;
 
-        Ok(Self {
+        let app = Self {
             queue,
             renderer,
             chart,
             process_name_text,
             utilization_text,
+            legend,
+            chart_labels,
             chart_visual,
             info_root,
-            perf_tracker,
+            source,
+            playback,
             timer,
+            last_tick: Instant::now(),
             root,
             timer_token: Default::default(),
-        })
+        };
+        app.layout_info()?;
+        Ok(app)
     }
 }
+
+fn playback_command(key: VIRTUAL_KEY, page: i64) -> Option<PlaybackCommand> {
+    let command = match key {
+        VK_SPACE => PlaybackCommand::TogglePause,
+        VK_OEM_PERIOD => PlaybackCommand::StepForward,
+        VK_OEM_COMMA => PlaybackCommand::StepBack,
+        VK_RIGHT => PlaybackCommand::SeekBy(page),
+        VK_LEFT => PlaybackCommand::SeekBy(-page),
+        VK_HOME => PlaybackCommand::SeekTo(0),
+        VK_END => PlaybackCommand::SeekTo(u64::MAX),
+        VK_UP | VK_OEM_PLUS | VK_ADD => PlaybackCommand::Faster,
+        VK_DOWN | VK_OEM_MINUS | VK_SUBTRACT => PlaybackCommand::Slower,
+        _ => return None,
+    };
+    Some(command)
+}
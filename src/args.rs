@@ -0,0 +1,404 @@
+use std::{path::PathBuf, time::Duration};
+
+use crate::{
+    axis::{ScaleMode, TimeScale},
+    chart_model::{ChartOptions, Color, SeriesLayout, SeriesStyleOverride},
+    counter_path::{CounterPath, ParseCounterPathError},
+    metric::{MetricSource, PlaybackSpeed},
+    pid::parse_pid,
+};
+
+// Shorter intervals make rate counters too noisy to read.
+const MIN_INTERVAL: Duration = Duration::from_millis(100);
+// Enough for a day at one sample per second. Every point is redrawn on
+// each tick, so much more than that can't keep up.
+const MAX_POINT_COUNT: usize = 100_000;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SourceKind {
+    Cpu,
+    Gpu,
+}
+
+/// How GPU engine utilization is split into series.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Grouping {
+    /// A series per engine type, summed across adapters.
+    #[default]
+    EngineType,
+    /// A series per adapter.
+    Adapter,
+}
+
+pub struct Args {
+    /// Several processes are charted side by side.
+    pub process_ids: Vec<u32>,
+    pub source: Option<SourceKind>,
+    /// Only chart this GPU engine instead of the total.
+    pub engine: Option<String>,
+    /// How GPU engines are split into series.
+    pub grouping: Grouping,
+    /// Chart these performance counters instead of a process.
+    pub counters: Vec<CounterPath>,
+    /// How the value axis follows the data. Depends on the unit if not
+    /// given.
+    pub scale_mode: Option<ScaleMode>,
+    pub layout: SeriesLayout,
+    pub time_scale: TimeScale,
+    pub series_styles: Vec<(String, SeriesStyleOverride)>,
+    /// Render headlessly to these files instead of opening a window.
+    pub png: Option<PathBuf>,
+    pub svg: Option<PathBuf>,
+    /// Record every sample to this file, as CSV if it ends in `.csv`.
+    pub record: Option<PathBuf>,
+    /// Play back this recording instead of sampling live.
+    pub replay: Option<PathBuf>,
+    pub speed: PlaybackSpeed,
+    /// How many points to collect before rendering headlessly. Fills the
+    /// window if not given.
+    pub samples: Option<usize>,
+    pub scale: f32,
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        Self {
+            process_ids: Vec::new(),
+            source: None,
+            engine: None,
+            grouping: Grouping::default(),
+            counters: Vec::new(),
+            scale_mode: None,
+            layout: SeriesLayout::default(),
+            time_scale: TimeScale::default(),
+            series_styles: Vec::new(),
+            png: None,
+            svg: None,
+            record: None,
+            replay: None,
+            speed: PlaybackSpeed::Multiplier(1.0),
+            samples: None,
+            scale: 1.0,
+        }
+    }
+}
+
+pub fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Args, String> {
+    let mut result = Args::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--source" => {
+                result.source = match next_value(&mut args, &arg)?.as_str() {
+                    "cpu" => Some(SourceKind::Cpu),
+                    "gpu" => Some(SourceKind::Gpu),
+                    value => return Err(format!("Unknown source '{}'!", value)),
+                };
+            }
+            "--engine" => result.engine = Some(next_value(&mut args, &arg)?),
+            "--group" => {
+                result.grouping = match next_value(&mut args, &arg)?.as_str() {
+                    "engine" => Grouping::EngineType,
+                    "adapter" => Grouping::Adapter,
+                    value => return Err(format!("Unknown grouping '{}'!", value)),
+                };
+            }
+            "--counter" => {
+                let path = next_value(&mut args, &arg)?
+                    .parse()
+                    .map_err(|error: ParseCounterPathError| error.to_string())?;
+                result.counters.push(path);
+            }
+            "--y-axis" => {
+                result.scale_mode = Some(parse_scale_mode(&next_value(&mut args, &arg)?)?);
+            }
+            "--stack" => {
+                if result.layout == SeriesLayout::Overlaid {
+                    result.layout = SeriesLayout::Stacked { order: Vec::new() };
+                }
+            }
+            "--stack-order" => {
+                let order = next_value(&mut args, &arg)?
+                    .split(',')
+                    .map(str::to_owned)
+                    .collect();
+                result.layout = SeriesLayout::Stacked { order };
+            }
+            "--window" => {
+                result.time_scale.window = parse_duration(&next_value(&mut args, &arg)?)
+                    .ok_or_else(|| "Failed to parse window!".to_owned())?;
+            }
+            "--interval" => {
+                result.time_scale.interval = parse_duration(&next_value(&mut args, &arg)?)
+                    .ok_or_else(|| "Failed to parse sample interval!".to_owned())?;
+            }
+            "--series-style" => {
+                let value = next_value(&mut args, &arg)?;
+                result.series_styles.push(parse_series_style(&value)?);
+            }
+            "--png" => result.png = Some(PathBuf::from(next_value(&mut args, &arg)?)),
+            "--svg" => result.svg = Some(PathBuf::from(next_value(&mut args, &arg)?)),
+            "--record" => result.record = Some(PathBuf::from(next_value(&mut args, &arg)?)),
+            "--replay" => result.replay = Some(PathBuf::from(next_value(&mut args, &arg)?)),
+            "--speed" => result.speed = parse_speed(&next_value(&mut args, &arg)?)?,
+            "--samples" => {
+                result.samples = Some(
+                    next_value(&mut args, &arg)?
+                        .parse()
+                        .map_err(|_| "Failed to parse sample count!".to_owned())?,
+                );
+            }
+            "--scale" => {
+                let scale: f32 = next_value(&mut args, &arg)?
+                    .parse()
+                    .map_err(|_| "Failed to parse scale!".to_owned())?;
+                if scale.is_nan() || scale <= 0.0 {
+                    return Err("Scale must be positive!".to_owned());
+                }
+                result.scale = scale;
+            }
+            _ if arg.starts_with("--") => return Err(format!("Unknown option '{}'!", arg)),
+            _ => {
+                let pid = parse_pid(&arg).map_err(|_| "Failed to parse process id!".to_owned())?;
+                result.process_ids.push(pid);
+            }
+        }
+    }
+    if !result.counters.is_empty() && (!result.process_ids.is_empty() || result.source.is_some()) {
+        return Err("Counters can't be charted along with a process!".to_owned());
+    }
+    let is_live =
+        !result.counters.is_empty() || !result.process_ids.is_empty() || result.source.is_some();
+    if result.replay.is_some() && is_live {
+        return Err("A recording can't be played back along with live data!".to_owned());
+    }
+    if result.is_conversion() && result.is_headless() {
+        return Err("A recording can't be converted and charted at once!".to_owned());
+    }
+    let TimeScale { window, interval } = result.time_scale;
+    if interval < MIN_INTERVAL {
+        return Err(format!(
+            "The sample interval must be at least {}ms!",
+            MIN_INTERVAL.as_millis()
+        ));
+    }
+    if window < interval * 2 {
+        return Err("The window must fit at least two samples!".to_owned());
+    }
+    if result.time_scale.point_count() > MAX_POINT_COUNT {
+        return Err(format!(
+            "The window can't fit more than {} samples!",
+            MAX_POINT_COUNT
+        ));
+    }
+    Ok(result)
+}
+
+impl Args {
+    pub fn is_headless(&self) -> bool {
+        self.png.is_some() || self.svg.is_some()
+    }
+
+    /// Recording a playback converts it without charting it.
+    pub fn is_conversion(&self) -> bool {
+        self.replay.is_some() && self.record.is_some()
+    }
+
+    /// The chart settings for `source`. Playbacks are charted at the
+    /// interval they were recorded at, which may be shorter than live
+    /// sampling allows, so their window shrinks to fit `MAX_POINT_COUNT`.
+    pub fn chart_options(&self, source: &dyn MetricSource) -> Result<ChartOptions, String> {
+        let mut time_scale = self.time_scale;
+        if let Some(playback) = source.playback() {
+            let interval = playback.interval;
+            if interval.is_zero() {
+                return Err("The recording has no sample interval!".to_owned());
+            }
+            let max_window = interval
+                .checked_mul(MAX_POINT_COUNT as u32 - 1)
+                .unwrap_or(Duration::MAX);
+            time_scale.interval = interval;
+            time_scale.window = time_scale.window.max(interval * 2).min(max_window);
+        }
+        Ok(ChartOptions {
+            scale_mode: self
+                .scale_mode
+                .unwrap_or_else(|| ScaleMode::for_unit(source.unit())),
+            layout: self.layout.clone(),
+            time_scale,
+            series_styles: self.series_styles.clone(),
+        })
+    }
+}
+
+/// Parses `auto`, `fit` or a fixed range like `0:100`.
+fn parse_scale_mode(value: &str) -> Result<ScaleMode, String> {
+    match value {
+        "auto" => Ok(ScaleMode::AutoExpand { min: 0.0, max: 0.0 }),
+        "fit" => Ok(ScaleMode::Fit),
+        _ => {
+            let (min, max) = value
+                .split_once(':')
+                .and_then(|(min, max)| Some((min.parse().ok()?, max.parse().ok()?)))
+                .ok_or_else(|| format!("Unknown y axis '{}'!", value))?;
+            if !(f64::is_finite(min) && f64::is_finite(max) && min < max) {
+                return Err("The y axis range must go from low to high!".to_owned());
+            }
+            Ok(ScaleMode::Fixed { min, max })
+        }
+    }
+}
+
+/// Parses a multiple of real time like `4` or `0.5`, or `instant`.
+fn parse_speed(value: &str) -> Result<PlaybackSpeed, String> {
+    if value == "instant" {
+        return Ok(PlaybackSpeed::Instant);
+    }
+    match value.trim_end_matches('x').parse::<f64>() {
+        Ok(multiplier) if multiplier.is_finite() && multiplier > 0.0 => {
+            Ok(PlaybackSpeed::Multiplier(multiplier))
+        }
+        _ => Err(format!("Unknown speed '{}'!", value)),
+    }
+}
+
+/// Parses a duration like `500ms`, `10s`, `5m` or `24h`.
+fn parse_duration(value: &str) -> Option<Duration> {
+    let split = value.find(|char: char| !char.is_ascii_digit() && char != '.')?;
+    let (number, unit) = value.split_at(split);
+    let number: f64 = number.parse().ok()?;
+    let seconds_per_unit = match unit {
+        "ms" => 0.001,
+        "s" => 1.0,
+        "m" => 60.0,
+        "h" => 3600.0,
+        _ => return None,
+    };
+    Duration::try_from_secs_f64(number * seconds_per_unit).ok()
+}
+
+/// Parses a series name followed by its settings, e.g.
+/// `3D=color:#117DBB,line:2,fill:0`.
+fn parse_series_style(value: &str) -> Result<(String, SeriesStyleOverride), String> {
+    let (name, settings) = value
+        .rsplit_once('=')
+        .ok_or_else(|| format!("Expected <series>=<settings> instead of '{}'!", value))?;
+    let mut style = SeriesStyleOverride::default();
+    for setting in settings.split(',') {
+        let (key, value) = setting
+            .split_once(':')
+            .ok_or_else(|| format!("Expected <key>:<value> instead of '{}'!", setting))?;
+        match key {
+            "color" => {
+                style.color =
+                    Some(parse_color(value).ok_or_else(|| format!("Unknown color '{}'!", value))?)
+            }
+            "line" => style.line_width = Some(parse_non_negative(value, "line width")?),
+            "fill" => {
+                let opacity = parse_non_negative(value, "fill opacity")?;
+                if opacity > 1.0 {
+                    return Err("Fill opacity must be between 0 and 1!".to_owned());
+                }
+                style.fill_opacity = Some(opacity);
+            }
+            _ => return Err(format!("Unknown series setting '{}'!", key)),
+        }
+    }
+    Ok((name.to_owned(), style))
+}
+
+/// Parses a `#RRGGBB` color.
+fn parse_color(value: &str) -> Option<Color> {
+    let hex = value.strip_prefix('#')?;
+    if hex.len() != 6 || !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return None;
+    }
+    let rgb = u32::from_str_radix(hex, 16).ok()?;
+    let channel = |shift: u32| ((rgb >> shift) & 0xFF) as f32 / 255.0;
+    Some(Color::new(channel(16), channel(8), channel(0), 1.0))
+}
+
+fn parse_non_negative(value: &str, what: &str) -> Result<f32, String> {
+    match value.parse::<f32>() {
+        Ok(number) if number >= 0.0 => Ok(number),
+        _ => Err(format!("Failed to parse {}!", what)),
+    }
+}
+
+fn next_value<I: Iterator<Item = String>>(args: &mut I, option: &str) -> Result<String, String> {
+    args.next()
+        .ok_or_else(|| format!("Missing value for '{}'!", option))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metric::{Playback, Reading, Result, Unit};
+
+    /// A playback that only exists to be charted.
+    struct FakePlayback(Duration);
+
+    impl MetricSource for FakePlayback {
+        fn name(&self) -> &str {
+            "recording"
+        }
+
+        fn unit(&self) -> Unit {
+            Unit::Percent
+        }
+
+        fn start(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn sample(&mut self) -> Result<Vec<Reading>> {
+            Ok(Vec::new())
+        }
+
+        fn close(self: Box<Self>) -> Result<()> {
+            Ok(())
+        }
+
+        fn playback(&self) -> Option<Playback> {
+            Some(Playback {
+                interval: self.0,
+                speed: PlaybackSpeed::Instant,
+                length: 0,
+            })
+        }
+    }
+
+    fn playback_time_scale(window: &str, interval: Duration) -> TimeScale {
+        let args = parse_args(["--window".to_owned(), window.to_owned()]).unwrap();
+        args.chart_options(&FakePlayback(interval))
+            .unwrap()
+            .time_scale
+    }
+
+    #[test]
+    fn playbacks_use_their_interval() {
+        let time_scale = playback_time_scale("60s", Duration::from_secs(2));
+        assert_eq!(time_scale.interval, Duration::from_secs(2));
+        assert_eq!(time_scale.window, Duration::from_secs(60));
+    }
+
+    #[test]
+    fn playback_windows_fit_two_samples() {
+        let time_scale = playback_time_scale("60s", Duration::from_secs(120));
+        assert_eq!(time_scale.window, Duration::from_secs(240));
+        assert_eq!(time_scale.point_count(), 3);
+    }
+
+    #[test]
+    fn playback_windows_fit_the_point_limit() {
+        let time_scale = playback_time_scale("24h", Duration::from_millis(1));
+        assert_eq!(time_scale.interval, Duration::from_millis(1));
+        assert_eq!(time_scale.point_count(), MAX_POINT_COUNT);
+    }
+
+    #[test]
+    fn rejects_playbacks_without_an_interval() {
+        let args = parse_args(["--window".to_owned(), "60s".to_owned()]).unwrap();
+        assert!(args.chart_options(&FakePlayback(Duration::ZERO)).is_err());
+    }
+}
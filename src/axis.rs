@@ -0,0 +1,425 @@
+use std::time::Duration;
+
+use crate::metric::Unit;
+
+/// How the value range of a chart follows the data.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ScaleMode {
+    /// Always shows `min..max`. Values outside the range are clipped.
+    Fixed { min: f64, max: f64 },
+    /// Starts at `min..max` and grows to fit larger values. The range
+    /// never shrinks, so the chart doesn't jump around as values drop.
+    AutoExpand { min: f64, max: f64 },
+    /// Fits the values currently on screen, always including zero.
+    Fit,
+}
+
+impl ScaleMode {
+    /// Percentages start out at 0–100%, but can go over (e.g. engines
+    /// summed across adapters). Anything else has no natural range.
+    pub fn for_unit(unit: Unit) -> Self {
+        match unit {
+            Unit::Percent => ScaleMode::AutoExpand {
+                min: 0.0,
+                max: 100.0,
+            },
+            Unit::Count => ScaleMode::Fit,
+        }
+    }
+}
+
+/// The range of an axis along with the spacing of its ticks.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Ticks {
+    pub min: f64,
+    pub max: f64,
+    pub step: f64,
+}
+
+impl Ticks {
+    /// Every multiple of `step` within the range, from the bottom up.
+    pub fn values(&self) -> Vec<f64> {
+        let first = (self.min / self.step).ceil() as i64;
+        let last = (self.max / self.step).floor() as i64;
+        // Multiplying instead of adding up the steps keeps rounding errors
+        // from accumulating.
+        (first..=last).map(|i| i as f64 * self.step).collect()
+    }
+}
+
+/// The smallest 1, 2 or 5 × 10^n that is at least `raw_step`.
+pub fn nice_step(raw_step: f64) -> f64 {
+    if !raw_step.is_finite() || raw_step <= 0.0 {
+        return 1.0;
+    }
+    let magnitude = 10f64.powf(raw_step.log10().floor());
+    // Leaves some room for rounding errors, so 10 doesn't become 20.
+    let fraction = raw_step / magnitude - 1e-9;
+    let nice = if fraction <= 1.0 {
+        1.0
+    } else if fraction <= 2.0 {
+        2.0
+    } else if fraction <= 5.0 {
+        5.0
+    } else {
+        10.0
+    };
+    nice * magnitude
+}
+
+/// The next larger nice step after `step`, e.g. 20 after 10.
+fn next_nice_step(step: f64) -> f64 {
+    nice_step(step * 1.001)
+}
+
+/// Rounds `min..max` out to the nearest nice ticks, using as few ticks as
+/// possible while keeping at most `max_ticks` intervals.
+pub fn nice_ticks(min: f64, max: f64, max_ticks: usize) -> Ticks {
+    let (min, max) = if max > min {
+        (min, max)
+    } else {
+        (min, min + 1.0)
+    };
+    // Infinite ranges can't be rounded out, so they'd never fit.
+    if !(max - min).is_finite() {
+        return nice_ticks(0.0, 1.0, max_ticks);
+    }
+    let max_ticks = max_ticks.max(1) as f64;
+    let mut step = nice_step((max - min) / max_ticks);
+    loop {
+        let nice_min = (min / step).floor() * step;
+        let nice_max = (max / step).ceil() * step;
+        if !(nice_max - nice_min).is_finite() {
+            return nice_ticks(0.0, 1.0, max_ticks as usize);
+        }
+        // Rounding out can add up to two intervals.
+        if ((nice_max - nice_min) / step).round() <= max_ticks {
+            return Ticks {
+                min: nice_min,
+                max: nice_max,
+                step,
+            };
+        }
+        step = next_nice_step(step);
+    }
+}
+
+/// The value axis of a chart, which maps values to heights.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct YAxis {
+    mode: ScaleMode,
+    max_ticks: usize,
+    ticks: Ticks,
+}
+
+impl YAxis {
+    pub fn new(mode: ScaleMode, max_ticks: usize) -> Self {
+        let ticks = match mode {
+            ScaleMode::Fixed { min, max } => fixed_ticks(min, max, max_ticks),
+            ScaleMode::AutoExpand { min, max } => nice_ticks(min, max, max_ticks),
+            ScaleMode::Fit => nice_ticks(0.0, 1.0, max_ticks),
+        };
+        Self {
+            mode,
+            max_ticks,
+            ticks,
+        }
+    }
+
+    pub fn ticks(&self) -> &Ticks {
+        &self.ticks
+    }
+
+    /// Goes back to the starting range, forgetting how far it expanded.
+    pub fn reset(&mut self) {
+        *self = Self::new(self.mode, self.max_ticks);
+    }
+
+    /// Adjusts the range to the smallest and largest value on screen, or
+    /// `None` if there aren't any.
+    pub fn update(&mut self, data_range: Option<(f64, f64)>) {
+        let Some((data_min, data_max)) = data_range else {
+            return;
+        };
+        match self.mode {
+            ScaleMode::Fixed { .. } => {}
+            ScaleMode::AutoExpand { .. } => {
+                let min = self.ticks.min.min(data_min);
+                let max = self.ticks.max.max(data_max);
+                if min < self.ticks.min || max > self.ticks.max {
+                    self.ticks = nice_ticks(min, max, self.max_ticks);
+                }
+            }
+            ScaleMode::Fit => {
+                self.ticks = nice_ticks(data_min.min(0.0), data_max.max(0.0), self.max_ticks);
+            }
+        }
+    }
+
+    /// Where `value` falls within the range, from 0 at the bottom to 1 at
+    /// the top. Values outside the range are clamped.
+    pub fn fraction(&self, value: f64) -> f64 {
+        let range = self.ticks.max - self.ticks.min;
+        if range <= 0.0 {
+            return 0.0;
+        }
+        ((value - self.ticks.min) / range).clamp(0.0, 1.0)
+    }
+}
+
+/// Ticks for a range that can't move, so it isn't rounded out.
+fn fixed_ticks(min: f64, max: f64, max_ticks: usize) -> Ticks {
+    let max = if max > min { max } else { min + 1.0 };
+    if !(max - min).is_finite() {
+        return nice_ticks(0.0, 1.0, max_ticks);
+    }
+    let mut step = nice_step((max - min) / max_ticks.max(1) as f64);
+    // A range that isn't a multiple of the step can fit one more tick.
+    while ((max / step).floor() - (min / step).ceil()) as usize > max_ticks {
+        step = next_nice_step(step);
+    }
+    Ticks { min, max, step }
+}
+
+// The most columns the vertical grid lines split the chart into.
+const MAX_TIME_CELLS: u32 = 6;
+/// Grid steps that read well on a clock, from a second to a day.
+const TIME_STEPS_IN_SECONDS: [u64; 18] = [
+    1, 2, 5, 10, 15, 30, 60, 120, 300, 600, 900, 1800, 3600, 7200, 10800, 21600, 43200, 86400,
+];
+const SECONDS_PER_DAY: u64 = 86400;
+
+/// How much history a chart shows, and how often it gets a new point.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TimeScale {
+    pub window: Duration,
+    pub interval: Duration,
+}
+
+impl Default for TimeScale {
+    /// The last minute's 60 points, a second apart.
+    fn default() -> Self {
+        Self {
+            window: Duration::from_secs(59),
+            interval: Duration::from_secs(1),
+        }
+    }
+}
+
+impl TimeScale {
+    /// How many points fit in the window, counting one at each end.
+    pub fn point_count(&self) -> usize {
+        (self.window.as_nanos() / self.interval.as_nanos().max(1)) as usize + 1
+    }
+
+    /// The time between vertical grid lines: the shortest clock-friendly
+    /// step that splits the window into at most `MAX_TIME_CELLS` cells,
+    /// and never less than a point apart.
+    pub fn grid_step(&self) -> Duration {
+        let min_step = (self.window / MAX_TIME_CELLS).max(self.interval);
+        TIME_STEPS_IN_SECONDS
+            .iter()
+            .map(|seconds| Duration::from_secs(*seconds))
+            .find(|step| *step >= min_step)
+            .unwrap_or_else(|| {
+                let days = min_step.as_secs().div_ceil(SECONDS_PER_DAY);
+                Duration::from_secs(days * SECONDS_PER_DAY)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ticks(min: f64, max: f64, step: f64) -> Ticks {
+        Ticks { min, max, step }
+    }
+
+    fn auto_expand(min: f64, max: f64) -> ScaleMode {
+        ScaleMode::AutoExpand { min, max }
+    }
+
+    #[test]
+    fn nice_steps_across_decades() {
+        let cases = [
+            (0.0007, 0.001),
+            (0.013, 0.02),
+            (0.3, 0.5),
+            (1.0, 1.0),
+            (1.2, 2.0),
+            (2.0, 2.0),
+            (4.9, 5.0),
+            (7.0, 10.0),
+            (10.0, 10.0),
+            (25.0, 50.0),
+            (120_000.0, 200_000.0),
+            (3.1e12, 5e12),
+        ];
+        for (raw_step, step) in cases {
+            let nice = nice_step(raw_step);
+            assert!(
+                (nice - step).abs() <= step * 1e-9,
+                "{} -> {}",
+                raw_step,
+                nice
+            );
+        }
+        assert_eq!(nice_step(0.0), 1.0);
+        assert_eq!(nice_step(-5.0), 1.0);
+        assert_eq!(nice_step(f64::NAN), 1.0);
+        assert_eq!(nice_step(f64::INFINITY), 1.0);
+    }
+
+    #[test]
+    fn rounds_ranges_out() {
+        assert_eq!(nice_ticks(0.0, 100.0, 5), ticks(0.0, 100.0, 20.0));
+        assert_eq!(nice_ticks(0.0, 87.0, 5), ticks(0.0, 100.0, 20.0));
+        assert_eq!(nice_ticks(3.0, 97.0, 4), ticks(0.0, 100.0, 50.0));
+        assert_eq!(nice_ticks(0.0, 1234.0, 5), ticks(0.0, 1500.0, 500.0));
+        assert_eq!(nice_ticks(-35.0, 12.0, 5), ticks(-40.0, 20.0, 20.0));
+        assert_eq!(nice_ticks(-7.0, -2.0, 5), ticks(-7.0, -2.0, 1.0));
+    }
+
+    #[test]
+    fn widens_empty_ranges() {
+        assert_eq!(nice_ticks(0.0, 0.0, 5), ticks(0.0, 1.0, 0.2));
+        assert_eq!(nice_ticks(42.0, 42.0, 5), ticks(42.0, 43.0, 0.2));
+        // Backwards ranges start at `min` too.
+        assert_eq!(nice_ticks(10.0, 5.0, 5), ticks(10.0, 11.0, 0.2));
+        assert_eq!(nice_ticks(0.0, 1.0, 0), ticks(0.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn falls_back_for_infinite_ranges() {
+        let fallback = ticks(0.0, 1.0, 0.2);
+        assert_eq!(nice_ticks(0.0, f64::INFINITY, 5), fallback);
+        assert_eq!(nice_ticks(f64::NEG_INFINITY, 0.0, 5), fallback);
+        assert_eq!(nice_ticks(f64::NAN, 10.0, 5), fallback);
+        // Finite ends can still be too far apart to subtract, or to round
+        // out.
+        assert_eq!(nice_ticks(-f64::MAX, f64::MAX, 5), fallback);
+        assert_eq!(nice_ticks(0.0, f64::MAX, 1), ticks(0.0, 1.0, 1.0));
+        let axis = YAxis::new(
+            ScaleMode::Fixed {
+                min: -f64::MAX,
+                max: f64::MAX,
+            },
+            5,
+        );
+        assert_eq!(axis.ticks(), &fallback);
+    }
+
+    #[test]
+    fn lists_tick_values() {
+        assert_eq!(
+            ticks(0.0, 100.0, 25.0).values(),
+            [0.0, 25.0, 50.0, 75.0, 100.0]
+        );
+        assert_eq!(ticks(-40.0, 20.0, 20.0).values(), [-40.0, -20.0, 0.0, 20.0]);
+        // Fixed ranges don't have to start or end on a tick.
+        assert_eq!(ticks(5.0, 55.0, 20.0).values(), [20.0, 40.0]);
+        let values = ticks(0.0, 1.0, 0.1).values();
+        assert_eq!(values.len(), 11);
+        assert_eq!(values[3], 0.30000000000000004);
+    }
+
+    #[test]
+    fn fixed_ranges_stay_put() {
+        let mut axis = YAxis::new(
+            ScaleMode::Fixed {
+                min: 5.0,
+                max: 55.0,
+            },
+            5,
+        );
+        assert_eq!(axis.ticks(), &ticks(5.0, 55.0, 10.0));
+        axis.update(Some((-100.0, 1000.0)));
+        assert_eq!(axis.ticks(), &ticks(5.0, 55.0, 10.0));
+        // Values outside the range are clipped to its edges.
+        assert_eq!(axis.fraction(30.0), 0.5);
+        assert_eq!(axis.fraction(-100.0), 0.0);
+        assert_eq!(axis.fraction(1000.0), 1.0);
+    }
+
+    #[test]
+    fn auto_expand_grows_but_never_shrinks() {
+        let mut axis = YAxis::new(auto_expand(0.0, 100.0), 5);
+        axis.update(None);
+        axis.update(Some((10.0, 90.0)));
+        assert_eq!(axis.ticks(), &ticks(0.0, 100.0, 20.0));
+
+        axis.update(Some((0.0, 130.0)));
+        assert_eq!(axis.ticks(), &ticks(0.0, 150.0, 50.0));
+        axis.update(Some((0.0, 20.0)));
+        assert_eq!(axis.ticks(), &ticks(0.0, 150.0, 50.0));
+        axis.update(Some((-10.0, 20.0)));
+        assert_eq!(axis.ticks(), &ticks(-50.0, 150.0, 50.0));
+
+        axis.reset();
+        assert_eq!(axis.ticks(), &ticks(0.0, 100.0, 20.0));
+    }
+
+    #[test]
+    fn fit_follows_the_data() {
+        let mut axis = YAxis::new(ScaleMode::Fit, 5);
+        assert_eq!(axis.ticks(), &ticks(0.0, 1.0, 0.2));
+        axis.update(Some((2000.0, 8500.0)));
+        assert_eq!(axis.ticks(), &ticks(0.0, 10000.0, 2000.0));
+        axis.update(Some((3.0, 4.0)));
+        assert_eq!(axis.ticks(), &ticks(0.0, 4.0, 1.0));
+        axis.update(Some((-8.0, -2.0)));
+        assert_eq!(axis.ticks(), &ticks(-8.0, 0.0, 2.0));
+        // Nothing on screen keeps the last range.
+        axis.update(None);
+        assert_eq!(axis.ticks(), &ticks(-8.0, 0.0, 2.0));
+        axis.update(Some((0.0, 0.0)));
+        assert_eq!(axis.ticks(), &ticks(0.0, 1.0, 0.2));
+    }
+
+    #[test]
+    fn picks_a_mode_per_unit() {
+        assert_eq!(ScaleMode::for_unit(Unit::Percent), auto_expand(0.0, 100.0));
+        assert_eq!(ScaleMode::for_unit(Unit::Count), ScaleMode::Fit);
+    }
+
+    #[test]
+    fn clamps_fractions() {
+        let axis = YAxis::new(auto_expand(-50.0, 150.0), 4);
+        assert_eq!(axis.fraction(-50.0), 0.0);
+        assert_eq!(axis.fraction(0.0), 0.25);
+        assert_eq!(axis.fraction(150.0), 1.0);
+        assert_eq!(axis.fraction(-1e9), 0.0);
+        assert_eq!(axis.fraction(f64::INFINITY), 1.0);
+    }
+
+    fn time_scale(window: u64, interval_millis: u64) -> TimeScale {
+        TimeScale {
+            window: Duration::from_secs(window),
+            interval: Duration::from_millis(interval_millis),
+        }
+    }
+
+    #[test]
+    fn counts_points() {
+        assert_eq!(TimeScale::default().point_count(), 60);
+        assert_eq!(time_scale(10, 250).point_count(), 41);
+        // A window that isn't a multiple of the interval drops the
+        // partial point.
+        assert_eq!(time_scale(10, 3000).point_count(), 4);
+    }
+
+    #[test]
+    fn picks_clock_friendly_grid_steps() {
+        let step = |window, interval_millis| time_scale(window, interval_millis).grid_step();
+        assert_eq!(step(60, 1000), Duration::from_secs(10));
+        assert_eq!(step(5, 100), Duration::from_secs(1));
+        assert_eq!(step(100, 1000), Duration::from_secs(30));
+        assert_eq!(step(3600, 1000), Duration::from_secs(600));
+        assert_eq!(step(86400, 1000), Duration::from_secs(6 * 3600));
+        // Never closer than a point apart.
+        assert_eq!(step(60, 40_000), Duration::from_secs(60));
+        // Past a day, whole days.
+        assert_eq!(step(7 * 86400, 1000), Duration::from_secs(2 * 86400));
+    }
+}
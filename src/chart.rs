@@ -1,5 +1,3 @@
-use std::collections::VecDeque;
-
 use windows::{
     core::Result,
     Foundation::Numerics::Matrix3x2,
@@ -10,39 +8,40 @@ use windows::{
     Win32::{
         Graphics::Direct2D::{
             Common::{
-                D2D1_COLOR_F, D2D1_FIGURE_BEGIN_FILLED, D2D1_FIGURE_END_CLOSED, D2D_POINT_2F,
-                D2D_RECT_F,
+                D2D1_COLOR_F, D2D1_FIGURE_BEGIN_FILLED, D2D1_FIGURE_BEGIN_HOLLOW,
+                D2D1_FIGURE_END_CLOSED, D2D1_FIGURE_END_OPEN, D2D_POINT_2F, D2D_RECT_F,
             },
-            ID2D1DeviceContext, ID2D1SolidColorBrush,
+            ID2D1DeviceContext, ID2D1PathGeometry, ID2D1SolidColorBrush,
         },
         System::WindowsProgramming::MulDiv,
     },
     UI::Composition::CompositionDrawingSurface,
 };
 
-use crate::{renderer::Renderer, windows_utils::composition::CompositionDrawingSurfaceInterop};
-
-const MAX_POINTS: usize = 60;
-const CELL_WIDTH_IN_SECONDS: usize = 10;
+use crate::{
+    chart_model::{
+        ChartModel, ChartOptions, Color, DrawCommand, Point, DEFAULT_HEIGHT, DEFAULT_WIDTH,
+    },
+    metric::Reading,
+    renderer::Renderer,
+    windows_utils::composition::CompositionDrawingSurfaceInterop,
+};
 
 pub struct ChartSurface {
     surface: CompositionDrawingSurface,
-    points: VecDeque<f32>,
+    model: ChartModel,
     width: i32,
     height: i32,
     unscaled_width: i32,
     unscaled_height: i32,
     dpi: i32,
-    outline_brush: ID2D1SolidColorBrush,
-    fill_brush: ID2D1SolidColorBrush,
-    grid_brush: ID2D1SolidColorBrush,
-    grid_offset: usize,
+    brush: ID2D1SolidColorBrush,
 }
 
 impl ChartSurface {
     pub fn new(renderer: &Renderer, dpi: u32) -> Result<Self> {
-        let unscaled_width = 250;
-        let unscaled_height = 226;
+        let unscaled_width = DEFAULT_WIDTH;
+        let unscaled_height = DEFAULT_HEIGHT;
         let width = unsafe { MulDiv(unscaled_width, dpi as i32, 96) };
         let height = unsafe { MulDiv(unscaled_height, dpi as i32, 96) };
         let surface = renderer.comp_graphics.CreateDrawingSurface2(
@@ -54,22 +53,15 @@ impl ChartSurface {
             DirectXAlphaMode::Premultiplied,
         )?;
 
-        let mut color = D2D1_COLOR_F {
-            a: 1.0,
-            r: 0.0667,
-            g: 0.4902,
-            b: 0.7333,
-        };
-        let outline_brush = unsafe { renderer.d2d_context.CreateSolidColorBrush(&color, None)? };
-        color.a = 0.1;
-        let fill_brush = unsafe { renderer.d2d_context.CreateSolidColorBrush(&color, None)? };
-        let grid_brush = unsafe {
+        // The display list carries its own colors, so we share a single
+        // brush and recolor it for each command.
+        let brush = unsafe {
             renderer.d2d_context.CreateSolidColorBrush(
                 &D2D1_COLOR_F {
                     a: 1.0,
-                    r: 0.8510,
-                    g: 0.9176,
-                    b: 0.9569,
+                    r: 0.0,
+                    g: 0.0,
+                    b: 0.0,
                 },
                 None,
             )?
@@ -77,16 +69,13 @@ impl ChartSurface {
 
         Ok(Self {
             surface,
-            points: VecDeque::with_capacity(MAX_POINTS),
+            model: ChartModel::new(width as f32, height as f32),
             width,
             height,
             unscaled_width,
             unscaled_height,
             dpi: dpi as i32,
-            outline_brush,
-            fill_brush,
-            grid_brush,
-            grid_offset: 0,
+            brush,
         })
     }
 
@@ -95,9 +84,7 @@ impl ChartSurface {
     }
 
     pub fn redraw(&self, renderer: &Renderer) -> Result<()> {
-        let path_geometry = unsafe { renderer.d2d_factory.CreatePathGeometry()? };
-        let pixels_per_second = self.pixels_per_second();
-        let pixels_per_percent = self.pixels_per_percent();
+        let display_list = self.model.display_list();
 
         self.surface
             .draw::<ID2D1DeviceContext, _>(None, |context, offset| -> Result<()> {
@@ -110,100 +97,76 @@ impl ChartSurface {
                         b: 0.0,
                     }));
 
-                    // Build geometry
-                    {
-                        let sink = path_geometry.Open()?;
-                        let start_x = (MAX_POINTS - self.points.len()) as f32 * pixels_per_second;
-                        sink.BeginFigure(
-                            D2D_POINT_2F {
-                                x: start_x,
-                                y: self.height as f32,
-                            },
-                            D2D1_FIGURE_BEGIN_FILLED,
-                        );
-
-                        let mut last_x = 0.0;
-                        for (i, point) in self.points.iter().enumerate() {
-                            let x =
-                                (start_x + (i as f32 * pixels_per_second)).min(self.width as f32);
-                            sink.AddLine(D2D_POINT_2F {
-                                x: x,
-                                y: self.height as f32 - (point * pixels_per_percent),
-                            });
-                            last_x = x;
+                    for command in &display_list.commands {
+                        match command {
+                            DrawCommand::GridLine {
+                                from,
+                                to,
+                                color,
+                                width,
+                            } => {
+                                self.brush.SetColor(&to_d2d_color(color));
+                                context.DrawLine(
+                                    to_d2d_point(from),
+                                    to_d2d_point(to),
+                                    &self.brush,
+                                    *width,
+                                    None,
+                                );
+                            }
+                            DrawCommand::Polyline {
+                                points,
+                                closed,
+                                color,
+                                width,
+                            } => {
+                                let geometry = create_path(renderer, points, false, *closed)?;
+                                self.brush.SetColor(&to_d2d_color(color));
+                                context.DrawGeometry(&geometry, &self.brush, *width, None);
+                            }
+                            DrawCommand::Fill { points, color } => {
+                                let geometry = create_path(renderer, points, true, true)?;
+                                self.brush.SetColor(&to_d2d_color(color));
+                                context.FillGeometry(&geometry, &self.brush, None);
+                            }
+                            DrawCommand::Rect { rect, color, width } => {
+                                self.brush.SetColor(&to_d2d_color(color));
+                                context.DrawRectangle(
+                                    &D2D_RECT_F {
+                                        left: rect.left,
+                                        top: rect.top,
+                                        right: rect.right,
+                                        bottom: rect.bottom,
+                                    },
+                                    &self.brush,
+                                    *width,
+                                    None,
+                                );
+                            }
+                            // The labels live in their own TextBlock visuals.
+                            DrawCommand::Text { .. } => {}
                         }
-
-                        sink.AddLine(D2D_POINT_2F {
-                            x: last_x,
-                            y: self.height as f32,
-                        });
-
-                        sink.EndFigure(D2D1_FIGURE_END_CLOSED);
-                        sink.Close()?;
-                    }
-
-                    // Draw graph lines
-                    // Horizontal lines
-                    let cell_height = self.height as f32 / 10.0;
-                    for i in 0..9 {
-                        let y = (i + 1) as f32 * cell_height;
-                        context.DrawLine(
-                            D2D_POINT_2F { x: 0.0, y: y },
-                            D2D_POINT_2F {
-                                x: self.width as f32,
-                                y: y,
-                            },
-                            &self.grid_brush,
-                            1.0,
-                            None,
-                        );
                     }
-                    // Vertical lines
-                    let cell_width = CELL_WIDTH_IN_SECONDS as f32 * pixels_per_second;
-                    for i in 0..10 {
-                        let offset = self.grid_offset as f32 * pixels_per_second;
-                        let x = (i + 1) as f32 * cell_width;
-                        context.DrawLine(
-                            D2D_POINT_2F {
-                                x: x - offset,
-                                y: 0.0,
-                            },
-                            D2D_POINT_2F {
-                                x: x - offset,
-                                y: self.height as f32,
-                            },
-                            &self.grid_brush,
-                            1.0,
-                            None,
-                        );
-                    }
-
-                    context.DrawGeometry(&path_geometry, &self.outline_brush, 1.0, None);
-                    context.FillGeometry(&path_geometry, &self.fill_brush, None);
-
-                    context.DrawRectangle(
-                        &D2D_RECT_F {
-                            left: 0.0,
-                            top: 0.0,
-                            right: self.width as f32,
-                            bottom: self.height as f32,
-                        },
-                        &self.outline_brush,
-                        2.0,
-                        None,
-                    );
                 }
                 Ok(())
             })?;
         Ok(())
     }
 
-    pub fn add_point(&mut self, point: f32) {
-        if self.points.len() == MAX_POINTS {
-            self.points.pop_front();
-        }
-        self.points.push_back(point);
-        self.grid_offset = (self.grid_offset + 1) % CELL_WIDTH_IN_SECONDS;
+    pub fn add_readings(&mut self, readings: &[Reading]) {
+        self.model.add_readings(readings);
+    }
+
+    pub fn set_options(&mut self, options: &ChartOptions) {
+        self.model.set_options(options);
+    }
+
+    pub fn model(&self) -> &ChartModel {
+        &self.model
+    }
+
+    pub fn model_mut(&mut self) -> &mut ChartModel {
+        &mut self.model
     }
 
     pub fn size(&self) -> SizeInt32 {
@@ -217,18 +180,58 @@ impl ChartSurface {
         self.dpi = dpi as i32;
         self.width = unsafe { MulDiv(self.unscaled_width, self.dpi, 96) };
         self.height = unsafe { MulDiv(self.unscaled_height, self.dpi, 96) };
+        self.model.set_size(self.width as f32, self.height as f32);
 
         self.surface.Resize(self.size())?;
         self.redraw(renderer)?;
 
         Ok(())
     }
+}
+
+fn create_path(
+    renderer: &Renderer,
+    points: &[Point],
+    filled: bool,
+    closed: bool,
+) -> Result<ID2D1PathGeometry> {
+    let path_geometry = unsafe { renderer.d2d_factory.CreatePathGeometry()? };
+    if let Some((first, rest)) = points.split_first() {
+        unsafe {
+            let sink = path_geometry.Open()?;
+            let begin = if filled {
+                D2D1_FIGURE_BEGIN_FILLED
+            } else {
+                D2D1_FIGURE_BEGIN_HOLLOW
+            };
+            sink.BeginFigure(to_d2d_point(first), begin);
+            for point in rest {
+                sink.AddLine(to_d2d_point(point));
+            }
+            let end = if closed {
+                D2D1_FIGURE_END_CLOSED
+            } else {
+                D2D1_FIGURE_END_OPEN
+            };
+            sink.EndFigure(end);
+            sink.Close()?;
+        }
+    }
+    Ok(path_geometry)
+}
 
-    fn pixels_per_second(&self) -> f32 {
-        self.width as f32 / (MAX_POINTS - 1) as f32
+fn to_d2d_point(point: &Point) -> D2D_POINT_2F {
+    D2D_POINT_2F {
+        x: point.x,
+        y: point.y,
     }
+}
 
-    fn pixels_per_percent(&self) -> f32 {
-        self.height as f32 / 100.0
+fn to_d2d_color(color: &Color) -> D2D1_COLOR_F {
+    D2D1_COLOR_F {
+        r: color.r,
+        g: color.g,
+        b: color.b,
+        a: color.a,
     }
 }
@@ -0,0 +1,166 @@
+use windows::{
+    core::Result,
+    Foundation::Numerics::Vector3,
+    UI::{Color, Composition::ContainerVisual},
+};
+
+use crate::{
+    renderer::Renderer,
+    scene::{layout_axis_labels, AxisLabel, AxisLabels},
+    text_block::TextBlock,
+};
+
+/// The value and time labels around the chart. The root sits at the top
+/// left of the chart, and the labels hang off its left and bottom edges.
+pub struct ChartLabels {
+    root: ContainerVisual,
+    values: Vec<TextBlock>,
+    times: Vec<TextBlock>,
+    dpi: u32,
+    labels: AxisLabels,
+    chart_width: f32,
+    chart_height: f32,
+}
+
+impl ChartLabels {
+    pub fn new(renderer: &Renderer, dpi: u32, chart_width: f32, chart_height: f32) -> Result<Self> {
+        let root = renderer.compositor.CreateContainerVisual()?;
+        Ok(Self {
+            root,
+            values: Vec::new(),
+            times: Vec::new(),
+            dpi,
+            labels: AxisLabels::default(),
+            chart_width,
+            chart_height,
+        })
+    }
+
+    pub fn root(&self) -> &ContainerVisual {
+        &self.root
+    }
+
+    pub fn update(&mut self, renderer: &Renderer, labels: AxisLabels) -> Result<()> {
+        // Most of the time nothing moved since the last tick.
+        if labels == self.labels {
+            return Ok(());
+        }
+        self.set_labels(renderer, labels)?;
+        self.layout()
+    }
+
+    /// `labels` have to be recomputed for the new chart size.
+    pub fn set_dpi(
+        &mut self,
+        renderer: &Renderer,
+        dpi: u32,
+        chart_width: f32,
+        chart_height: f32,
+        labels: AxisLabels,
+    ) -> Result<()> {
+        self.dpi = dpi;
+        self.chart_width = chart_width;
+        self.chart_height = chart_height;
+        for text in self.values.iter_mut().chain(&mut self.times) {
+            text.set_dpi(renderer, dpi)?;
+        }
+        self.set_labels(renderer, labels)?;
+        self.layout()
+    }
+
+    fn set_labels(&mut self, renderer: &Renderer, labels: AxisLabels) -> Result<()> {
+        sync_text_blocks(
+            &self.root,
+            renderer,
+            self.dpi,
+            &mut self.values,
+            &labels.values,
+        )?;
+        sync_text_blocks(
+            &self.root,
+            renderer,
+            self.dpi,
+            &mut self.times,
+            &labels.times,
+        )?;
+        self.labels = labels;
+        Ok(())
+    }
+
+    fn layout(&self) -> Result<()> {
+        let mut line_height: f32 = 0.0;
+        let mut measure = |texts: &[TextBlock]| -> Result<Vec<f32>> {
+            texts
+                .iter()
+                .map(|text| {
+                    let size = text.root().Size()?;
+                    line_height = line_height.max(size.Y);
+                    Ok(size.X)
+                })
+                .collect()
+        };
+        let value_widths = measure(&self.values)?;
+        let time_widths = measure(&self.times)?;
+
+        let scale = self.dpi as f32 / 96.0;
+        let layout = layout_axis_labels(
+            &self.labels,
+            &value_widths,
+            &time_widths,
+            self.chart_width,
+            self.chart_height,
+            line_height,
+            scale,
+        );
+        let texts = self.values.iter().zip(layout.values);
+        let texts = texts.chain(self.times.iter().zip(layout.times));
+        for (text, origin) in texts {
+            let root = text.root();
+            match origin {
+                Some(origin) => {
+                    root.SetOffset(Vector3::new(origin.x, origin.y, 0.0))?;
+                    root.SetIsVisible(true)?;
+                }
+                None => root.SetIsVisible(false)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Makes `texts` show `labels`, adding and removing text blocks as needed.
+fn sync_text_blocks(
+    root: &ContainerVisual,
+    renderer: &Renderer,
+    dpi: u32,
+    texts: &mut Vec<TextBlock>,
+    labels: &[AxisLabel],
+) -> Result<()> {
+    let children = root.Children()?;
+    while texts.len() > labels.len() {
+        let text = texts.pop().unwrap();
+        children.Remove(text.root())?;
+    }
+    for (i, label) in labels.iter().enumerate() {
+        if let Some(text) = texts.get_mut(i) {
+            if text.text() != label.text {
+                text.set_text(renderer, label.text.clone())?;
+            }
+            continue;
+        }
+        let text = TextBlock::new(
+            renderer,
+            label.text.clone(),
+            Color {
+                A: 255,
+                R: 112,
+                G: 112,
+                B: 112,
+            },
+            dpi,
+        )?;
+        children.InsertAtTop(text.root())?;
+        texts.push(text);
+    }
+    Ok(())
+}
@@ -0,0 +1,851 @@
+use std::{collections::VecDeque, time::Duration};
+
+use crate::{
+    axis::{ScaleMode, TimeScale, YAxis},
+    metric::Reading,
+};
+
+pub const DEFAULT_WIDTH: i32 = 250;
+pub const DEFAULT_HEIGHT: i32 = 226;
+/// Height of a line of text relative to its font size. Matches the line
+/// spacing DirectWrite uses for Segoe UI.
+pub const LINE_HEIGHT: f32 = 1.33;
+
+// The most rows the horizontal grid lines split the chart into.
+const MAX_Y_TICKS: usize = 10;
+
+/// Colors given to series in the order they first show up. The first one
+/// is the chart's outline color.
+const SERIES_COLORS: [Color; 8] = [
+    Color::new(0.0667, 0.4902, 0.7333, 1.0),
+    Color::new(0.8471, 0.3216, 0.0745, 1.0),
+    Color::new(0.2980, 0.6000, 0.1569, 1.0),
+    Color::new(0.5569, 0.2667, 0.6784, 1.0),
+    Color::new(0.7529, 0.2235, 0.1686, 1.0),
+    Color::new(0.0863, 0.6275, 0.5216, 1.0),
+    Color::new(0.4980, 0.5490, 0.5529, 1.0),
+    Color::new(0.8275, 0.6275, 0.0000, 1.0),
+];
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Color {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+    pub a: f32,
+}
+
+impl Color {
+    pub const fn new(r: f32, g: f32, b: f32, a: f32) -> Self {
+        Self { r, g, b, a }
+    }
+
+    pub const fn with_alpha(self, a: f32) -> Self {
+        Self { a, ..self }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Point {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl Point {
+    pub const fn new(x: f32, y: f32) -> Self {
+        Self { x, y }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Rect {
+    pub left: f32,
+    pub top: f32,
+    pub right: f32,
+    pub bottom: f32,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TextAlign {
+    Left,
+    Right,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DrawCommand {
+    GridLine {
+        from: Point,
+        to: Point,
+        color: Color,
+        width: f32,
+    },
+    Polyline {
+        points: Vec<Point>,
+        closed: bool,
+        color: Color,
+        width: f32,
+    },
+    Fill {
+        points: Vec<Point>,
+        color: Color,
+    },
+    Rect {
+        rect: Rect,
+        color: Color,
+        width: f32,
+    },
+    /// A single line of text. `origin` is the top of the line box, on the
+    /// left or right edge of the text depending on `align`.
+    Text {
+        origin: Point,
+        text: String,
+        color: Color,
+        size: f32,
+        align: TextAlign,
+    },
+}
+
+impl DrawCommand {
+    pub fn offset(&mut self, dx: f32, dy: f32) {
+        let offset_point = |point: &mut Point| {
+            point.x += dx;
+            point.y += dy;
+        };
+        match self {
+            DrawCommand::GridLine { from, to, .. } => {
+                offset_point(from);
+                offset_point(to);
+            }
+            DrawCommand::Polyline { points, .. } | DrawCommand::Fill { points, .. } => {
+                points.iter_mut().for_each(offset_point);
+            }
+            DrawCommand::Rect { rect, .. } => {
+                rect.left += dx;
+                rect.top += dy;
+                rect.right += dx;
+                rect.bottom += dy;
+            }
+            DrawCommand::Text { origin, .. } => offset_point(origin),
+        }
+    }
+}
+
+/// Renderer-neutral description of a chart. Commands are listed in the
+/// order they should be drawn.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DisplayList {
+    pub width: f32,
+    pub height: f32,
+    pub commands: Vec<DrawCommand>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ChartStyle {
+    pub outline: Color,
+    /// The default opacity of the area under each series.
+    pub fill_opacity: f32,
+    pub grid: Color,
+    /// The default width of each series' line.
+    pub line_width: f32,
+    pub grid_width: f32,
+    pub border_width: f32,
+}
+
+impl Default for ChartStyle {
+    fn default() -> Self {
+        Self {
+            outline: SERIES_COLORS[0],
+            fill_opacity: 0.1,
+            grid: Color::new(0.8510, 0.9176, 0.9569, 1.0),
+            line_width: 1.0,
+            grid_width: 1.0,
+            border_width: 2.0,
+        }
+    }
+}
+
+/// How a single series is drawn.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SeriesStyle {
+    pub color: Color,
+    pub line_width: f32,
+    /// Opacity of the area under the line, applied to `color`. Zero leaves
+    /// the area unfilled.
+    pub fill_opacity: f32,
+}
+
+/// Changes to the default style of a series. Fields left as `None` keep
+/// their default.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct SeriesStyleOverride {
+    pub color: Option<Color>,
+    pub line_width: Option<f32>,
+    pub fill_opacity: Option<f32>,
+}
+
+impl SeriesStyleOverride {
+    fn apply(&self, style: SeriesStyle) -> SeriesStyle {
+        SeriesStyle {
+            color: self.color.unwrap_or(style.color),
+            line_width: self.line_width.unwrap_or(style.line_width),
+            fill_opacity: self.fill_opacity.unwrap_or(style.fill_opacity),
+        }
+    }
+}
+
+/// How series are laid out against each other.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum SeriesLayout {
+    /// Every series is drawn from zero, on top of each other.
+    #[default]
+    Overlaid,
+    /// Each series sits on the sum of the series below it, so the top
+    /// edge shows the total. The series named in `order` go first, from
+    /// the bottom up, followed by the rest in the order they showed up.
+    Stacked { order: Vec<String> },
+}
+
+/// Chart settings that every frontend applies the same way.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChartOptions {
+    pub scale_mode: ScaleMode,
+    pub time_scale: TimeScale,
+    pub layout: SeriesLayout,
+    /// Style overrides by series name.
+    pub series_styles: Vec<(String, SeriesStyleOverride)>,
+}
+
+/// The history of one named value. Ticks without a value are `None` and
+/// drawn as gaps.
+pub struct Series {
+    name: String,
+    style: SeriesStyle,
+    points: VecDeque<Option<f32>>,
+}
+
+impl Series {
+    fn new(name: String, style: SeriesStyle, point_count: usize) -> Self {
+        Self {
+            name,
+            style,
+            points: VecDeque::with_capacity(point_count),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn color(&self) -> Color {
+        self.style.color
+    }
+
+    pub fn points(&self) -> &VecDeque<Option<f32>> {
+        &self.points
+    }
+
+    fn push(&mut self, point: Option<f32>, point_count: usize) {
+        if self.points.len() == point_count {
+            self.points.pop_front();
+        }
+        self.points.push_back(point);
+    }
+
+    /// Drops the oldest points so at most `point_count` are left.
+    fn truncate(&mut self, point_count: usize) {
+        let excess = self.points.len().saturating_sub(point_count);
+        self.points.drain(..excess);
+    }
+}
+
+/// The area a series covers, from a base up to its value at each of its
+/// points. Overlaid series sit on zero, stacked ones on the series below.
+pub struct Band<'a> {
+    pub series: &'a Series,
+    /// `(base, top)` of every point, or `None` where the series has a gap.
+    pub points: Vec<Option<(f32, f32)>>,
+}
+
+pub struct ChartModel {
+    series: Vec<Series>,
+    width: f32,
+    height: f32,
+    time_scale: TimeScale,
+    /// How long ago the newest vertical grid line scrolled in from the
+    /// right edge.
+    grid_offset: Duration,
+    style: ChartStyle,
+    y_axis: YAxis,
+    layout: SeriesLayout,
+    style_overrides: Vec<(String, SeriesStyleOverride)>,
+}
+
+impl ChartModel {
+    pub fn new(width: f32, height: f32) -> Self {
+        Self {
+            series: Vec::new(),
+            width,
+            height,
+            time_scale: TimeScale::default(),
+            grid_offset: Duration::ZERO,
+            style: ChartStyle::default(),
+            y_axis: YAxis::new(
+                ScaleMode::Fixed {
+                    min: 0.0,
+                    max: 100.0,
+                },
+                MAX_Y_TICKS,
+            ),
+            layout: SeriesLayout::default(),
+            style_overrides: Vec::new(),
+        }
+    }
+
+    /// Adds one tick worth of readings.
+    pub fn add_readings(&mut self, readings: &[Reading]) {
+        self.add_values(readings.iter().map(|reading| {
+            let value = reading.sample.value.map(|value| value as f32);
+            (reading.series.as_str(), value)
+        }));
+    }
+
+    /// Adds one tick worth of values, by series name. Series seen for the
+    /// first time are added, and series missing from this tick get a gap
+    /// so every series stays aligned with the time axis.
+    pub fn add_values<'a, I: IntoIterator<Item = (&'a str, Option<f32>)>>(&mut self, values: I) {
+        let mut updated = vec![false; self.series.len()];
+        for (name, value) in values {
+            let index = match self.series.iter().position(|series| series.name == name) {
+                Some(index) => index,
+                None => {
+                    let style = self.default_series_style(name, self.series.len());
+                    let point_count = self.point_count();
+                    self.series
+                        .push(Series::new(name.to_owned(), style, point_count));
+                    updated.push(false);
+                    self.series.len() - 1
+                }
+            };
+            let point_count = self.point_count();
+            let series = &mut self.series[index];
+            match series.points.back_mut() {
+                // The same series twice in one tick; the last one wins.
+                Some(point) if updated[index] => *point = value,
+                _ => series.push(value, point_count),
+            }
+            updated[index] = true;
+        }
+        let point_count = self.point_count();
+        for (series, updated) in self.series.iter_mut().zip(updated) {
+            if !updated {
+                series.push(None, point_count);
+            }
+        }
+        let grid_offset = self.grid_offset + self.time_scale.interval;
+        self.grid_offset = Duration::from_nanos(
+            (grid_offset.as_nanos() % self.time_scale.grid_step().as_nanos()) as u64,
+        );
+        self.y_axis.update(self.value_range());
+    }
+
+    /// Drops every point, e.g. before charting a different stretch of a
+    /// recording. Series keep their colors.
+    pub fn clear(&mut self) {
+        for series in &mut self.series {
+            series.points.clear();
+        }
+        self.grid_offset = Duration::ZERO;
+        self.y_axis.reset();
+    }
+
+    /// Chooses how the value range follows the data. Charts start out at
+    /// a fixed 0–100.
+    pub fn set_scale_mode(&mut self, mode: ScaleMode) {
+        self.y_axis = YAxis::new(mode, MAX_Y_TICKS);
+        self.y_axis.update(self.value_range());
+    }
+
+    /// Changes how much history is shown and how often points come in.
+    /// Points that no longer fit are dropped.
+    pub fn set_time_scale(&mut self, time_scale: TimeScale) {
+        self.time_scale = time_scale;
+        self.grid_offset = Duration::ZERO;
+        let point_count = self.point_count();
+        for series in &mut self.series {
+            series.truncate(point_count);
+        }
+        self.y_axis.update(self.value_range());
+    }
+
+    /// How many points of each series fit on screen.
+    pub fn point_count(&self) -> usize {
+        self.time_scale.point_count()
+    }
+
+    pub fn set_layout(&mut self, layout: SeriesLayout) {
+        self.layout = layout;
+        // Stacking changes the largest value on screen.
+        self.y_axis.update(self.value_range());
+    }
+
+    /// Overrides the style of the series called `name`, whether or not
+    /// it has shown up yet.
+    pub fn set_series_style(&mut self, name: &str, style: SeriesStyleOverride) {
+        match self
+            .style_overrides
+            .iter_mut()
+            .find(|(other, _)| other == name)
+        {
+            Some((_, existing)) => *existing = style,
+            None => self.style_overrides.push((name.to_owned(), style)),
+        }
+        if let Some(index) = self.series.iter().position(|series| series.name == name) {
+            self.series[index].style = self.default_series_style(name, index);
+        }
+    }
+
+    pub fn set_options(&mut self, options: &ChartOptions) {
+        self.set_time_scale(options.time_scale);
+        self.set_layout(options.layout.clone());
+        self.set_scale_mode(options.scale_mode);
+        for (name, style) in &options.series_styles {
+            self.set_series_style(name, *style);
+        }
+    }
+
+    /// Series take the next color of the palette in the order they show
+    /// up, unless told otherwise.
+    fn default_series_style(&self, name: &str, index: usize) -> SeriesStyle {
+        let style = SeriesStyle {
+            color: SERIES_COLORS[index % SERIES_COLORS.len()],
+            line_width: self.style.line_width,
+            fill_opacity: self.style.fill_opacity,
+        };
+        match self.style_overrides.iter().find(|(other, _)| other == name) {
+            Some((_, style_override)) => style_override.apply(style),
+            None => style,
+        }
+    }
+
+    pub fn y_axis(&self) -> &YAxis {
+        &self.y_axis
+    }
+
+    /// The smallest and largest value on screen, or `None` if there are no
+    /// values yet. Stacked series count with their totals. Values too
+    /// large for an `f32` are left out, since no range fits them.
+    fn value_range(&self) -> Option<(f64, f64)> {
+        self.bands()
+            .iter()
+            .flat_map(|band| band.points.iter().flatten())
+            .filter(|(_, top)| top.is_finite())
+            .fold(None, |range, (_, top)| {
+                let value = *top as f64;
+                Some(match range {
+                    Some((min, max)) => (value.min(min), value.max(max)),
+                    None => (value, value),
+                })
+            })
+    }
+
+    pub fn series(&self) -> &[Series] {
+        &self.series
+    }
+
+    /// The area of every series, in the order they are stacked from the
+    /// bottom up. Gaps add nothing to the series above them.
+    pub fn bands(&self) -> Vec<Band<'_>> {
+        let stacked = matches!(self.layout, SeriesLayout::Stacked { .. });
+        // The top of the stack so far, by slot. Every series ends at the
+        // newest slot.
+        let point_count = self.point_count();
+        let mut totals = vec![0.0; point_count];
+        self.stack_order()
+            .into_iter()
+            .map(|index| {
+                let series = &self.series[index];
+                let start_slot = point_count - series.points.len();
+                let points = series
+                    .points
+                    .iter()
+                    .zip(&mut totals[start_slot..])
+                    .map(|(point, total)| {
+                        let value = (*point)?;
+                        if !stacked {
+                            return Some((0.0, value));
+                        }
+                        let base = *total;
+                        *total += value;
+                        Some((base, *total))
+                    })
+                    .collect();
+                Band { series, points }
+            })
+            .collect()
+    }
+
+    /// Indices of the series from the bottom of the stack up.
+    fn stack_order(&self) -> Vec<usize> {
+        let SeriesLayout::Stacked { order } = &self.layout else {
+            return (0..self.series.len()).collect();
+        };
+        let position = |series: &Series| order.iter().position(|name| *name == series.name);
+        let mut indices: Vec<usize> = (0..self.series.len()).collect();
+        // Series that aren't listed keep their order after the listed ones.
+        indices.sort_by_key(|index| position(&self.series[*index]).unwrap_or(order.len()));
+        indices
+    }
+
+    #[cfg_attr(not(windows), allow(dead_code))]
+    pub fn set_size(&mut self, width: f32, height: f32) {
+        self.width = width;
+        self.height = height;
+    }
+
+    pub fn display_list(&self) -> DisplayList {
+        let mut commands = Vec::new();
+        self.add_grid_lines(&mut commands);
+
+        for band in self.bands() {
+            let style = band.series.style;
+            for figure in self.figures(&band.points) {
+                commands.push(DrawCommand::Polyline {
+                    points: figure.clone(),
+                    closed: true,
+                    color: style.color,
+                    width: style.line_width,
+                });
+                if style.fill_opacity > 0.0 {
+                    commands.push(DrawCommand::Fill {
+                        points: figure,
+                        color: style.color.with_alpha(style.fill_opacity),
+                    });
+                }
+            }
+        }
+
+        commands.push(DrawCommand::Rect {
+            rect: Rect {
+                left: 0.0,
+                top: 0.0,
+                right: self.width,
+                bottom: self.height,
+            },
+            color: self.style.outline,
+            width: self.style.border_width,
+        });
+
+        DisplayList {
+            width: self.width,
+            height: self.height,
+            commands,
+        }
+    }
+
+    /// The closed outlines of a band, along its top from left to right
+    /// and back along its base. Gaps split the band into separate
+    /// figures.
+    fn figures(&self, points: &[Option<(f32, f32)>]) -> Vec<Vec<Point>> {
+        let start_slot = self.point_count() - points.len();
+
+        let mut figures = Vec::new();
+        let mut tops: Vec<Point> = Vec::new();
+        let mut bases: Vec<Point> = Vec::new();
+        for (i, point) in points.iter().enumerate() {
+            let Some((base, top)) = point else {
+                close_figure(&mut tops, &mut bases, &mut figures);
+                continue;
+            };
+            let x = self.slot_to_x(start_slot + i);
+            tops.push(Point::new(x, self.value_to_y(*top)));
+            bases.push(Point::new(x, self.value_to_y(*base)));
+        }
+        close_figure(&mut tops, &mut bases, &mut figures);
+        figures
+    }
+
+    fn add_grid_lines(&self, commands: &mut Vec<DrawCommand>) {
+        let color = self.style.grid;
+        let width = self.style.grid_width;
+
+        // Horizontal lines, one per tick. The border already covers the
+        // ticks on the edges.
+        for value in self.y_axis.ticks().values() {
+            let y = self.value_to_y(value as f32);
+            if y <= 0.0 || y >= self.height {
+                continue;
+            }
+            commands.push(DrawCommand::GridLine {
+                from: Point::new(0.0, y),
+                to: Point::new(self.width, y),
+                color,
+                width,
+            });
+        }
+
+        // Vertical lines, a grid step apart, scrolling left with the
+        // points. The border already covers the lines on the edges.
+        let step = self.time_scale.grid_step();
+        let mut age = self.grid_offset;
+        while age <= self.time_scale.window {
+            let x = self.age_to_x(age);
+            age += step;
+            if x <= 0.0 || x >= self.width {
+                continue;
+            }
+            commands.push(DrawCommand::GridLine {
+                from: Point::new(x, 0.0),
+                to: Point::new(x, self.height),
+                color,
+                width,
+            });
+        }
+    }
+
+    fn pixels_per_second(&self) -> f32 {
+        self.width / self.time_scale.window.as_secs_f32()
+    }
+
+    /// The x of a point `age` old, counted back from the right edge.
+    fn age_to_x(&self, age: Duration) -> f32 {
+        (self.width - age.as_secs_f32() * self.pixels_per_second()).max(0.0)
+    }
+
+    /// The x of a slot. The newest point is in the last slot, on the right
+    /// edge, and each slot before it is an interval older.
+    fn slot_to_x(&self, slot: usize) -> f32 {
+        let age = (self.point_count() - 1 - slot) as u32;
+        self.age_to_x(self.time_scale.interval * age)
+    }
+
+    /// Points along the time axis a grid step apart, as how long ago they
+    /// are and their x, newest first. Unlike the grid lines, these stay
+    /// put as the chart scrolls.
+    pub fn time_ticks(&self) -> Vec<(Duration, f32)> {
+        let step = self.time_scale.grid_step();
+        (0..)
+            .map(|i| step * i)
+            .take_while(|age| *age < self.time_scale.window)
+            .map(|age| (age, self.age_to_x(age)))
+            .collect()
+    }
+
+    /// The y of `value`, measured from the top of the chart.
+    pub fn value_to_y(&self, value: f32) -> f32 {
+        self.height - self.y_axis.fraction(value as f64) as f32 * self.height
+    }
+}
+
+fn close_figure(tops: &mut Vec<Point>, bases: &mut Vec<Point>, figures: &mut Vec<Vec<Point>>) {
+    if tops.is_empty() {
+        return;
+    }
+    let mut figure = std::mem::take(tops);
+    figure.extend(bases.drain(..).rev());
+    figures.push(figure);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 100×100 chart of the last 10 seconds, a point a second, so a
+    /// second is 10 pixels and a value of 1 is a pixel.
+    fn chart() -> ChartModel {
+        let mut chart = ChartModel::new(100.0, 100.0);
+        chart.set_time_scale(TimeScale {
+            window: Duration::from_secs(10),
+            interval: Duration::from_secs(1),
+        });
+        chart
+    }
+
+    fn points(chart: &ChartModel, series: usize) -> Vec<Option<f32>> {
+        chart.series()[series].points().iter().copied().collect()
+    }
+
+    /// The x of every vertical grid line and the y of every horizontal
+    /// one, rounded to hide float noise.
+    fn grid_lines(chart: &ChartModel) -> (Vec<f32>, Vec<f32>) {
+        let round = |value: f32| (value * 100.0).round() / 100.0;
+        let (mut xs, mut ys) = (Vec::new(), Vec::new());
+        for command in chart.display_list().commands {
+            if let DrawCommand::GridLine { from, to, .. } = command {
+                if from.x == to.x {
+                    xs.push(round(from.x));
+                } else {
+                    ys.push(round(from.y));
+                }
+            }
+        }
+        (xs, ys)
+    }
+
+    #[test]
+    fn keeps_a_window_of_points() {
+        let mut chart = chart();
+        assert_eq!(chart.point_count(), 11);
+        for i in 0..15 {
+            chart.add_values([("3D", Some(i as f32))]);
+        }
+        let expected: Vec<_> = (4..15).map(|i| Some(i as f32)).collect();
+        assert_eq!(points(&chart, 0), expected);
+
+        // A shorter window drops the oldest points.
+        chart.set_time_scale(TimeScale {
+            window: Duration::from_secs(2),
+            interval: Duration::from_secs(1),
+        });
+        assert_eq!(points(&chart, 0), [Some(12.0), Some(13.0), Some(14.0)]);
+    }
+
+    #[test]
+    fn keeps_series_aligned() {
+        let mut chart = chart();
+        chart.add_values([("3D", Some(1.0))]);
+        // A new series starts at the newest slot, and one that's missing
+        // gets a gap.
+        chart.add_values([("Copy", Some(2.0))]);
+        // The same series twice in a tick keeps the last value.
+        chart.add_values([("3D", Some(3.0)), ("3D", Some(4.0)), ("Copy", None)]);
+
+        assert_eq!(chart.series()[0].name(), "3D");
+        assert_eq!(points(&chart, 0), [Some(1.0), None, Some(4.0)]);
+        assert_eq!(points(&chart, 1), [Some(2.0), None]);
+        assert_eq!(chart.series()[0].color(), SERIES_COLORS[0]);
+        assert_eq!(chart.series()[1].color(), SERIES_COLORS[1]);
+
+        // Clearing keeps the series and their colors.
+        chart.clear();
+        assert_eq!(points(&chart, 1), []);
+        assert_eq!(chart.series()[1].color(), SERIES_COLORS[1]);
+    }
+
+    #[test]
+    fn splits_figures_at_gaps() {
+        let mut chart = chart();
+        for value in [Some(10.0), None, Some(20.0), Some(30.0)] {
+            chart.add_values([("3D", value)]);
+        }
+        let bands = chart.bands();
+        assert_eq!(
+            chart.figures(&bands[0].points),
+            [
+                vec![Point::new(70.0, 90.0), Point::new(70.0, 100.0)],
+                vec![
+                    Point::new(90.0, 80.0),
+                    Point::new(100.0, 70.0),
+                    Point::new(100.0, 100.0),
+                    Point::new(90.0, 100.0),
+                ],
+            ]
+        );
+        assert_eq!(chart.figures(&[None, None]), Vec::<Vec<Point>>::new());
+
+        // Each figure is outlined on its own.
+        let outlines = chart
+            .display_list()
+            .commands
+            .iter()
+            .filter(|command| matches!(command, DrawCommand::Polyline { .. }))
+            .count();
+        assert_eq!(outlines, 2);
+    }
+
+    #[test]
+    fn stacks_bands() {
+        let mut chart = chart();
+        chart.add_values([("3D", Some(10.0)), ("Copy", Some(5.0))]);
+        chart.add_values([("3D", Some(20.0)), ("Copy", None)]);
+        chart.add_values([("3D", None), ("Copy", Some(5.0))]);
+
+        let names = |bands: &[Band]| -> Vec<String> {
+            bands
+                .iter()
+                .map(|band| band.series.name().to_owned())
+                .collect()
+        };
+        let bands = chart.bands();
+        assert_eq!(names(&bands), ["3D", "Copy"]);
+        assert_eq!(
+            bands[0].points,
+            [Some((0.0, 10.0)), Some((0.0, 20.0)), None]
+        );
+        assert_eq!(bands[1].points, [Some((0.0, 5.0)), None, Some((0.0, 5.0))]);
+
+        // Listed series go at the bottom, and gaps add nothing.
+        chart.set_layout(SeriesLayout::Stacked {
+            order: vec!["Copy".to_owned()],
+        });
+        let bands = chart.bands();
+        assert_eq!(names(&bands), ["Copy", "3D"]);
+        assert_eq!(bands[0].points, [Some((0.0, 5.0)), None, Some((0.0, 5.0))]);
+        assert_eq!(
+            bands[1].points,
+            [Some((5.0, 15.0)), Some((0.0, 20.0)), None]
+        );
+    }
+
+    #[test]
+    fn scales_to_the_stacked_total() {
+        let mut chart = chart();
+        chart.set_scale_mode(ScaleMode::Fit);
+        chart.add_values([("3D", Some(30.0)), ("Copy", Some(40.0))]);
+        assert_eq!(chart.y_axis().ticks().max, 40.0);
+        chart.set_layout(SeriesLayout::Stacked { order: Vec::new() });
+        assert_eq!(chart.y_axis().ticks().max, 70.0);
+    }
+
+    #[test]
+    fn ignores_values_too_large_to_scale() {
+        let mut chart = chart();
+        chart.set_scale_mode(ScaleMode::Fit);
+        chart.add_values([("3D", Some(f32::INFINITY)), ("Copy", Some(40.0))]);
+        chart.add_values([("3D", Some(f32::NAN)), ("Copy", Some(40.0))]);
+        assert_eq!(chart.y_axis().ticks().max, 40.0);
+
+        // A stacked total can overflow too.
+        chart.add_values([("3D", Some(f32::MAX)), ("Copy", Some(f32::MAX))]);
+        chart.set_layout(SeriesLayout::Stacked { order: Vec::new() });
+        assert!(chart.y_axis().ticks().max >= f32::MAX as f64);
+        assert!(chart.y_axis().ticks().max.is_finite());
+        assert!(chart.display_list().commands.len() > 1);
+    }
+
+    #[test]
+    fn places_grid_lines() {
+        let mut chart = chart();
+        // A line every 10%, leaving out the ones on the border.
+        let (_, ys) = grid_lines(&chart);
+        let expected: Vec<f32> = (1..10).rev().map(|i| i as f32 * 10.0).collect();
+        assert_eq!(ys, expected);
+
+        // A line every 2 seconds, scrolling left a second per point.
+        assert_eq!(grid_lines(&chart).0, [80.0, 60.0, 40.0, 20.0]);
+        chart.add_values([("3D", Some(50.0))]);
+        assert_eq!(grid_lines(&chart).0, [90.0, 70.0, 50.0, 30.0, 10.0]);
+        chart.add_values([("3D", Some(50.0))]);
+        assert_eq!(grid_lines(&chart).0, [80.0, 60.0, 40.0, 20.0]);
+    }
+
+    #[test]
+    fn places_time_ticks() {
+        let chart = chart();
+        let ticks: Vec<(u64, f32)> = chart
+            .time_ticks()
+            .into_iter()
+            .map(|(age, x)| (age.as_secs(), x))
+            .collect();
+        // The window's edge is left out.
+        assert_eq!(
+            ticks,
+            [(0, 100.0), (2, 80.0), (4, 60.0), (6, 40.0), (8, 20.0)]
+        );
+
+        // Anything older than the window is clamped to the left edge.
+        assert_eq!(chart.age_to_x(Duration::from_secs(10)), 0.0);
+        assert_eq!(chart.age_to_x(Duration::from_secs(25)), 0.0);
+        assert_eq!(chart.slot_to_x(0), 0.0);
+        assert_eq!(chart.slot_to_x(10), 100.0);
+    }
+}
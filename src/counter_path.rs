@@ -0,0 +1,351 @@
+use std::{fmt, str::FromStr};
+
+/// The instance part of a counter path, `parent/name#index` in
+/// `\Object(parent/name#index)\Counter`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct CounterInstance {
+    pub parent: Option<String>,
+    pub name: String,
+    /// Tells apart instances with the same name, e.g. two processes with
+    /// the same executable. `#0` is the same as no index.
+    pub index: Option<u32>,
+}
+
+impl fmt::Display for CounterInstance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(parent) = &self.parent {
+            write!(f, "{}/", parent)?;
+        }
+        write!(f, "{}", self.name)?;
+        if let Some(index) = self.index {
+            write!(f, "#{}", index)?;
+        }
+        Ok(())
+    }
+}
+
+/// A PDH counter path:
+/// `[\\machine]\object[(parent/instance#index)]\counter`. Any part but the
+/// machine may use `*` as a wildcard, either on its own or within a name
+/// (e.g. `pid_1234_*`). Formatting a parsed path gives back the text it
+/// was parsed from.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct CounterPath {
+    pub machine: Option<String>,
+    pub object: String,
+    pub instance: Option<CounterInstance>,
+    pub counter: String,
+}
+
+impl CounterPath {
+    /// A path to a counter of an object without instances, e.g.
+    /// `\Memory\Available Bytes`. `object` and `counter` must not contain
+    /// backslashes, and `object` must not contain parentheses.
+    #[cfg_attr(not(windows), allow(dead_code))]
+    pub fn new<O: Into<String>, C: Into<String>>(object: O, counter: C) -> Self {
+        Self {
+            machine: None,
+            object: object.into(),
+            instance: None,
+            counter: counter.into(),
+        }
+    }
+
+    /// Selects an instance of the object. The name is escaped, so any
+    /// name works, including ones with wildcards.
+    #[cfg_attr(not(windows), allow(dead_code))]
+    pub fn with_instance(mut self, name: &str) -> Self {
+        self.instance = Some(CounterInstance {
+            parent: None,
+            name: escape_instance_name(name),
+            index: None,
+        });
+        self
+    }
+
+    /// A name for the counter in titles and legends: the path without the
+    /// machine or the leading backslash, e.g.
+    /// `Processor(_Total)\% Processor Time`.
+    pub fn display_name(&self) -> String {
+        let mut name = self.object.clone();
+        if let Some(instance) = &self.instance {
+            name.push_str(&format!("({})", instance));
+        }
+        name.push('\\');
+        name.push_str(&self.counter);
+        name
+    }
+
+    /// Whether the counter is a percentage, judging by its name.
+    pub fn is_percentage(&self) -> bool {
+        self.counter.starts_with('%')
+    }
+}
+
+impl fmt::Display for CounterPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(machine) = &self.machine {
+            write!(f, r"\\{}", machine)?;
+        }
+        write!(f, r"\{}", self.object)?;
+        if let Some(instance) = &self.instance {
+            write!(f, "({})", instance)?;
+        }
+        write!(f, r"\{}", self.counter)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseCounterPathError {
+    path: String,
+    reason: &'static str,
+}
+
+impl fmt::Display for ParseCounterPathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid counter path '{}': {}", self.path, self.reason)
+    }
+}
+
+impl std::error::Error for ParseCounterPathError {}
+
+impl FromStr for CounterPath {
+    type Err = ParseCounterPathError;
+
+    fn from_str(path: &str) -> Result<Self, Self::Err> {
+        parse_counter_path(path).map_err(|reason| ParseCounterPathError {
+            path: path.to_owned(),
+            reason,
+        })
+    }
+}
+
+fn parse_counter_path(path: &str) -> Result<CounterPath, &'static str> {
+    let (machine, rest) = match path.strip_prefix(r"\\") {
+        Some(rest) => {
+            let (machine, rest) = rest
+                .split_once('\\')
+                .ok_or("expected an object after the machine name")?;
+            if machine.is_empty() {
+                return Err("the machine name is empty");
+            }
+            if machine.contains('*') {
+                return Err("the machine name can't contain wildcards");
+            }
+            (Some(machine.to_owned()), rest)
+        }
+        None => (
+            None,
+            path.strip_prefix('\\')
+                .ok_or("expected a path starting with a backslash")?,
+        ),
+    };
+
+    let object_end = rest
+        .find(['(', '\\'])
+        .ok_or("expected a counter after the object")?;
+    let object = &rest[..object_end];
+    if object.is_empty() {
+        return Err("the object name is empty");
+    }
+
+    let (instance, counter) = if rest[object_end..].starts_with('(') {
+        // Instance names can contain backslashes and parentheses (e.g.
+        // file paths), but never a closing parenthesis right before one.
+        let instance_end = rest
+            .rfind(r")\")
+            .filter(|end| *end > object_end)
+            .ok_or("expected ')' followed by a counter after the instance")?;
+        let instance = parse_instance(&rest[object_end + 1..instance_end])?;
+        (Some(instance), &rest[instance_end + 2..])
+    } else {
+        (None, &rest[object_end + 1..])
+    };
+    if counter.is_empty() {
+        return Err("the counter name is empty");
+    }
+    if counter.contains('\\') {
+        return Err("the counter name can't contain backslashes");
+    }
+
+    Ok(CounterPath {
+        machine,
+        object: object.to_owned(),
+        instance,
+        counter: counter.to_owned(),
+    })
+}
+
+fn parse_instance(instance: &str) -> Result<CounterInstance, &'static str> {
+    let (parent, rest) = match instance.split_once('/') {
+        Some((parent, rest)) => (Some(parent.to_owned()), rest),
+        None => (None, instance),
+    };
+    // Only a trailing `#` followed by digits is an index, so names like
+    // `C# Compiler` stay intact.
+    let (name, index) = match rest.rsplit_once('#') {
+        Some((name, index))
+            if !index.is_empty() && index.bytes().all(|byte| byte.is_ascii_digit()) =>
+        {
+            let index = index
+                .parse()
+                .map_err(|_| "the instance index is too large")?;
+            (name, Some(index))
+        }
+        _ => (rest, None),
+    };
+    if name.is_empty() {
+        return Err("the instance name is empty");
+    }
+    if parent.as_deref().is_some_and(str::is_empty) {
+        return Err("the parent instance name is empty");
+    }
+
+    Ok(CounterInstance {
+        parent,
+        name: name.to_owned(),
+        index,
+    })
+}
+
+/// Replaces the characters that have a meaning in counter paths the same
+/// way Windows does for process names, e.g. `app (1).exe` becomes
+/// `app [1].exe`. Wildcards are kept.
+#[cfg_attr(not(windows), allow(dead_code))]
+pub fn escape_instance_name(name: &str) -> String {
+    name.chars()
+        .map(|char| match char {
+            '(' => '[',
+            ')' => ']',
+            '/' | '\\' | '#' => '_',
+            char => char,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(path: &str) -> CounterPath {
+        path.parse().unwrap()
+    }
+
+    fn instance(parent: Option<&str>, name: &str, index: Option<u32>) -> Option<CounterInstance> {
+        Some(CounterInstance {
+            parent: parent.map(str::to_owned),
+            name: name.to_owned(),
+            index,
+        })
+    }
+
+    #[test]
+    fn parses_every_part() {
+        assert_eq!(
+            parse(r"\\GAMING-PC\Thread(dwm/3#2)\% Processor Time"),
+            CounterPath {
+                machine: Some("GAMING-PC".to_owned()),
+                object: "Thread".to_owned(),
+                instance: instance(Some("dwm"), "3", Some(2)),
+                counter: "% Processor Time".to_owned(),
+            }
+        );
+        assert_eq!(
+            parse(r"\Memory\Available Bytes"),
+            CounterPath::new("Memory", "Available Bytes")
+        );
+        assert_eq!(
+            parse(r"\Process(dwm#0)\IO Data Bytes/sec").instance,
+            instance(None, "dwm", Some(0))
+        );
+    }
+
+    #[test]
+    fn parses_awkward_instance_names() {
+        // Parentheses and backslashes, e.g. in file paths.
+        assert_eq!(
+            parse(r"\LogicalDisk(C:\Users (old))\% Free Space").instance,
+            instance(None, r"C:\Users (old)", None)
+        );
+        assert_eq!(
+            parse(r"\Process(app [1].exe)\% Processor Time").instance,
+            instance(None, "app [1].exe", None)
+        );
+        // Only digits after the last `#` are an index.
+        assert_eq!(
+            parse(r"\.NET CLR(C# Compiler)\Bytes").instance,
+            instance(None, "C# Compiler", None)
+        );
+        assert_eq!(
+            parse(r"\.NET CLR(C# Compiler#12)\Bytes").instance,
+            instance(None, "C# Compiler", Some(12))
+        );
+    }
+
+    #[test]
+    fn parses_wildcards() {
+        let path = parse(r"\GPU Engine(pid_1234_*_engtype_3D)\Utilization Percentage");
+        assert_eq!(path.instance, instance(None, "pid_1234_*_engtype_3D", None));
+        let path = parse(r"\Process(*/*#*)\*");
+        assert_eq!(path.instance, instance(Some("*"), "*#*", None));
+        assert_eq!(path.counter, "*");
+        assert_eq!(parse(r"\*\*").object, "*");
+    }
+
+    #[test]
+    fn round_trips() {
+        for path in [
+            r"\\GAMING-PC\Thread(dwm/3#2)\% Processor Time",
+            r"\Memory\Available Bytes",
+            r"\LogicalDisk(C:\Users (old))\% Free Space",
+            r"\.NET CLR(C# Compiler)\Bytes",
+            r"\GPU Engine(pid_1234_*)\Utilization Percentage",
+            r"\Process(*/*#*)\*",
+        ] {
+            assert_eq!(parse(path).to_string(), path);
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_paths() {
+        for path in [
+            "",
+            r"Memory\Available Bytes",
+            r"\Memory",
+            r"\Memory\",
+            r"\\Memory",
+            r"\\\Memory\Available Bytes",
+            r"\\PC*\Memory\Available Bytes",
+            r"\(dwm)\% Processor Time",
+            r"\Process(dwm\% Processor Time",
+            r"\Process()\% Processor Time",
+            r"\Process(/dwm)\% Processor Time",
+            r"\Process(#1)\% Processor Time",
+            r"\Process(dwm#99999999999)\% Processor Time",
+            r"\Memory\Available\Bytes",
+        ] {
+            assert!(path.parse::<CounterPath>().is_err(), "{}", path);
+        }
+        assert_eq!(
+            r"\Memory".parse::<CounterPath>().unwrap_err().to_string(),
+            r"Invalid counter path '\Memory': expected a counter after the object"
+        );
+    }
+
+    #[test]
+    fn escapes_instance_names() {
+        assert_eq!(escape_instance_name("app (1).exe"), "app [1].exe");
+        assert_eq!(escape_instance_name(r"a/b\c#1*"), "a_b_c_1*");
+        let path = CounterPath::new("Process", "% Processor Time").with_instance("app (1).exe");
+        assert_eq!(path.to_string(), r"\Process(app [1].exe)\% Processor Time");
+        assert_eq!(parse(&path.to_string()), path);
+    }
+
+    #[test]
+    fn names_counters() {
+        let path = parse(r"\\GAMING-PC\Processor(_Total)\% Processor Time");
+        assert_eq!(path.display_name(), r"Processor(_Total)\% Processor Time");
+        assert!(path.is_percentage());
+        assert!(!parse(r"\Memory\Available Bytes").is_percentage());
+    }
+}
@@ -0,0 +1,389 @@
+use std::collections::HashSet;
+
+use crate::metric;
+
+/// The parts of PDH needed to keep the counters behind a wildcard path up
+/// to date, so the bookkeeping can run against a fake.
+pub trait CounterBackend {
+    type Handle: Copy;
+
+    /// Returns the paths that currently match `wildcard_path`.
+    fn expand(&mut self, wildcard_path: &str) -> metric::Result<Vec<String>>;
+    fn add_counter(&mut self, path: &str) -> metric::Result<Self::Handle>;
+    fn remove_counter(&mut self, handle: Self::Handle) -> metric::Result<()>;
+}
+
+/// How many counters a reconciliation added and removed.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Changes {
+    pub added: usize,
+    pub removed: usize,
+}
+
+/// The counters a wildcard path expands to. Instances come and go while
+/// a process runs (e.g. when it starts using another adapter), so the set
+/// is re-expanded every `interval` ticks. Counters that are still around
+/// keep their handles, so their history carries on uninterrupted.
+pub struct CounterSet<H> {
+    wildcard_path: String,
+    counters: Vec<(String, H)>,
+    interval: usize,
+    ticks: usize,
+}
+
+impl<H: Copy> CounterSet<H> {
+    /// Creates an empty set. Call `reconcile` to add the initial counters.
+    pub fn new(wildcard_path: String, interval: usize) -> Self {
+        Self {
+            wildcard_path,
+            counters: Vec::new(),
+            interval: interval.max(1),
+            ticks: 0,
+        }
+    }
+
+    /// Every counter path along with its handle, in the order they were
+    /// added.
+    pub fn counters(&self) -> &[(String, H)] {
+        &self.counters
+    }
+
+    /// Counts a tick, and reconciles the set if one is due. Returns `None`
+    /// if it wasn't. A reconciliation that fails is tried again an
+    /// interval later.
+    pub fn tick<B: CounterBackend<Handle = H>>(
+        &mut self,
+        backend: &mut B,
+    ) -> metric::Result<Option<Changes>> {
+        self.ticks += 1;
+        if self.ticks < self.interval {
+            return Ok(None);
+        }
+        self.reconcile(backend).map(Some)
+    }
+
+    /// Re-expands the wildcard path, removing the counters of instances
+    /// that went away and adding counters for new ones.
+    pub fn reconcile<B: CounterBackend<Handle = H>>(
+        &mut self,
+        backend: &mut B,
+    ) -> metric::Result<Changes> {
+        self.ticks = 0;
+        let paths = backend.expand(&self.wildcard_path)?;
+        let current: HashSet<&str> = paths.iter().map(String::as_str).collect();
+
+        let (kept, vanished): (Vec<_>, Vec<_>) = self
+            .counters
+            .drain(..)
+            .partition(|(path, _)| current.contains(path.as_str()));
+        self.counters = kept;
+        // Counters that fail to be removed stay in the set, so removing
+        // them is tried again along with the next reconciliation.
+        let mut removed = 0;
+        let mut first_error = None;
+        for (path, handle) in vanished {
+            match backend.remove_counter(handle) {
+                Ok(()) => removed += 1,
+                Err(error) => {
+                    self.counters.push((path, handle));
+                    first_error.get_or_insert(error);
+                }
+            }
+        }
+        if let Some(error) = first_error {
+            return Err(error);
+        }
+
+        let mut existing: HashSet<String> =
+            self.counters.iter().map(|(path, _)| path.clone()).collect();
+        let mut added = 0;
+        for path in paths {
+            if !existing.insert(path.clone()) {
+                continue;
+            }
+            let handle = backend.add_counter(&path)?;
+            self.counters.push((path, handle));
+            added += 1;
+        }
+
+        Ok(Changes { added, removed })
+    }
+}
+
+/// The counters of several sets, each path once. Wildcards can overlap,
+/// e.g. `\Process(*)\% Processor Time` and `\Process(dwm)\% Processor Time`,
+/// and a path that's in more than one set is only read from the first.
+pub struct UniqueCounters<H> {
+    counters: Vec<(String, H)>,
+    /// Paths that have been in more than one set, which were reported.
+    overlapping: HashSet<String>,
+}
+
+impl<H> Default for UniqueCounters<H> {
+    fn default() -> Self {
+        Self {
+            counters: Vec::new(),
+            overlapping: HashSet::new(),
+        }
+    }
+}
+
+impl<H: Copy> UniqueCounters<H> {
+    /// Every counter to read, in the order of the sets.
+    pub fn counters(&self) -> &[(String, H)] {
+        &self.counters
+    }
+
+    /// Collects the counters of `sets` again after they changed. Returns
+    /// the paths that are in more than one set for the first time, so
+    /// each gets reported once.
+    pub fn update(&mut self, sets: &[CounterSet<H>]) -> Vec<String> {
+        let mut seen = HashSet::new();
+        let mut newly_overlapping = Vec::new();
+        self.counters.clear();
+        for (path, handle) in sets.iter().flat_map(CounterSet::counters) {
+            if seen.insert(path.as_str()) {
+                self.counters.push((path.clone(), *handle));
+            } else if self.overlapping.insert(path.clone()) {
+                newly_overlapping.push(path.clone());
+            }
+        }
+        newly_overlapping
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gpu_engine::process_engines_path;
+
+    /// Stands in for PDH: `paths` is what the wildcard expands to, and
+    /// handles are handed out in order.
+    #[derive(Default)]
+    struct FakeBackend {
+        paths: Vec<String>,
+        added: Vec<String>,
+        removed: Vec<usize>,
+        fail_expand: bool,
+        /// Handles that fail to be removed.
+        fail_remove: Vec<usize>,
+    }
+
+    impl FakeBackend {
+        fn set_paths(&mut self, paths: &[&str]) {
+            self.paths = paths.iter().map(|path| path.to_string()).collect();
+        }
+    }
+
+    impl CounterBackend for FakeBackend {
+        type Handle = usize;
+
+        fn expand(&mut self, wildcard_path: &str) -> metric::Result<Vec<String>> {
+            assert_eq!(
+                wildcard_path,
+                r"\GPU Engine(pid_1234_*)\Utilization Percentage"
+            );
+            if self.fail_expand {
+                return Err("PDH_CSTATUS_NO_OBJECT".into());
+            }
+            Ok(self.paths.clone())
+        }
+
+        fn add_counter(&mut self, path: &str) -> metric::Result<usize> {
+            self.added.push(path.to_owned());
+            Ok(self.added.len() - 1)
+        }
+
+        fn remove_counter(&mut self, handle: usize) -> metric::Result<()> {
+            if self.fail_remove.contains(&handle) {
+                return Err("PDH_INVALID_HANDLE".into());
+            }
+            self.removed.push(handle);
+            Ok(())
+        }
+    }
+
+    fn counter_set(interval: usize) -> CounterSet<usize> {
+        CounterSet::new(process_engines_path(1234).to_string(), interval)
+    }
+
+    fn paths(set: &CounterSet<usize>) -> Vec<(&str, usize)> {
+        set.counters()
+            .iter()
+            .map(|(path, handle)| (path.as_str(), *handle))
+            .collect()
+    }
+
+    #[test]
+    fn adds_the_initial_counters() {
+        let mut backend = FakeBackend::default();
+        backend.set_paths(&["3D", "Copy"]);
+        let mut set = counter_set(1);
+        assert_eq!(
+            set.reconcile(&mut backend).unwrap(),
+            Changes {
+                added: 2,
+                removed: 0
+            }
+        );
+        assert_eq!(paths(&set), [("3D", 0), ("Copy", 1)]);
+    }
+
+    #[test]
+    fn keeps_the_handles_of_counters_that_stay() {
+        let mut backend = FakeBackend::default();
+        backend.set_paths(&["3D", "Copy", "Video"]);
+        let mut set = counter_set(1);
+        set.reconcile(&mut backend).unwrap();
+
+        backend.set_paths(&["Compute", "3D", "Video"]);
+        assert_eq!(
+            set.reconcile(&mut backend).unwrap(),
+            Changes {
+                added: 1,
+                removed: 1
+            }
+        );
+        assert_eq!(paths(&set), [("3D", 0), ("Video", 2), ("Compute", 3)]);
+        assert_eq!(backend.removed, [1]);
+
+        // Nothing changed, so nothing is touched.
+        assert_eq!(set.reconcile(&mut backend).unwrap(), Changes::default());
+        assert_eq!(backend.added.len(), 4);
+    }
+
+    #[test]
+    fn handles_instances_that_come_back() {
+        let mut backend = FakeBackend::default();
+        backend.set_paths(&["3D"]);
+        let mut set = counter_set(1);
+        set.reconcile(&mut backend).unwrap();
+
+        backend.set_paths(&[]);
+        set.reconcile(&mut backend).unwrap();
+        assert_eq!(paths(&set), []);
+
+        // A counter that comes back is a new counter.
+        backend.set_paths(&["3D", "3D"]);
+        assert_eq!(
+            set.reconcile(&mut backend).unwrap(),
+            Changes {
+                added: 1,
+                removed: 0
+            }
+        );
+        assert_eq!(paths(&set), [("3D", 1)]);
+    }
+
+    #[test]
+    fn reconciles_every_interval() {
+        let mut backend = FakeBackend::default();
+        backend.set_paths(&["3D"]);
+        let mut set = counter_set(3);
+        set.reconcile(&mut backend).unwrap();
+
+        backend.set_paths(&["3D", "Copy"]);
+        assert_eq!(set.tick(&mut backend).unwrap(), None);
+        assert_eq!(set.tick(&mut backend).unwrap(), None);
+        assert_eq!(set.tick(&mut backend).unwrap().unwrap().added, 1);
+        assert_eq!(set.tick(&mut backend).unwrap(), None);
+    }
+
+    #[test]
+    fn keeps_counters_that_fail_to_be_removed() {
+        let mut backend = FakeBackend::default();
+        backend.set_paths(&["3D", "Copy", "Video"]);
+        let mut set = counter_set(1);
+        set.reconcile(&mut backend).unwrap();
+
+        // The first removal failing doesn't stop the others.
+        backend.fail_remove = vec![0];
+        backend.set_paths(&["Compute"]);
+        assert!(set.reconcile(&mut backend).is_err());
+        assert_eq!(backend.removed, [1, 2]);
+        assert_eq!(paths(&set), [("3D", 0)]);
+
+        backend.fail_remove.clear();
+        assert_eq!(
+            set.reconcile(&mut backend).unwrap(),
+            Changes {
+                added: 1,
+                removed: 1
+            }
+        );
+        assert_eq!(backend.removed, [1, 2, 0]);
+        assert_eq!(paths(&set), [("Compute", 3)]);
+    }
+
+    #[test]
+    fn retries_failed_reconciliations_an_interval_later() {
+        let mut backend = FakeBackend::default();
+        backend.set_paths(&["3D"]);
+        let mut set = counter_set(2);
+        set.reconcile(&mut backend).unwrap();
+
+        backend.fail_expand = true;
+        assert_eq!(set.tick(&mut backend).unwrap(), None);
+        assert!(set.tick(&mut backend).is_err());
+        backend.fail_expand = false;
+        backend.set_paths(&["3D", "Copy"]);
+        assert_eq!(set.tick(&mut backend).unwrap(), None);
+        assert_eq!(set.tick(&mut backend).unwrap().unwrap().added, 1);
+        assert_eq!(paths(&set), [("3D", 0), ("Copy", 1)]);
+    }
+
+    #[test]
+    fn keeps_the_counters_when_expanding_fails() {
+        let mut backend = FakeBackend::default();
+        backend.set_paths(&["3D"]);
+        let mut set = counter_set(1);
+        set.reconcile(&mut backend).unwrap();
+
+        backend.fail_expand = true;
+        assert!(set.reconcile(&mut backend).is_err());
+        assert_eq!(paths(&set), [("3D", 0)]);
+        assert_eq!(backend.removed, []);
+    }
+
+    #[test]
+    fn reads_overlapping_counters_once() {
+        let mut backend = FakeBackend::default();
+        let mut first = counter_set(1);
+        backend.set_paths(&["3D", "Copy"]);
+        first.reconcile(&mut backend).unwrap();
+        let mut second = counter_set(1);
+        backend.set_paths(&["Copy", "Video"]);
+        second.reconcile(&mut backend).unwrap();
+        let mut sets = [first, second];
+
+        let mut unique = UniqueCounters::default();
+        assert_eq!(unique.update(&sets), ["Copy"]);
+        let counters = |unique: &UniqueCounters<usize>| unique.counters().to_vec();
+        assert_eq!(
+            counters(&unique),
+            [
+                ("3D".to_owned(), 0),
+                ("Copy".to_owned(), 1),
+                ("Video".to_owned(), 3)
+            ]
+        );
+
+        // Each overlap is only reported the first time, even if it goes
+        // away and comes back.
+        assert_eq!(unique.update(&sets), Vec::<String>::new());
+        backend.set_paths(&["Video"]);
+        sets[1].reconcile(&mut backend).unwrap();
+        assert_eq!(unique.update(&sets), Vec::<String>::new());
+        backend.set_paths(&["3D", "Copy", "Video"]);
+        sets[1].reconcile(&mut backend).unwrap();
+        assert_eq!(unique.update(&sets), ["3D"]);
+        assert_eq!(
+            counters(&unique),
+            [
+                ("3D".to_owned(), 0),
+                ("Copy".to_owned(), 1),
+                ("Video".to_owned(), 3)
+            ]
+        );
+    }
+}
@@ -0,0 +1,186 @@
+use std::collections::HashMap;
+
+use windows::core::Result;
+
+use crate::{
+    counter_path::CounterPath,
+    counter_set::{CounterSet, UniqueCounters},
+    metric::{self, MetricSource, Reading, Unit},
+    pdh::{localize_counter_path, read_sample, PerfQueryHandle},
+    windows_utils::error::to_windows_error,
+};
+
+// How often to look for new instances of counters with wildcards.
+const RECONCILE_INTERVAL_IN_TICKS: usize = 5;
+
+/// Samples arbitrary performance counters, one series per counter. A path
+/// with wildcards gets a series for every counter it expands to.
+pub struct CounterTracker {
+    name: String,
+    unit: Unit,
+    query_handle: PerfQueryHandle,
+    counter_sets: Vec<CounterSet<isize>>,
+    /// The counters read every tick, each path once.
+    counters: UniqueCounters<isize>,
+    /// Tells the user about counters that several `--counter` arguments
+    /// match.
+    overlap_warning: Option<String>,
+    /// Why looking for new counters failed the last time it was tried.
+    reconcile_error: Option<String>,
+    /// The series name of every counter, by path.
+    series_names: HashMap<String, String>,
+    /// The last value of each counter, by path, for filling in stale
+    /// samples.
+    last_values: HashMap<String, f64>,
+}
+
+impl CounterTracker {
+    /// `paths` are in English, and are localized to the language of the
+    /// system.
+    pub fn new(paths: &[CounterPath]) -> Result<Self> {
+        let mut query_handle = PerfQueryHandle::open_query()?;
+        let mut counter_sets = Vec::with_capacity(paths.len());
+        for path in paths {
+            let wildcard_path = localize_counter_path(&query_handle, &path.to_string())?;
+            let mut counters = CounterSet::new(wildcard_path, RECONCILE_INTERVAL_IN_TICKS);
+            counters
+                .reconcile(&mut query_handle)
+                .map_err(to_windows_error)?;
+            counter_sets.push(counters);
+        }
+
+        let name = match paths {
+            [path] => path.display_name(),
+            _ => "Performance Counters".to_owned(),
+        };
+        let unit = if paths.iter().all(CounterPath::is_percentage) {
+            Unit::Percent
+        } else {
+            Unit::Count
+        };
+
+        let mut tracker = Self {
+            name,
+            unit,
+            query_handle,
+            counter_sets,
+            counters: UniqueCounters::default(),
+            overlap_warning: None,
+            reconcile_error: None,
+            series_names: HashMap::new(),
+            last_values: HashMap::new(),
+        };
+        tracker.update_counters();
+        Ok(tracker)
+    }
+
+    pub fn start(&self) -> Result<()> {
+        self.query_handle.collect_data()
+    }
+
+    pub fn get_current_values(&mut self) -> Result<Vec<Reading>> {
+        self.query_handle.collect_data()?;
+
+        let mut readings = Vec::new();
+        let mut last_values = HashMap::with_capacity(self.last_values.len());
+        for (path, counter_handle) in self.counters.counters() {
+            let sample = read_sample(*counter_handle, self.last_values.get(path).copied())?;
+            if let Some(value) = sample.value {
+                last_values.insert(path.clone(), value);
+            }
+            readings.push(Reading::new(self.series_names[path].clone(), sample));
+        }
+        self.last_values = last_values;
+
+        // The readings are kept even if the counters can't be updated,
+        // which is tried again an interval later.
+        let (mut added, mut removed) = (0, 0);
+        let mut reconciled = false;
+        let mut errors = Vec::new();
+        for counters in &mut self.counter_sets {
+            match counters.tick(&mut self.query_handle) {
+                Ok(Some(changes)) => {
+                    reconciled = true;
+                    added += changes.added;
+                    removed += changes.removed;
+                }
+                Ok(None) => {}
+                Err(error) => errors.push(error.to_string()),
+            }
+        }
+        if reconciled || !errors.is_empty() {
+            self.reconcile_error = errors
+                .first()
+                .map(|error| format!("Failed to look for new counters: {}", error));
+        }
+        // A failed reconciliation may still have changed some counters.
+        if added > 0 || removed > 0 || !errors.is_empty() {
+            self.update_counters();
+        }
+        if added > 0 {
+            // Rate counters need two samples before they have a value. If
+            // collecting fails, so will the next tick's.
+            let _ = self.query_handle.collect_data();
+        }
+
+        Ok(readings)
+    }
+
+    pub fn close(mut self) -> Result<()> {
+        self.query_handle.close_query()
+    }
+
+    /// Collects the counters to read, and names every one after its
+    /// expanded path, falling back to the path itself if PDH returns one
+    /// we can't parse. A path matched by several `--counter` arguments is
+    /// charted once, with a warning.
+    fn update_counters(&mut self) {
+        let overlapping = self.counters.update(&self.counter_sets);
+        if !overlapping.is_empty() {
+            self.overlap_warning = Some(format!(
+                "{} matched by more than one --counter, charted once",
+                overlapping.join(", ")
+            ));
+        }
+        self.series_names = self
+            .counters
+            .counters()
+            .iter()
+            .map(|(path, _)| {
+                let name = path
+                    .parse::<CounterPath>()
+                    .map(|path| path.display_name())
+                    .unwrap_or_else(|_| path.clone());
+                (path.clone(), name)
+            })
+            .collect();
+    }
+}
+
+impl MetricSource for CounterTracker {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn unit(&self) -> Unit {
+        self.unit
+    }
+
+    fn start(&mut self) -> metric::Result<()> {
+        Ok(CounterTracker::start(self)?)
+    }
+
+    fn sample(&mut self) -> metric::Result<Vec<Reading>> {
+        Ok(self.get_current_values()?)
+    }
+
+    fn close(self: Box<Self>) -> metric::Result<()> {
+        Ok(CounterTracker::close(*self)?)
+    }
+
+    fn warning(&self) -> Option<String> {
+        self.reconcile_error
+            .clone()
+            .or_else(|| self.overlap_warning.clone())
+    }
+}
@@ -0,0 +1,450 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs, io,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    metric::{self, MetricSource, ProcessInfo, Reading, Sample, SampleStatus, Unit},
+    pid::read_process_name,
+};
+
+/// The usage stats of one DRM client, parsed from a single
+/// /proc/<pid>/fdinfo/<fd> file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DrmClient {
+    pub driver: String,
+    pub pdev: Option<String>,
+    pub client_id: u64,
+    /// Busy time in nanoseconds, by engine name.
+    pub engines: BTreeMap<String, u64>,
+    /// Number of engines of each class. Missing entries mean 1.
+    pub capacities: BTreeMap<String, u32>,
+}
+
+impl DrmClient {
+    /// Several file descriptors can refer to the same client (e.g. after a
+    /// dup), and client ids are only unique per device.
+    fn key(&self) -> (String, Option<String>, u64) {
+        (self.driver.clone(), self.pdev.clone(), self.client_id)
+    }
+}
+
+/// Parses the contents of an fdinfo file. Returns `None` for file
+/// descriptors that aren't DRM clients.
+pub fn parse_fdinfo(text: &str) -> Option<DrmClient> {
+    let mut driver = None;
+    let mut pdev = None;
+    let mut client_id = None;
+    let mut engines = BTreeMap::new();
+    let mut capacities = BTreeMap::new();
+
+    for line in text.lines() {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        if key == "drm-driver" {
+            driver = Some(value.to_owned());
+        } else if key == "drm-pdev" {
+            pdev = Some(value.to_owned());
+        } else if key == "drm-client-id" {
+            client_id = value.parse().ok();
+        } else if let Some(engine) = key.strip_prefix("drm-engine-capacity-") {
+            if let Ok(capacity) = value.parse() {
+                capacities.insert(engine.to_owned(), capacity);
+            }
+        } else if let Some(engine) = key.strip_prefix("drm-engine-") {
+            if let Some(Ok(busy)) = value.strip_suffix("ns").map(|value| value.trim().parse()) {
+                engines.insert(engine.to_owned(), busy);
+            }
+        }
+    }
+
+    Some(DrmClient {
+        driver: driver?,
+        pdev,
+        client_id: client_id?,
+        engines,
+        capacities,
+    })
+}
+
+/// Busy time of every DRM client a process has open at one point in time.
+#[derive(Clone, Debug, Default)]
+pub struct DrmSnapshot {
+    clients: HashMap<(String, Option<String>, u64), DrmClient>,
+}
+
+impl DrmSnapshot {
+    pub fn from_clients<I: IntoIterator<Item = DrmClient>>(clients: I) -> Self {
+        let mut snapshot = Self::default();
+        for client in clients {
+            // Duplicated file descriptors report identical values, so
+            // keeping either one is fine.
+            snapshot.clients.entry(client.key()).or_insert(client);
+        }
+        snapshot
+    }
+}
+
+/// Per-engine busy percentages between two snapshots. Clients that are
+/// new in `current` are counted from zero, since they were opened during
+/// the interval.
+pub fn engine_utilization(
+    previous: &DrmSnapshot,
+    current: &DrmSnapshot,
+    elapsed: Duration,
+) -> BTreeMap<String, f64> {
+    let elapsed = elapsed.as_nanos() as f64;
+    let mut busy: BTreeMap<String, u64> = BTreeMap::new();
+    let mut capacities: BTreeMap<String, u32> = BTreeMap::new();
+    for (key, client) in &current.clients {
+        let previous_client = previous.clients.get(key);
+        for (engine, value) in &client.engines {
+            let previous_value = previous_client
+                .and_then(|client| client.engines.get(engine))
+                .copied()
+                .unwrap_or(0);
+            *busy.entry(engine.clone()).or_default() += value.saturating_sub(previous_value);
+            let capacity = client.capacities.get(engine).copied().unwrap_or(1);
+            let entry = capacities.entry(engine.clone()).or_insert(capacity);
+            *entry = (*entry).max(capacity);
+        }
+    }
+
+    busy.into_iter()
+        .map(|(engine, busy)| {
+            let capacity = capacities.get(&engine).copied().unwrap_or(1).max(1) as f64;
+            let percent = if elapsed > 0.0 {
+                (busy as f64 / (elapsed * capacity) * 100.0).clamp(0.0, 100.0)
+            } else {
+                0.0
+            };
+            (engine, percent)
+        })
+        .collect()
+}
+
+/// How busy the GPU is as a whole: as busy as its busiest engine. Each
+/// engine already adds up every client using it, the same way engines of
+/// one type are summed on Windows, and taking the busiest one matches how
+/// Task Manager and `--grouping adapter` report a whole GPU.
+pub fn total_utilization(engines: &BTreeMap<String, f64>) -> f64 {
+    engines.values().copied().fold(0.0, f64::max)
+}
+
+/// The series for the GPU as a whole.
+const TOTAL_SERIES: &str = "Total";
+
+/// GPU engine utilization of a single process, read from the DRM fdinfo
+/// keys in /proc/<pid>/fdinfo.
+pub struct DrmGpuSource {
+    root: PathBuf,
+    process_id: u32,
+    name: String,
+    engine: Option<String>,
+    /// Every engine seen so far, so engines that go idle or away keep
+    /// their series.
+    engines: Vec<String>,
+    last: Option<(DrmSnapshot, Instant)>,
+}
+
+impl DrmGpuSource {
+    /// Reports `engine` if given, otherwise every engine and the total.
+    pub fn new(process_id: u32, engine: Option<String>) -> io::Result<Self> {
+        Self::with_root("/proc", process_id, engine)
+    }
+
+    /// Reads from a /proc tree rooted somewhere other than /proc.
+    pub fn with_root<P: Into<PathBuf>>(
+        root: P,
+        process_id: u32,
+        engine: Option<String>,
+    ) -> io::Result<Self> {
+        let root = root.into();
+        let name = read_process_name(&root, process_id)?;
+        Ok(Self {
+            root,
+            process_id,
+            name,
+            engines: engine.iter().cloned().collect(),
+            engine,
+            last: None,
+        })
+    }
+
+    /// A reading without a value for every series.
+    fn missing_readings(&self, status: SampleStatus) -> Vec<Reading> {
+        let total = self.engine.is_none().then_some(TOTAL_SERIES);
+        self.engines
+            .iter()
+            .map(String::as_str)
+            .chain(total)
+            .map(|series| Reading::new(series, Sample::missing(status)))
+            .collect()
+    }
+
+    fn read_snapshot(&self) -> io::Result<DrmSnapshot> {
+        let directory = self.root.join(self.process_id.to_string()).join("fdinfo");
+        let mut clients = Vec::new();
+        for entry in fs::read_dir(directory)? {
+            let entry = entry?;
+            // File descriptors can be closed while we're iterating.
+            let text = match fs::read_to_string(entry.path()) {
+                Ok(text) => text,
+                Err(error) if error.kind() == io::ErrorKind::NotFound => continue,
+                Err(error) => return Err(error),
+            };
+            clients.extend(parse_fdinfo(&text));
+        }
+        Ok(DrmSnapshot::from_clients(clients))
+    }
+}
+
+impl MetricSource for DrmGpuSource {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn unit(&self) -> Unit {
+        Unit::Percent
+    }
+
+    fn start(&mut self) -> metric::Result<()> {
+        self.last = Some((self.read_snapshot()?, Instant::now()));
+        Ok(())
+    }
+
+    fn sample(&mut self) -> metric::Result<Vec<Reading>> {
+        let snapshot = match self.read_snapshot() {
+            Ok(snapshot) => snapshot,
+            // The process exited.
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                self.last = None;
+                return Ok(self.missing_readings(SampleStatus::NoInstance));
+            }
+            Err(error) => return Err(error.into()),
+        };
+        let now = Instant::now();
+        let engines = match &self.last {
+            Some((last_snapshot, last_time)) => {
+                engine_utilization(last_snapshot, &snapshot, now - *last_time)
+            }
+            None => {
+                self.last = Some((snapshot, now));
+                return Ok(self.missing_readings(SampleStatus::Invalid));
+            }
+        };
+        self.last = Some((snapshot, now));
+
+        if self.engine.is_none() {
+            for engine in engines.keys() {
+                if !self.engines.contains(engine) {
+                    self.engines.push(engine.clone());
+                }
+            }
+        }
+        let mut readings: Vec<Reading> = self
+            .engines
+            .iter()
+            .map(|engine| {
+                let value = engines.get(engine).copied().unwrap_or(0.0);
+                Reading::new(engine.as_str(), Sample::valid(value))
+            })
+            .collect();
+        if self.engine.is_none() {
+            let total = Sample::valid(total_utilization(&engines));
+            readings.push(Reading::new(TOTAL_SERIES, total));
+        }
+        Ok(readings)
+    }
+
+    fn close(self: Box<Self>) -> metric::Result<()> {
+        Ok(())
+    }
+
+    fn process(&self) -> Option<ProcessInfo> {
+        Some(ProcessInfo {
+            process_id: self.process_id,
+            name: self.name.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake_proc::FakeProc;
+
+    const PROCESS_ID: u32 = 4321;
+
+    /// An fdinfo file the way amdgpu writes one.
+    fn fdinfo(client_id: u64, gfx: u64, compute: u64) -> String {
+        format!(
+            "pos:\t0\nflags:\t02100002\nmnt_id:\t24\nino:\t1085\n\
+             drm-driver:\tamdgpu\ndrm-pdev:\t0000:03:00.0\ndrm-client-id:\t{}\n\
+             drm-memory-vram:\t4096 KiB\n\
+             drm-engine-gfx:\t{} ns\ndrm-engine-compute:\t{} ns\n\
+             drm-engine-capacity-compute:\t4\n",
+            client_id, gfx, compute
+        )
+    }
+
+    fn fake_process() -> FakeProc {
+        let proc = FakeProc::new();
+        proc.write(&format!("{}/comm", PROCESS_ID), "game\n");
+        proc
+    }
+
+    fn write_fd(proc: &FakeProc, fd: u32, contents: &str) {
+        proc.write(&format!("{}/fdinfo/{}", PROCESS_ID, fd), contents);
+    }
+
+    fn source(proc: &FakeProc, engine: Option<&str>) -> DrmGpuSource {
+        DrmGpuSource::with_root(proc.root(), PROCESS_ID, engine.map(str::to_owned)).unwrap()
+    }
+
+    fn series(readings: &[Reading]) -> Vec<&str> {
+        readings
+            .iter()
+            .map(|reading| reading.series.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn parses_fdinfo() {
+        let client = parse_fdinfo(&fdinfo(7, 1000, 2000)).unwrap();
+        assert_eq!(client.driver, "amdgpu");
+        assert_eq!(client.pdev.as_deref(), Some("0000:03:00.0"));
+        assert_eq!(client.client_id, 7);
+        assert_eq!(
+            client.engines,
+            BTreeMap::from([("compute".to_owned(), 2000), ("gfx".to_owned(), 1000)])
+        );
+        assert_eq!(
+            client.capacities,
+            BTreeMap::from([("compute".to_owned(), 4)])
+        );
+
+        // Regular files and sockets have fdinfo too.
+        assert_eq!(parse_fdinfo("pos:\t0\nflags:\t02\nmnt_id:\t24\n"), None);
+        assert_eq!(parse_fdinfo("drm-driver:\ti915\n"), None);
+    }
+
+    #[test]
+    fn reads_each_client_once() {
+        let proc = fake_process();
+        // Two descriptors for client 7, e.g. after a dup, and one for
+        // client 8. Descriptors that aren't DRM clients are skipped.
+        write_fd(&proc, 3, &fdinfo(7, 1000, 0));
+        write_fd(&proc, 4, &fdinfo(7, 1000, 0));
+        write_fd(&proc, 5, &fdinfo(8, 500, 0));
+        write_fd(&proc, 6, "pos:\t0\nflags:\t02\n");
+        let snapshot = source(&proc, None).read_snapshot().unwrap();
+        assert_eq!(snapshot.clients.len(), 2);
+
+        let busy = engine_utilization(&DrmSnapshot::default(), &snapshot, Duration::from_micros(3));
+        assert_eq!(busy["gfx"], 50.0);
+    }
+
+    #[test]
+    fn divides_by_capacity() {
+        let previous = DrmSnapshot::from_clients([
+            parse_fdinfo(&fdinfo(7, 1_000_000, 0)).unwrap(),
+            parse_fdinfo(&fdinfo(8, 0, 1_000_000)).unwrap(),
+        ]);
+        let current = DrmSnapshot::from_clients([
+            parse_fdinfo(&fdinfo(7, 1_500_000, 2_000_000)).unwrap(),
+            parse_fdinfo(&fdinfo(8, 250_000, 2_000_000)).unwrap(),
+        ]);
+        let busy = engine_utilization(&previous, &current, Duration::from_millis(1));
+        // Client 7's compute time all counts, since it wasn't busy before.
+        assert_eq!(busy["gfx"], 75.0);
+        assert_eq!(busy["compute"], 75.0);
+        assert_eq!(total_utilization(&busy), 75.0);
+
+        // Clients that keep their fd but restart their counters don't go
+        // negative, and nothing goes past 100%.
+        let busy = engine_utilization(&current, &previous, Duration::from_micros(1));
+        assert_eq!(busy["gfx"], 0.0);
+        let busy = engine_utilization(&previous, &current, Duration::from_micros(1));
+        assert_eq!(busy["gfx"], 100.0);
+        assert!(engine_utilization(&previous, &current, Duration::ZERO)
+            .values()
+            .all(|busy| *busy == 0.0));
+    }
+
+    #[test]
+    fn reports_every_engine_and_the_total() {
+        let proc = fake_process();
+        write_fd(&proc, 3, &fdinfo(7, 0, 0));
+        let mut source = source(&proc, None);
+        assert_eq!(source.name(), "game");
+
+        source.start().unwrap();
+        let readings = source.sample().unwrap();
+        assert_eq!(series(&readings), ["compute", "gfx", "Total"]);
+        assert!(readings
+            .iter()
+            .all(|reading| reading.sample.status == SampleStatus::Valid));
+        let total = readings[2].sample.value.unwrap();
+        assert_eq!(
+            total,
+            readings[0]
+                .sample
+                .value
+                .unwrap()
+                .max(readings[1].sample.value.unwrap())
+        );
+
+        // A new engine shows up, and the client using the others goes
+        // away, so they drop to zero.
+        write_fd(
+            &proc,
+            3,
+            "drm-driver:\tamdgpu\ndrm-client-id:\t9\ndrm-engine-dec:\t0 ns\n",
+        );
+        let readings = source.sample().unwrap();
+        assert_eq!(series(&readings), ["compute", "gfx", "dec", "Total"]);
+        assert_eq!(readings[0].sample, Sample::valid(0.0));
+
+        proc.remove(&PROCESS_ID.to_string());
+        let readings = source.sample().unwrap();
+        assert_eq!(series(&readings), ["compute", "gfx", "dec", "Total"]);
+        assert!(readings
+            .iter()
+            .all(|reading| reading.sample == Sample::missing(SampleStatus::NoInstance)));
+    }
+
+    #[test]
+    fn reports_one_engine() {
+        let proc = fake_process();
+        write_fd(&proc, 3, &fdinfo(7, 0, 0));
+        let mut source = source(&proc, Some("video"));
+        source.start().unwrap();
+        assert_eq!(
+            source.sample().unwrap(),
+            [Reading::new("video", Sample::valid(0.0))]
+        );
+    }
+
+    #[test]
+    fn waits_for_a_baseline() {
+        let proc = fake_process();
+        write_fd(&proc, 3, &fdinfo(7, 0, 0));
+        let mut source = source(&proc, None);
+        assert_eq!(
+            source.sample().unwrap(),
+            [Reading::new(
+                "Total",
+                Sample::missing(SampleStatus::Invalid)
+            )]
+        );
+        assert_eq!(
+            series(&source.sample().unwrap()),
+            ["compute", "gfx", "Total"]
+        );
+    }
+}
@@ -0,0 +1,51 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// A /proc tree in a temporary directory, for sources that take a root.
+/// It's deleted when dropped.
+pub struct FakeProc {
+    root: PathBuf,
+}
+
+impl FakeProc {
+    pub fn new() -> Self {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let root = std::env::temp_dir().join(format!("chartfun-proc-{}-{}", process::id(), id));
+        fs::create_dir_all(&root).unwrap();
+        Self { root }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Writes `contents` to `path`, relative to the root, creating the
+    /// directories above it.
+    pub fn write(&self, path: &str, contents: &str) {
+        let path = self.root.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    /// Removes a file or a directory, e.g. a process that exited.
+    pub fn remove(&self, path: &str) {
+        let path = self.root.join(path);
+        if path.is_dir() {
+            fs::remove_dir_all(path).unwrap();
+        } else {
+            fs::remove_file(path).unwrap();
+        }
+    }
+}
+
+impl Drop for FakeProc {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
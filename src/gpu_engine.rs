@@ -0,0 +1,373 @@
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+use crate::{args::Grouping, counter_path::CounterPath, metric::Sample};
+
+/// Identifies a GPU adapter. Matches the layout of the Win32 LUID, which
+/// PDH prints high part first.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Luid {
+    pub high: u32,
+    pub low: u32,
+}
+
+impl fmt::Display for Luid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "0x{:08X}_0x{:08X}", self.high, self.low)
+    }
+}
+
+/// The path of the utilization counters of every engine `process_id`
+/// uses, as a wildcard.
+pub fn process_engines_path(process_id: u32) -> CounterPath {
+    // The trailing underscore keeps pid 12 from matching pid 123.
+    CounterPath::new("GPU Engine", "Utilization Percentage")
+        .with_instance(&format!("pid_{}_*", process_id))
+}
+
+/// The instance name of a "GPU Engine" counter, e.g.
+/// `pid_1234_luid_0x00000000_0x0000D1E5_phys_0_eng_3_engtype_3D`.
+/// Formatting an instance gives back the name it was parsed from, as long
+/// as the LUID was written the way PDH writes it (8 upper case hex digits).
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct GpuEngineInstance {
+    pub pid: u32,
+    /// The adapter the engine belongs to.
+    pub luid: Luid,
+    /// The physical adapter index within a linked adapter.
+    pub phys: u32,
+    /// The engine index within the adapter.
+    pub eng: u32,
+    /// The engine type, e.g. `3D`, `Copy` or `VideoDecode`. Some drivers
+    /// use spaces (`GDI Render`) and some leave it empty.
+    pub engtype: String,
+}
+
+impl GpuEngineInstance {
+    /// The engine type to show to the user.
+    pub fn engine_type_name(&self) -> &str {
+        if self.engtype.is_empty() {
+            "Other"
+        } else {
+            &self.engtype
+        }
+    }
+}
+
+impl fmt::Display for GpuEngineInstance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "pid_{}_luid_{}_phys_{}_eng_{}_engtype_{}",
+            self.pid, self.luid, self.phys, self.eng, self.engtype
+        )
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseInstanceError(String);
+
+impl fmt::Display for ParseInstanceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unexpected GPU Engine instance name: {}", self.0)
+    }
+}
+
+impl std::error::Error for ParseInstanceError {}
+
+impl FromStr for GpuEngineInstance {
+    type Err = ParseInstanceError;
+
+    fn from_str(instance: &str) -> Result<Self, Self::Err> {
+        parse_instance(instance).ok_or_else(|| ParseInstanceError(instance.to_owned()))
+    }
+}
+
+fn parse_instance(instance: &str) -> Option<GpuEngineInstance> {
+    // The engine type is last since it's the only field that can contain
+    // underscores or spaces.
+    let rest = instance.strip_prefix("pid_")?;
+    let (pid, rest) = rest.split_once("_luid_")?;
+    let (high, rest) = rest.split_once('_')?;
+    let (low, rest) = rest.split_once("_phys_")?;
+    let (phys, rest) = rest.split_once("_eng_")?;
+    let (eng, engtype) = rest.split_once("_engtype_")?;
+
+    Some(GpuEngineInstance {
+        pid: pid.parse().ok()?,
+        luid: Luid {
+            high: parse_hex(high)?,
+            low: parse_hex(low)?,
+        },
+        phys: phys.parse().ok()?,
+        eng: eng.parse().ok()?,
+        engtype: engtype.to_owned(),
+    })
+}
+
+fn parse_hex(value: &str) -> Option<u32> {
+    let digits = value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))?;
+    u32::from_str_radix(digits, 16).ok()
+}
+
+/// Splits per-engine utilization into named series, ordered by engine
+/// type or by adapter. Engines of the same type are summed. An adapter is
+/// as busy as its busiest engine type, which is how Task Manager reports
+/// it. Adapters missing from `adapter_names` are named by their LUID, as
+/// are adapters that share a name (e.g. two of the same card). A group is
+/// only as valid as its worst sample.
+pub fn group_utilization<'a, I: IntoIterator<Item = (&'a GpuEngineInstance, Sample)>>(
+    samples: I,
+    grouping: Grouping,
+    adapter_names: &BTreeMap<Luid, String>,
+) -> Vec<(String, Sample)> {
+    match grouping {
+        Grouping::EngineType => {
+            let mut engine_types: BTreeMap<&str, Sample> = BTreeMap::new();
+            for (instance, sample) in samples {
+                add_sample(&mut engine_types, instance.engine_type_name(), sample);
+            }
+            engine_types
+                .into_iter()
+                .map(|(engine_type, sample)| (engine_type.to_owned(), sample))
+                .collect()
+        }
+        Grouping::Adapter => {
+            let mut adapters: BTreeMap<Luid, BTreeMap<&str, Sample>> = BTreeMap::new();
+            for (instance, sample) in samples {
+                let engine_types = adapters.entry(instance.luid).or_default();
+                add_sample(engine_types, instance.engine_type_name(), sample);
+            }
+            // Names are told apart among every adapter rather than the
+            // ones in use, so a series keeps its name as others come and go.
+            let mut name_counts: BTreeMap<&str, usize> = BTreeMap::new();
+            for name in adapter_names.values() {
+                *name_counts.entry(name).or_default() += 1;
+            }
+            adapters
+                .into_iter()
+                .map(|(luid, engine_types)| {
+                    let name = match adapter_names.get(&luid) {
+                        Some(name) if name_counts[name.as_str()] > 1 => {
+                            format!("{} ({})", name, luid)
+                        }
+                        Some(name) => name.clone(),
+                        None => format!("Adapter {}", luid),
+                    };
+                    // Every adapter in the map has at least one engine.
+                    let sample = engine_types
+                        .into_values()
+                        .reduce(|a, b| a.combine(b, f64::max))
+                        .unwrap();
+                    (name, sample)
+                })
+                .collect()
+        }
+    }
+}
+
+/// Sums `sample` into the group for `key`.
+fn add_sample<'a>(groups: &mut BTreeMap<&'a str, Sample>, key: &'a str, sample: Sample) {
+    groups
+        .entry(key)
+        .and_modify(|group| *group = group.combine(sample, |a, b| a + b))
+        .or_insert(sample);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::metric::SampleStatus;
+
+    use super::*;
+
+    const INTEGRATED: Luid = Luid {
+        high: 0,
+        low: 0xD1E5,
+    };
+    const DISCRETE: Luid = Luid {
+        high: 0x1,
+        low: 0xABCDEF01,
+    };
+
+    fn instance(name: &str) -> GpuEngineInstance {
+        name.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_instance_names() {
+        assert_eq!(
+            instance("pid_1234_luid_0x00000000_0x0000D1E5_phys_0_eng_3_engtype_3D"),
+            GpuEngineInstance {
+                pid: 1234,
+                luid: INTEGRATED,
+                phys: 0,
+                eng: 3,
+                engtype: "3D".to_owned(),
+            }
+        );
+        let instance =
+            instance("pid_4_luid_0x00000001_0xABCDEF01_phys_1_eng_12_engtype_GDI Render");
+        assert_eq!(instance.luid, DISCRETE);
+        assert_eq!(instance.phys, 1);
+        assert_eq!(instance.eng, 12);
+        assert_eq!(instance.engine_type_name(), "GDI Render");
+        // Some drivers don't name their engines.
+        let instance = self::instance("pid_4_luid_0x00000000_0x0000D1E5_phys_0_eng_7_engtype_");
+        assert_eq!(instance.engine_type_name(), "Other");
+        let instance =
+            self::instance("pid_4_luid_0x00000000_0x0000D1E5_phys_0_eng_7_engtype_Video_Codec");
+        assert_eq!(instance.engtype, "Video_Codec");
+    }
+
+    #[test]
+    fn formats_instance_names() {
+        for name in [
+            "pid_1234_luid_0x00000000_0x0000D1E5_phys_0_eng_3_engtype_3D",
+            "pid_4_luid_0x00000001_0xABCDEF01_phys_1_eng_12_engtype_GDI Render",
+            "pid_4_luid_0x00000000_0x0000D1E5_phys_0_eng_7_engtype_",
+        ] {
+            assert_eq!(instance(name).to_string(), name);
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_instance_names() {
+        for name in [
+            "",
+            "_Total",
+            "pid_1234",
+            "pid__luid_0x00000000_0x0000D1E5_phys_0_eng_3_engtype_3D",
+            "pid_-1_luid_0x00000000_0x0000D1E5_phys_0_eng_3_engtype_3D",
+            "pid_1234_luid_00000000_0x0000D1E5_phys_0_eng_3_engtype_3D",
+            "pid_1234_luid_0x00000000_0xD1E5Z_phys_0_eng_3_engtype_3D",
+            "pid_1234_luid_0x100000000_0x0000D1E5_phys_0_eng_3_engtype_3D",
+            "pid_1234_luid_0x00000000_0x0000D1E5_phys_x_eng_3_engtype_3D",
+            "pid_1234_luid_0x00000000_0x0000D1E5_phys_0_eng_3",
+            "pid_1234_luid_0x00000000_0x0000D1E5_eng_3_engtype_3D",
+        ] {
+            assert!(name.parse::<GpuEngineInstance>().is_err(), "{}", name);
+        }
+        assert_eq!(
+            "_Total"
+                .parse::<GpuEngineInstance>()
+                .unwrap_err()
+                .to_string(),
+            "Unexpected GPU Engine instance name: _Total"
+        );
+    }
+
+    /// Two adapters, with two 3D engines on the integrated one.
+    fn samples() -> Vec<(GpuEngineInstance, Sample)> {
+        [
+            (
+                "pid_1_luid_0x00000000_0x0000D1E5_phys_0_eng_0_engtype_3D",
+                Sample::valid(20.0),
+            ),
+            (
+                "pid_1_luid_0x00000000_0x0000D1E5_phys_0_eng_1_engtype_3D",
+                Sample::valid(15.0),
+            ),
+            (
+                "pid_1_luid_0x00000000_0x0000D1E5_phys_0_eng_2_engtype_Copy",
+                Sample::valid(50.0),
+            ),
+            (
+                "pid_1_luid_0x00000001_0xABCDEF01_phys_0_eng_0_engtype_3D",
+                Sample::valid(10.0),
+            ),
+            (
+                "pid_1_luid_0x00000001_0xABCDEF01_phys_0_eng_5_engtype_",
+                Sample::missing(SampleStatus::Invalid),
+            ),
+        ]
+        .into_iter()
+        .map(|(name, sample)| (instance(name), sample))
+        .collect()
+    }
+
+    fn group(grouping: Grouping, adapter_names: &BTreeMap<Luid, String>) -> Vec<(String, Sample)> {
+        let samples = samples();
+        group_utilization(
+            samples.iter().map(|(instance, sample)| (instance, *sample)),
+            grouping,
+            adapter_names,
+        )
+    }
+
+    #[test]
+    fn sums_engine_types_across_adapters() {
+        assert_eq!(
+            group(Grouping::EngineType, &BTreeMap::new()),
+            [
+                ("3D".to_owned(), Sample::valid(45.0)),
+                ("Copy".to_owned(), Sample::valid(50.0)),
+                ("Other".to_owned(), Sample::missing(SampleStatus::Invalid)),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_adapters_by_their_busiest_engine_type() {
+        let adapter_names = BTreeMap::from([(DISCRETE, "Radeon RX 7900".to_owned())]);
+        assert_eq!(
+            group(Grouping::Adapter, &adapter_names),
+            [
+                // The two 3D engines add up to 35%, less than Copy.
+                (
+                    "Adapter 0x00000000_0x0000D1E5".to_owned(),
+                    Sample::valid(50.0)
+                ),
+                (
+                    "Radeon RX 7900".to_owned(),
+                    Sample {
+                        value: Some(10.0),
+                        status: SampleStatus::Invalid,
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn tells_apart_adapters_with_the_same_name() {
+        let adapter_names = BTreeMap::from([
+            (INTEGRATED, "GeForce RTX 4090".to_owned()),
+            (DISCRETE, "GeForce RTX 4090".to_owned()),
+        ]);
+        let names = |samples: &[(GpuEngineInstance, Sample)]| -> Vec<String> {
+            group_utilization(
+                samples.iter().map(|(instance, sample)| (instance, *sample)),
+                Grouping::Adapter,
+                &adapter_names,
+            )
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect()
+        };
+        assert_eq!(
+            names(&samples()),
+            [
+                "GeForce RTX 4090 (0x00000000_0x0000D1E5)",
+                "GeForce RTX 4090 (0x00000001_0xABCDEF01)",
+            ]
+        );
+        // Even while only one of them is busy.
+        let discrete_only: Vec<_> = samples()
+            .into_iter()
+            .filter(|(instance, _)| instance.luid == DISCRETE)
+            .collect();
+        assert_eq!(
+            names(&discrete_only),
+            ["GeForce RTX 4090 (0x00000001_0xABCDEF01)"]
+        );
+    }
+
+    #[test]
+    fn groups_nothing() {
+        assert_eq!(
+            group_utilization([], Grouping::Adapter, &BTreeMap::new()),
+            []
+        );
+    }
+}
@@ -0,0 +1,59 @@
+use std::thread;
+
+use crate::{
+    args::Args,
+    chart_model::{ChartModel, DEFAULT_HEIGHT, DEFAULT_WIDTH},
+    metric::{self, tick_interval, MetricSource},
+    scene::{axis_labels, compose_frame, legend_entries, value_label, Labels},
+    software_renderer::{self, Canvas},
+    svg::{self, write_svg},
+};
+
+/// Samples `source` without a window and writes the resulting chart to the
+/// requested PNG and/or SVG files once `args.samples` points have been
+/// collected, or enough to fill the window.
+pub fn run(mut source: Box<dyn MetricSource>, args: &Args) -> metric::Result<()> {
+    let scale = args.scale;
+    let mut chart = ChartModel::new(DEFAULT_WIDTH as f32 * scale, DEFAULT_HEIGHT as f32 * scale);
+
+    source.start()?;
+    let unit = source.unit();
+    let options = args.chart_options(source.as_ref())?;
+    chart.set_options(&options);
+    let samples = args.samples.unwrap_or_else(|| chart.point_count());
+    let mut value = unit.format_value(0.0);
+    let tick_interval = tick_interval(source.as_ref(), options.time_scale.interval);
+    for _ in 0..samples {
+        if source.is_finished() {
+            break;
+        }
+        thread::sleep(tick_interval);
+        let readings = source.sample()?;
+        chart.add_readings(&readings);
+        value = value_label(unit, &readings);
+    }
+    let labels = Labels {
+        title: source.name().to_owned(),
+        value,
+        legend: legend_entries(&chart, unit),
+        axis: axis_labels(&chart, unit),
+    };
+    source.close()?;
+
+    // The legend is laid out with each output's own text metrics.
+    let display_list = chart.display_list();
+    if let Some(path) = &args.png {
+        let frame = compose_frame(
+            &display_list,
+            &labels,
+            scale,
+            software_renderer::measure_text,
+        );
+        Canvas::render(&frame).save_png(path)?;
+    }
+    if let Some(path) = &args.svg {
+        let frame = compose_frame(&display_list, &labels, scale, svg::measure_text);
+        write_svg(&frame, path)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,140 @@
+use windows::{
+    core::Result,
+    Foundation::Numerics::{Vector2, Vector3},
+    UI::{
+        Color,
+        Composition::{CompositionColorBrush, ContainerVisual, SpriteVisual},
+    },
+};
+
+use crate::{
+    chart_model,
+    renderer::Renderer,
+    scene::{layout_legend, LegendEntry},
+    text_block::TextBlock,
+};
+
+struct LegendItem {
+    swatch: SpriteVisual,
+    swatch_brush: CompositionColorBrush,
+    text: TextBlock,
+}
+
+/// A row of color swatches and labels, one per series, wrapped to the
+/// width of the chart.
+pub struct Legend {
+    root: ContainerVisual,
+    items: Vec<LegendItem>,
+    dpi: u32,
+    max_width: f32,
+    height: f32,
+}
+
+impl Legend {
+    pub fn new(renderer: &Renderer, dpi: u32, max_width: f32) -> Result<Self> {
+        let root = renderer.compositor.CreateContainerVisual()?;
+        Ok(Self {
+            root,
+            items: Vec::new(),
+            dpi,
+            max_width,
+            height: 0.0,
+        })
+    }
+
+    pub fn root(&self) -> &ContainerVisual {
+        &self.root
+    }
+
+    pub fn height(&self) -> f32 {
+        self.height
+    }
+
+    pub fn update(&mut self, renderer: &Renderer, entries: &[LegendEntry]) -> Result<()> {
+        let children = self.root.Children()?;
+        while self.items.len() > entries.len() {
+            let item = self.items.pop().unwrap();
+            children.Remove(&item.swatch)?;
+            children.Remove(item.text.root())?;
+        }
+        for (i, entry) in entries.iter().enumerate() {
+            if let Some(item) = self.items.get_mut(i) {
+                item.swatch_brush.SetColor(to_ui_color(entry.color))?;
+                item.text.set_text(renderer, entry.text())?;
+                continue;
+            }
+
+            let swatch_brush = renderer
+                .compositor
+                .CreateColorBrushWithColor(to_ui_color(entry.color))?;
+            let swatch = renderer.compositor.CreateSpriteVisual()?;
+            swatch.SetBrush(&swatch_brush)?;
+            let text = TextBlock::new(
+                renderer,
+                entry.text(),
+                Color {
+                    A: 255,
+                    R: 0,
+                    G: 0,
+                    B: 0,
+                },
+                self.dpi,
+            )?;
+            children.InsertAtTop(&swatch)?;
+            children.InsertAtTop(text.root())?;
+            self.items.push(LegendItem {
+                swatch,
+                swatch_brush,
+                text,
+            });
+        }
+        self.layout()
+    }
+
+    pub fn set_dpi(&mut self, renderer: &Renderer, dpi: u32, max_width: f32) -> Result<()> {
+        self.dpi = dpi;
+        self.max_width = max_width;
+        for item in &mut self.items {
+            item.text.set_dpi(renderer, dpi)?;
+        }
+        self.layout()
+    }
+
+    fn layout(&mut self) -> Result<()> {
+        let mut text_widths = Vec::with_capacity(self.items.len());
+        let mut line_height: f32 = 0.0;
+        for item in &self.items {
+            let size = item.text.root().Size()?;
+            text_widths.push(size.X);
+            line_height = line_height.max(size.Y);
+        }
+
+        let scale = self.dpi as f32 / 96.0;
+        let (slots, height) = layout_legend(&text_widths, self.max_width, line_height, scale);
+        for (item, slot) in self.items.iter().zip(slots) {
+            let swatch = slot.swatch;
+            item.swatch
+                .SetOffset(Vector3::new(swatch.left, swatch.top, 0.0))?;
+            item.swatch.SetSize(Vector2::new(
+                swatch.right - swatch.left,
+                swatch.bottom - swatch.top,
+            ))?;
+            item.text
+                .root()
+                .SetOffset(Vector3::new(slot.text.x, slot.text.y, 0.0))?;
+        }
+        self.height = height;
+        self.root.SetSize(Vector2::new(self.max_width, height))?;
+        Ok(())
+    }
+}
+
+fn to_ui_color(color: chart_model::Color) -> Color {
+    let channel = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+    Color {
+        A: channel(color.a),
+        R: channel(color.r),
+        G: channel(color.g),
+        B: channel(color.b),
+    }
+}
@@ -1,18 +1,71 @@
 #![windows_subsystem = "windows"]
 
+#[cfg(windows)]
 mod app;
+mod args;
+mod axis;
+mod bitmap_font;
+#[cfg(windows)]
 mod chart;
+#[cfg(windows)]
+mod chart_labels;
+mod chart_model;
+mod counter_path;
+#[cfg(any(windows, test))]
+mod counter_set;
+#[cfg(windows)]
+mod counter_tracker;
+#[cfg(target_os = "linux")]
+mod drm_fdinfo;
+#[cfg(all(target_os = "linux", test))]
+mod fake_proc;
+#[cfg(any(windows, test))]
+mod gpu_engine;
+mod headless;
+#[cfg(windows)]
+mod legend;
+mod metric;
+#[cfg(windows)]
 mod pdh;
+#[cfg(any(windows, test))]
+mod pdh_error;
+#[cfg(windows)]
 mod perf;
+mod perfmon_log;
 mod pid;
+mod playback;
+#[cfg(target_os = "linux")]
+mod proc_cpu;
+mod recording;
+mod recording_file;
+#[cfg(windows)]
 mod renderer;
+mod replay;
+mod scene;
+mod software_renderer;
+mod svg;
+#[cfg(windows)]
 mod text_block;
+mod trace_export;
+#[cfg(not(windows))]
+mod tui;
+#[cfg(windows)]
 mod window;
+#[cfg(windows)]
 mod windows_utils;
 
+#[cfg(windows)]
 use app::App;
-use pid::{get_current_dwm_pid, parse_pid};
+use args::{parse_args, SourceKind};
+#[cfg(windows)]
+use counter_tracker::CounterTracker;
+#[cfg(windows)]
+use perf::PerfTracker;
+#[cfg(windows)]
+use pid::get_current_dwm_pid;
+#[cfg(windows)]
 use window::Window;
+#[cfg(windows)]
 use windows::{
     core::{w, Result, HSTRING},
     Win32::{
@@ -26,28 +79,35 @@ use windows::{
         },
     },
 };
+#[cfg(windows)]
 use windows_utils::{
     composition::CompositionInterop,
     dispatcher_queue::{
         create_dispatcher_queue_controller_for_current_thread,
         shutdown_dispatcher_queue_controller_and_wait,
     },
+    error::to_windows_error,
 };
 
+#[cfg(windows)]
 fn run() -> Result<()> {
-    let args: Vec<_> = std::env::args().skip(1).collect();
-    let pid = if let Some(pid_string) = args.get(0) {
-        if let Ok(pid) = parse_pid(&pid_string) {
-            Some(pid)
-        } else {
-            return Err(windows::core::Error::new(
-                E_FAIL,
-                "Failed to parse process id!",
-            ));
-        }
-    } else {
-        None
-    };
+    let args = parse_args(std::env::args().skip(1))
+        .map_err(|message| windows::core::Error::new(E_FAIL, message))?;
+    if args.source == Some(SourceKind::Cpu) {
+        return Err(windows::core::Error::new(
+            E_FAIL,
+            "The CPU source is only available on Linux!",
+        ));
+    }
+
+    if args.is_conversion() {
+        let source = open_source(&args).map_err(to_windows_error)?;
+        return recording::convert(source).map_err(to_windows_error);
+    }
+    if args.is_headless() {
+        let source = open_source(&args).map_err(to_windows_error)?;
+        return headless::run(source, &args).map_err(to_windows_error);
+    }
 
     unsafe {
         SetProcessDpiAwarenessContext(DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2)?;
@@ -60,13 +120,11 @@ fn run() -> Result<()> {
     let mut window = Window::new("chartfun", window_width, window_height)?;
     let dpi = window.dpi();
 
-    let process_id = if let Some(pid) = pid {
-        pid
-    } else {
-        get_current_dwm_pid()?
-    };
-
-    let app = App::new(process_id, dpi)?;
+    let source = open_source(&args).map_err(to_windows_error)?;
+    let options = args
+        .chart_options(source.as_ref())
+        .map_err(|message| windows::core::Error::new(E_FAIL, message))?;
+    let app = App::new(source, dpi, &options)?;
     let root = app.root().clone();
     let compositor = app.compositor().clone();
 
@@ -87,6 +145,25 @@ fn run() -> Result<()> {
     Ok(())
 }
 
+#[cfg(windows)]
+fn create_source(args: &args::Args) -> Result<Box<dyn metric::MetricSource>> {
+    if !args.counters.is_empty() {
+        return Ok(Box::new(CounterTracker::new(&args.counters)?));
+    }
+    let process_ids = if args.process_ids.is_empty() {
+        vec![get_current_dwm_pid()?]
+    } else {
+        args.process_ids.clone()
+    };
+    let mut sources: Vec<Box<dyn metric::MetricSource>> = Vec::new();
+    for process_id in process_ids {
+        let source = PerfTracker::new(process_id, args.engine.as_deref(), args.grouping)?;
+        sources.push(Box::new(source));
+    }
+    metric::combine_sources(sources).map_err(to_windows_error)
+}
+
+#[cfg(windows)]
 fn main() -> Result<()> {
     if let Err(error) = run() {
         let message = HSTRING::from(&format!("0x{:08X} - {}", error.code().0, error.message()));
@@ -98,3 +175,72 @@ fn main() -> Result<()> {
         Ok(())
     }
 }
+
+#[cfg(not(windows))]
+fn run() -> metric::Result<()> {
+    let args = parse_args(std::env::args().skip(1))?;
+    let source = open_source(&args)?;
+    if args.is_conversion() {
+        recording::convert(source)
+    } else if args.is_headless() {
+        headless::run(source, &args)
+    } else {
+        let options = args.chart_options(source.as_ref())?;
+        tui::run(source, &options)
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn create_source(args: &args::Args) -> metric::Result<Box<dyn metric::MetricSource>> {
+    if args.process_ids.is_empty() {
+        return Err("A process id is required on this platform!".into());
+    }
+    if args.grouping != args::Grouping::default() {
+        return Err("Grouping GPU engines is only available on Windows!".into());
+    }
+    if !args.counters.is_empty() {
+        return Err("Performance counters are only available on Windows!".into());
+    }
+    let mut sources: Vec<Box<dyn metric::MetricSource>> = Vec::new();
+    for &process_id in &args.process_ids {
+        let source: Box<dyn metric::MetricSource> = match args.source {
+            Some(SourceKind::Gpu) => Box::new(drm_fdinfo::DrmGpuSource::new(
+                process_id,
+                args.engine.clone(),
+            )?),
+            Some(SourceKind::Cpu) | None => Box::new(proc_cpu::ProcCpuSource::new(process_id)?),
+        };
+        sources.push(source);
+    }
+    metric::combine_sources(sources)
+}
+
+#[cfg(not(any(windows, target_os = "linux")))]
+fn create_source(_args: &args::Args) -> metric::Result<Box<dyn metric::MetricSource>> {
+    Err("No metric sources are available on this platform yet!".into())
+}
+
+/// The live source asked for, or a playback of `--replay`. Every sample
+/// also gets recorded to the file given with `--record`, if any.
+fn open_source(args: &args::Args) -> metric::Result<Box<dyn metric::MetricSource>> {
+    let source: Box<dyn metric::MetricSource> = match &args.replay {
+        Some(path) => replay::open(path, args.speed)?,
+        None => create_source(args)?,
+    };
+    match &args.record {
+        Some(path) => {
+            let interval = args.chart_options(source.as_ref())?.time_scale.interval;
+            let source = recording::RecordingSource::new(source, path, interval)?;
+            Ok(Box::new(source))
+        }
+        None => Ok(source),
+    }
+}
+
+#[cfg(not(windows))]
+fn main() {
+    if let Err(error) = run() {
+        eprintln!("chartfun: {}", error);
+        std::process::exit(1);
+    }
+}
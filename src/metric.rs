@@ -0,0 +1,344 @@
+use std::time::Duration;
+
+pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Unit {
+    Percent,
+    /// A plain number, e.g. bytes or operations per second.
+    #[cfg_attr(not(windows), allow(dead_code))]
+    Count,
+}
+
+impl Unit {
+    pub fn format_value(&self, value: f64) -> String {
+        match self {
+            Unit::Percent => format!("{}%", value as i32),
+            Unit::Count => format_count(value),
+        }
+    }
+}
+
+/// Shortens large numbers with a metric suffix, e.g. `12.3M`.
+fn format_count(value: f64) -> String {
+    const SUFFIXES: [(f64, &str); 4] = [(1e12, "T"), (1e9, "G"), (1e6, "M"), (1e3, "K")];
+    for (scale, suffix) in SUFFIXES {
+        if value.abs() >= scale {
+            return format!("{:.1}{}", value / scale, suffix);
+        }
+    }
+    if value.fract() == 0.0 {
+        format!("{}", value)
+    } else {
+        format!("{:.2}", value)
+    }
+}
+
+/// How trustworthy a sample is, from best to worst.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SampleStatus {
+    Valid,
+    /// No new value was available, so the last one was repeated.
+    #[cfg_attr(not(windows), allow(dead_code))]
+    Stale,
+    /// The source returned something that isn't a usable value, e.g. a
+    /// rate counter that hasn't been sampled twice yet.
+    Invalid,
+    /// The thing being measured went away, e.g. the process exited.
+    NoInstance,
+}
+
+/// A value along with its status. Samples without a value are drawn as
+/// gaps.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Sample {
+    pub value: Option<f64>,
+    pub status: SampleStatus,
+}
+
+impl Sample {
+    pub fn valid(value: f64) -> Self {
+        Self {
+            value: Some(value),
+            status: SampleStatus::Valid,
+        }
+    }
+
+    pub fn missing(status: SampleStatus) -> Self {
+        Self {
+            value: None,
+            status,
+        }
+    }
+
+    /// Merges two samples of the same series, e.g. two engines of the same
+    /// type. The result has a value if either one does, and the worse of
+    /// the two statuses.
+    #[cfg_attr(not(windows), allow(dead_code))]
+    pub fn combine(self, other: Sample, merge: fn(f64, f64) -> f64) -> Self {
+        let value = match (self.value, other.value) {
+            (Some(a), Some(b)) => Some(merge(a, b)),
+            (a, b) => a.or(b),
+        };
+        Self {
+            value,
+            status: self.status.max(other.status),
+        }
+    }
+}
+
+/// One sample of one series, as returned by a single tick.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Reading {
+    pub series: String,
+    pub sample: Sample,
+}
+
+impl Reading {
+    pub fn new<S: Into<String>>(series: S, sample: Sample) -> Self {
+        Self {
+            series: series.into(),
+            sample,
+        }
+    }
+}
+
+/// The value shown next to the title: the largest reading, the same way
+/// Task Manager reports the busiest GPU engine as the GPU's usage.
+pub fn headline_value(readings: &[Reading]) -> Option<f64> {
+    readings
+        .iter()
+        .filter_map(|reading| reading.sample.value)
+        .reduce(f64::max)
+}
+
+/// Whether any series in a tick is missing or out of date.
+pub fn is_partial(readings: &[Reading]) -> bool {
+    readings
+        .iter()
+        .any(|reading| reading.sample.status != SampleStatus::Valid)
+}
+
+/// A process a source measures.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProcessInfo {
+    pub process_id: u32,
+    pub name: String,
+}
+
+/// Where a series comes from: the name of its source and the process it
+/// measures, if any.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SeriesOrigin {
+    pub source: String,
+    pub process: Option<ProcessInfo>,
+}
+
+/// How fast to play back samples that were taken earlier.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PlaybackSpeed {
+    /// A multiple of the speed they were taken at, e.g. 1.0 for real time.
+    Multiplier(f64),
+    /// As fast as they can be charted.
+    Instant,
+}
+
+/// The pace of a source that plays back samples taken earlier.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Playback {
+    /// How far apart the samples were taken.
+    pub interval: Duration,
+    pub speed: PlaybackSpeed,
+    /// How many samples there are to play.
+    pub length: u64,
+}
+
+impl Playback {
+    /// How long to wait between samples.
+    pub fn tick_interval(&self) -> Duration {
+        match self.speed {
+            PlaybackSpeed::Multiplier(multiplier) => self.interval.div_f64(multiplier),
+            PlaybackSpeed::Instant => Duration::ZERO,
+        }
+    }
+}
+
+/// How long to wait between samples of `source`, which is charted
+/// `sample_interval` apart.
+pub fn tick_interval(source: &dyn MetricSource, sample_interval: Duration) -> Duration {
+    match source.playback() {
+        Some(playback) => playback.tick_interval(),
+        None => sample_interval,
+    }
+}
+
+/// Something that can be sampled once per tick and charted.
+pub trait MetricSource {
+    /// Name displayed in the header above the chart.
+    fn name(&self) -> &str;
+    fn unit(&self) -> Unit;
+    /// Called once before the first tick. Sources that compute rates
+    /// should take their baseline sample here.
+    fn start(&mut self) -> Result<()>;
+    /// Returns one reading per series. Sources may add series over time.
+    fn sample(&mut self) -> Result<Vec<Reading>>;
+    fn close(self: Box<Self>) -> Result<()>;
+
+    /// The process being measured, for sources that measure one.
+    fn process(&self) -> Option<ProcessInfo> {
+        None
+    }
+
+    /// Where `series` comes from, for recordings.
+    fn series_origin(&self, _series: &str) -> SeriesOrigin {
+        SeriesOrigin {
+            source: self.name().to_owned(),
+            process: self.process(),
+        }
+    }
+
+    /// Sources that play back earlier samples pace themselves. Live ones
+    /// return `None` and are sampled at the chart's interval.
+    fn playback(&self) -> Option<Playback> {
+        None
+    }
+
+    /// Whether there is nothing left to sample, e.g. a playback reached
+    /// the end of its recording. Live sources never finish.
+    fn is_finished(&self) -> bool {
+        false
+    }
+
+    /// Makes `position` the next sample to play, for playbacks. Sources
+    /// that can't go back in time fail.
+    fn seek(&mut self, _position: u64) -> Result<()> {
+        Err(format!("Can't seek in {}!", self.name()).into())
+    }
+
+    /// Something wrong that doesn't stop sampling, e.g. counters that are
+    /// charted less often than asked for, to show next to the title.
+    fn warning(&self) -> Option<String> {
+        None
+    }
+}
+
+/// Returns the only source as is, or several combined into one.
+pub fn combine_sources(mut sources: Vec<Box<dyn MetricSource>>) -> Result<Box<dyn MetricSource>> {
+    if sources.len() == 1 {
+        Ok(sources.pop().unwrap())
+    } else {
+        Ok(Box::new(CombinedSource::new(sources)?))
+    }
+}
+
+/// Charts several sources side by side, e.g. DWM and a game. Every series
+/// is prefixed with the name of its source.
+pub struct CombinedSource {
+    name: String,
+    unit: Unit,
+    sources: Vec<(String, Box<dyn MetricSource>)>,
+}
+
+impl CombinedSource {
+    /// The sources have to share a unit, since they share an axis.
+    pub fn new(sources: Vec<Box<dyn MetricSource>>) -> Result<Self> {
+        let unit = match sources.first() {
+            Some(source) => source.unit(),
+            None => return Err("There's nothing to chart!".into()),
+        };
+        if sources.iter().any(|source| source.unit() != unit) {
+            return Err("Only sources with the same unit can be charted together!".into());
+        }
+
+        // Two instances of the same program get told apart the way PDH
+        // does, e.g. `game.exe` and `game.exe#1`.
+        let mut named_sources: Vec<(String, Box<dyn MetricSource>)> = Vec::new();
+        for source in sources {
+            let base_name = source.name().to_owned();
+            let mut name = base_name.clone();
+            let mut index = 0;
+            while named_sources.iter().any(|(other, _)| *other == name) {
+                index += 1;
+                name = format!("{}#{}", base_name, index);
+            }
+            named_sources.push((name, source));
+        }
+        let name = named_sources
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        Ok(Self {
+            name,
+            unit,
+            sources: named_sources,
+        })
+    }
+}
+
+impl MetricSource for CombinedSource {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn unit(&self) -> Unit {
+        self.unit
+    }
+
+    fn start(&mut self) -> Result<()> {
+        for (_, source) in &mut self.sources {
+            source.start()?;
+        }
+        Ok(())
+    }
+
+    fn sample(&mut self) -> Result<Vec<Reading>> {
+        let mut readings = Vec::new();
+        for (name, source) in &mut self.sources {
+            for reading in source.sample()? {
+                let series = format!("{} {}", name, reading.series);
+                readings.push(Reading::new(series, reading.sample));
+            }
+        }
+        Ok(readings)
+    }
+
+    fn series_origin(&self, series: &str) -> SeriesOrigin {
+        self.sources
+            .iter()
+            .find_map(|(name, source)| {
+                let series = series.strip_prefix(name.as_str())?.strip_prefix(' ')?;
+                Some(source.series_origin(series))
+            })
+            .unwrap_or_else(|| SeriesOrigin {
+                source: self.name.clone(),
+                process: None,
+            })
+    }
+
+    fn is_finished(&self) -> bool {
+        self.sources.iter().all(|(_, source)| source.is_finished())
+    }
+
+    fn warning(&self) -> Option<String> {
+        let warnings: Vec<String> = self
+            .sources
+            .iter()
+            .filter_map(|(name, source)| Some(format!("{}: {}", name, source.warning()?)))
+            .collect();
+        (!warnings.is_empty()).then(|| warnings.join("; "))
+    }
+
+    fn close(self: Box<Self>) -> Result<()> {
+        // Close everything even if one of them fails.
+        let mut result = Ok(());
+        for (_, source) in self.sources {
+            let closed = source.close();
+            if result.is_ok() {
+                result = closed;
+            }
+        }
+        result
+    }
+}
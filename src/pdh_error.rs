@@ -0,0 +1,331 @@
+use std::fmt;
+
+use crate::metric::SampleStatus;
+
+macro_rules! pdh_errors {
+    ($($variant:ident = $code:literal, $name:literal, $description:literal;)*) => {
+        /// A PDH status code, as returned by the PDH functions and in the
+        /// CStatus of counter values. Codes that aren't in the table are
+        /// kept as `Unknown`, so `from_code(code).code() == code` holds for
+        /// every code.
+        #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+        pub enum PdhError {
+            $($variant,)*
+            Unknown(u32),
+        }
+
+        impl PdhError {
+            /// Only returns `Unknown` for codes that aren't in the table.
+            pub fn from_code(code: u32) -> Self {
+                match code {
+                    $($code => Self::$variant,)*
+                    code => Self::Unknown(code),
+                }
+            }
+
+            pub fn code(self) -> u32 {
+                match self {
+                    $(Self::$variant => $code,)*
+                    Self::Unknown(code) => code,
+                }
+            }
+
+            /// The name of the constant in pdhmsg.h, e.g. `PDH_NO_DATA`.
+            pub fn name(self) -> Option<&'static str> {
+                match self {
+                    $(Self::$variant => Some($name),)*
+                    Self::Unknown(_) => None,
+                }
+            }
+
+            pub fn description(self) -> Option<&'static str> {
+                match self {
+                    $(Self::$variant => Some($description),)*
+                    Self::Unknown(_) => None,
+                }
+            }
+        }
+    };
+}
+
+// Every status code from pdhmsg.h. The other PDH_* constants (log types,
+// PDH_MAX_* limits and flags like PDH_NOEXPANDCOUNTERS) aren't status
+// codes, and their values overlap with the ones below.
+pdh_errors! {
+    CstatusValidData = 0x00000000, "PDH_CSTATUS_VALID_DATA",
+        "The returned data is valid.";
+    CstatusNewData = 0x00000001, "PDH_CSTATUS_NEW_DATA",
+        "The returned data is valid and different from the last sample.";
+    CstatusNoMachine = 0x800007D0, "PDH_CSTATUS_NO_MACHINE",
+        "Unable to connect to the specified computer, or the computer is offline.";
+    CstatusNoInstance = 0x800007D1, "PDH_CSTATUS_NO_INSTANCE",
+        "The specified instance is not present.";
+    MoreData = 0x800007D2, "PDH_MORE_DATA",
+        "There is more data to return than would fit in the supplied buffer.";
+    CstatusItemNotValidated = 0x800007D3, "PDH_CSTATUS_ITEM_NOT_VALIDATED",
+        "The data item has been added to the query but has not been validated or accessed.";
+    Retry = 0x800007D4, "PDH_RETRY",
+        "The selected operation should be retried.";
+    NoData = 0x800007D5, "PDH_NO_DATA",
+        "No data to return.";
+    CalcNegativeDenominator = 0x800007D6, "PDH_CALC_NEGATIVE_DENOMINATOR",
+        "A counter with a negative denominator value was detected.";
+    CalcNegativeTimebase = 0x800007D7, "PDH_CALC_NEGATIVE_TIMEBASE",
+        "A counter with a negative time base value was detected.";
+    CalcNegativeValue = 0x800007D8, "PDH_CALC_NEGATIVE_VALUE",
+        "A counter with a negative value was detected.";
+    DialogCancelled = 0x800007D9, "PDH_DIALOG_CANCELLED",
+        "The user canceled the dialog box.";
+    EndOfLogFile = 0x800007DA, "PDH_END_OF_LOG_FILE",
+        "The end of the log file was reached.";
+    AsyncQueryTimeout = 0x800007DB, "PDH_ASYNC_QUERY_TIMEOUT",
+        "A time-out occurred while waiting for the asynchronous counter collection thread to end.";
+    CannotSetDefaultRealtimeDatasource = 0x800007DC, "PDH_CANNOT_SET_DEFAULT_REALTIME_DATASOURCE",
+        "Cannot change the default real-time data source while real-time queries are collecting data.";
+    UnableMapNameFiles = 0x80000BD5, "PDH_UNABLE_MAP_NAME_FILES",
+        "Unable to map to the performance counter name data files. The data will be read from the registry.";
+    PlaValidationWarning = 0x80000BF3, "PDH_PLA_VALIDATION_WARNING",
+        "Some of the information passed is not valid.";
+    CstatusNoObject = 0xC0000BB8, "PDH_CSTATUS_NO_OBJECT",
+        "The specified object is not found on the system.";
+    CstatusNoCounter = 0xC0000BB9, "PDH_CSTATUS_NO_COUNTER",
+        "The specified counter could not be found.";
+    CstatusInvalidData = 0xC0000BBA, "PDH_CSTATUS_INVALID_DATA",
+        "The returned data is not valid.";
+    MemoryAllocationFailure = 0xC0000BBB, "PDH_MEMORY_ALLOCATION_FAILURE",
+        "A PDH function could not allocate enough temporary memory to complete the operation.";
+    InvalidHandle = 0xC0000BBC, "PDH_INVALID_HANDLE",
+        "The handle is not a valid PDH object.";
+    InvalidArgument = 0xC0000BBD, "PDH_INVALID_ARGUMENT",
+        "A required argument is missing or incorrect.";
+    FunctionNotFound = 0xC0000BBE, "PDH_FUNCTION_NOT_FOUND",
+        "Unable to find the specified function.";
+    CstatusNoCountername = 0xC0000BBF, "PDH_CSTATUS_NO_COUNTERNAME",
+        "No counter was specified.";
+    CstatusBadCountername = 0xC0000BC0, "PDH_CSTATUS_BAD_COUNTERNAME",
+        "Unable to parse the counter path.";
+    InvalidBuffer = 0xC0000BC1, "PDH_INVALID_BUFFER",
+        "The buffer passed by the caller is not valid.";
+    InsufficientBuffer = 0xC0000BC2, "PDH_INSUFFICIENT_BUFFER",
+        "The requested data is larger than the buffer supplied.";
+    CannotConnectMachine = 0xC0000BC3, "PDH_CANNOT_CONNECT_MACHINE",
+        "Unable to connect to the requested computer.";
+    InvalidPath = 0xC0000BC4, "PDH_INVALID_PATH",
+        "The specified counter path could not be interpreted.";
+    InvalidInstance = 0xC0000BC5, "PDH_INVALID_INSTANCE",
+        "The instance name could not be read from the specified counter path.";
+    InvalidData = 0xC0000BC6, "PDH_INVALID_DATA",
+        "The data is not valid.";
+    NoDialogData = 0xC0000BC7, "PDH_NO_DIALOG_DATA",
+        "The dialog box data block was missing or not valid.";
+    CannotReadNameStrings = 0xC0000BC8, "PDH_CANNOT_READ_NAME_STRINGS",
+        "Unable to read the counter and/or help text from the specified computer.";
+    LogFileCreateError = 0xC0000BC9, "PDH_LOG_FILE_CREATE_ERROR",
+        "Unable to create the specified log file.";
+    LogFileOpenError = 0xC0000BCA, "PDH_LOG_FILE_OPEN_ERROR",
+        "Unable to open the specified log file.";
+    LogTypeNotFound = 0xC0000BCB, "PDH_LOG_TYPE_NOT_FOUND",
+        "The specified log file type has not been installed on this system.";
+    NoMoreData = 0xC0000BCC, "PDH_NO_MORE_DATA",
+        "No more data is available.";
+    EntryNotInLogFile = 0xC0000BCD, "PDH_ENTRY_NOT_IN_LOG_FILE",
+        "The specified record was not found in the log file.";
+    DataSourceIsLogFile = 0xC0000BCE, "PDH_DATA_SOURCE_IS_LOG_FILE",
+        "The specified data source is a log file.";
+    DataSourceIsRealTime = 0xC0000BCF, "PDH_DATA_SOURCE_IS_REAL_TIME",
+        "The specified data source is the current activity.";
+    UnableReadLogHeader = 0xC0000BD0, "PDH_UNABLE_READ_LOG_HEADER",
+        "The log file header could not be read.";
+    FileNotFound = 0xC0000BD1, "PDH_FILE_NOT_FOUND",
+        "Unable to find the specified file.";
+    FileAlreadyExists = 0xC0000BD2, "PDH_FILE_ALREADY_EXISTS",
+        "There is already a file with the specified file name.";
+    NotImplemented = 0xC0000BD3, "PDH_NOT_IMPLEMENTED",
+        "The function referenced has not been implemented.";
+    StringNotFound = 0xC0000BD4, "PDH_STRING_NOT_FOUND",
+        "Unable to find the specified string in the list of performance name and help text strings.";
+    UnknownLogFormat = 0xC0000BD6, "PDH_UNKNOWN_LOG_FORMAT",
+        "The log file is not in a format PDH recognizes.";
+    UnknownLogsvcCommand = 0xC0000BD7, "PDH_UNKNOWN_LOGSVC_COMMAND",
+        "The specified command is not recognized by the logging service.";
+    LogsvcQueryNotFound = 0xC0000BD8, "PDH_LOGSVC_QUERY_NOT_FOUND",
+        "The specified query could not be found in the logging service's registry key.";
+    LogsvcNotOpened = 0xC0000BD9, "PDH_LOGSVC_NOT_OPENED",
+        "The performance data log service key could not be opened.";
+    WbemError = 0xC0000BDA, "PDH_WBEM_ERROR",
+        "An error occurred while accessing the WMI data store.";
+    AccessDenied = 0xC0000BDB, "PDH_ACCESS_DENIED",
+        "Unable to access the desired computer or service.";
+    LogFileTooSmall = 0xC0000BDC, "PDH_LOG_FILE_TOO_SMALL",
+        "The maximum log file size specified is too small to log the selected counters.";
+    InvalidDatasource = 0xC0000BDD, "PDH_INVALID_DATASOURCE",
+        "Cannot connect to the ODBC data source name.";
+    InvalidSqldb = 0xC0000BDE, "PDH_INVALID_SQLDB",
+        "The SQL database does not contain a valid set of tables for Perfmon.";
+    NoCounters = 0xC0000BDF, "PDH_NO_COUNTERS",
+        "No counters were found for this Perfmon SQL log set.";
+    SqlAllocFailed = 0xC0000BE0, "PDH_SQL_ALLOC_FAILED",
+        "Call to SQLAllocStmt failed.";
+    SqlAllocconFailed = 0xC0000BE1, "PDH_SQL_ALLOCCON_FAILED",
+        "Call to SQLAllocConnect failed.";
+    SqlExecDirectFailed = 0xC0000BE2, "PDH_SQL_EXEC_DIRECT_FAILED",
+        "Call to SQLExecDirect failed.";
+    SqlFetchFailed = 0xC0000BE3, "PDH_SQL_FETCH_FAILED",
+        "Call to SQLFetch failed.";
+    SqlRowcountFailed = 0xC0000BE4, "PDH_SQL_ROWCOUNT_FAILED",
+        "Call to SQLRowCount failed.";
+    SqlMoreResultsFailed = 0xC0000BE5, "PDH_SQL_MORE_RESULTS_FAILED",
+        "Call to SQLMoreResults failed.";
+    SqlConnectFailed = 0xC0000BE6, "PDH_SQL_CONNECT_FAILED",
+        "Call to SQLConnect failed.";
+    SqlBindFailed = 0xC0000BE7, "PDH_SQL_BIND_FAILED",
+        "Call to SQLBindCol failed.";
+    CannotConnectWmiServer = 0xC0000BE8, "PDH_CANNOT_CONNECT_WMI_SERVER",
+        "Unable to connect to the WMI server on the requested computer.";
+    PlaCollectionAlreadyRunning = 0xC0000BE9, "PDH_PLA_COLLECTION_ALREADY_RUNNING",
+        "The collection is already running.";
+    PlaErrorScheduleOverlap = 0xC0000BEA, "PDH_PLA_ERROR_SCHEDULE_OVERLAP",
+        "The specified start time is after the end time.";
+    PlaCollectionNotFound = 0xC0000BEB, "PDH_PLA_COLLECTION_NOT_FOUND",
+        "The collection does not exist.";
+    PlaErrorScheduleElapsed = 0xC0000BEC, "PDH_PLA_ERROR_SCHEDULE_ELAPSED",
+        "The specified end time has already elapsed.";
+    PlaErrorNostart = 0xC0000BED, "PDH_PLA_ERROR_NOSTART",
+        "The collection did not start.";
+    PlaErrorAlreadyExists = 0xC0000BEE, "PDH_PLA_ERROR_ALREADY_EXISTS",
+        "The collection already exists.";
+    PlaErrorTypeMismatch = 0xC0000BEF, "PDH_PLA_ERROR_TYPE_MISMATCH",
+        "There is a mismatch in the settings type.";
+    PlaErrorFilepath = 0xC0000BF0, "PDH_PLA_ERROR_FILEPATH",
+        "The information specified does not resolve to a valid path name.";
+    PlaServiceError = 0xC0000BF1, "PDH_PLA_SERVICE_ERROR",
+        "The Performance Logs & Alerts service did not respond.";
+    PlaValidationError = 0xC0000BF2, "PDH_PLA_VALIDATION_ERROR",
+        "The information passed is not valid.";
+    PlaErrorNameTooLong = 0xC0000BF4, "PDH_PLA_ERROR_NAME_TOO_LONG",
+        "The name supplied is too long.";
+    InvalidSqlLogFormat = 0xC0000BF5, "PDH_INVALID_SQL_LOG_FORMAT",
+        "The SQL log format is incorrect.";
+    CounterAlreadyInQuery = 0xC0000BF6, "PDH_COUNTER_ALREADY_IN_QUERY",
+        "The counter has already been added to the query.";
+    BinaryLogCorrupt = 0xC0000BF7, "PDH_BINARY_LOG_CORRUPT",
+        "Unable to read counter information and data from the input binary log files.";
+    LogSampleTooSmall = 0xC0000BF8, "PDH_LOG_SAMPLE_TOO_SMALL",
+        "At least one of the input binary log files contains fewer than two data samples.";
+    OsLaterVersion = 0xC0000BF9, "PDH_OS_LATER_VERSION",
+        "The version of the operating system on the computer named is later than that on the local computer.";
+    OsEarlierVersion = 0xC0000BFA, "PDH_OS_EARLIER_VERSION",
+        "The version of the operating system on the computer named is not supported.";
+    IncorrectAppendTime = 0xC0000BFB, "PDH_INCORRECT_APPEND_TIME",
+        "The output file must contain earlier data than the file to be appended.";
+    UnmatchedAppendCounter = 0xC0000BFC, "PDH_UNMATCHED_APPEND_COUNTER",
+        "Both files must have identical counters in order to append.";
+    SqlAlterDetailFailed = 0xC0000BFD, "PDH_SQL_ALTER_DETAIL_FAILED",
+        "Cannot alter the CounterDetail table layout in the SQL database.";
+    QueryPerfDataTimeout = 0xC0000BFE, "PDH_QUERY_PERF_DATA_TIMEOUT",
+        "The system is busy. A time-out occurred when collecting counter data.";
+}
+
+impl PdhError {
+    /// Maps the CStatus of a counter value to how it should be charted.
+    pub fn sample_status(self) -> SampleStatus {
+        match self {
+            Self::CstatusValidData | Self::CstatusNewData => SampleStatus::Valid,
+            Self::CstatusNoInstance
+            | Self::CstatusNoObject
+            | Self::CstatusNoCounter
+            | Self::CstatusNoMachine => SampleStatus::NoInstance,
+            // The raw values didn't move forward since the last collection,
+            // so there's no new rate to compute.
+            Self::CalcNegativeDenominator
+            | Self::CalcNegativeTimebase
+            | Self::CalcNegativeValue => SampleStatus::Stale,
+            _ => SampleStatus::Invalid,
+        }
+    }
+}
+
+impl fmt::Display for PdhError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.name(), self.description()) {
+            (Some(name), Some(description)) => write!(f, "{}: {}", name, description),
+            _ => write!(f, "Unknown PDH status 0x{:08X}", self.code()),
+        }
+    }
+}
+
+impl std::error::Error for PdhError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes_round_trip() {
+        let codes = (0x0000_0000..0x0000_0010)
+            .chain(0x8000_07D0..0x8000_0800)
+            .chain(0xC000_0BB8..0xC000_0C00)
+            .chain([0x8000_4005, 0xFFFF_FFFF]);
+        for code in codes {
+            assert_eq!(PdhError::from_code(code).code(), code, "0x{:08X}", code);
+        }
+    }
+
+    #[test]
+    fn knows_the_table() {
+        assert_eq!(PdhError::from_code(0x800007D5), PdhError::NoData);
+        assert_eq!(PdhError::from_code(0xC0000BB8), PdhError::CstatusNoObject);
+        assert_eq!(
+            PdhError::from_code(0xC0000BFE),
+            PdhError::QueryPerfDataTimeout
+        );
+        // There's a hole in pdhmsg.h here.
+        assert_eq!(
+            PdhError::from_code(0xC0000BF3),
+            PdhError::Unknown(0xC0000BF3)
+        );
+        assert_eq!(PdhError::NoData.name(), Some("PDH_NO_DATA"));
+        assert_eq!(PdhError::Unknown(1234).name(), None);
+        assert_eq!(PdhError::Unknown(1234).description(), None);
+    }
+
+    #[test]
+    fn displays_names_and_codes() {
+        assert_eq!(
+            PdhError::CstatusNoInstance.to_string(),
+            "PDH_CSTATUS_NO_INSTANCE: The specified instance is not present."
+        );
+        assert_eq!(
+            PdhError::from_code(0xC0000BF3).to_string(),
+            "Unknown PDH status 0xC0000BF3"
+        );
+        assert_eq!(
+            PdhError::from_code(0x2A).to_string(),
+            "Unknown PDH status 0x0000002A"
+        );
+    }
+
+    #[test]
+    fn maps_statuses_to_samples() {
+        assert_eq!(
+            PdhError::CstatusNewData.sample_status(),
+            SampleStatus::Valid
+        );
+        assert_eq!(
+            PdhError::CstatusNoObject.sample_status(),
+            SampleStatus::NoInstance
+        );
+        assert_eq!(
+            PdhError::CalcNegativeDenominator.sample_status(),
+            SampleStatus::Stale
+        );
+        assert_eq!(
+            PdhError::CstatusInvalidData.sample_status(),
+            SampleStatus::Invalid
+        );
+        assert_eq!(
+            PdhError::Unknown(0x2A).sample_status(),
+            SampleStatus::Invalid
+        );
+    }
+}
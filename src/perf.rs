@@ -1,67 +1,179 @@
-use windows::{
-    core::Result,
-    Win32::System::Performance::{
-        PdhCollectQueryData, PdhGetFormattedCounterValue, PDH_CSTATUS_VALID_DATA,
-        PDH_FMT_COUNTERVALUE, PDH_FMT_DOUBLE,
-    },
+use std::collections::{BTreeMap, HashMap};
+
+use windows::core::Result;
+
+use crate::{
+    args::Grouping,
+    counter_path::CounterPath,
+    counter_set::CounterSet,
+    gpu_engine::{group_utilization, process_engines_path, GpuEngineInstance, Luid},
+    metric::{self, MetricSource, ProcessInfo, Reading, Sample, Unit},
+    pdh::{localize_counter_path, read_sample, PerfQueryHandle},
+    pid::get_name_from_pid,
+    windows_utils::{dxgi::enumerate_adapters, error::to_windows_error},
 };
 
-use crate::pdh::{add_perf_counters, PerfQueryHandle, PDH_FUNCTION};
+// How often to look for engines the process started or stopped using.
+const RECONCILE_INTERVAL_IN_TICKS: usize = 5;
 
 pub struct PerfTracker {
+    process_id: u32,
+    name: String,
     query_handle: PerfQueryHandle,
-    counter_handles: Vec<isize>,
+    counters: CounterSet<isize>,
+    engine_type_filter: Option<String>,
+    grouping: Grouping,
+    adapter_names: BTreeMap<Luid, String>,
+    /// Why looking for new engines failed the last time it was tried.
+    reconcile_error: Option<String>,
+    /// The last value of each counter, by path, for filling in stale
+    /// samples.
+    last_values: HashMap<String, f64>,
 }
 
 impl PerfTracker {
-    pub fn new(process_id: u32) -> Result<Self> {
-        let counter_path = format!(
-            r#"\GPU Engine(pid_{}*engtype_3D)\Utilization Percentage"#,
-            process_id
-        );
+    /// Tracks every engine type the process uses, or only
+    /// `engine_type_filter` if given.
+    pub fn new(
+        process_id: u32,
+        engine_type_filter: Option<&str>,
+        grouping: Grouping,
+    ) -> Result<Self> {
+        let counter_path = process_engines_path(process_id).to_string();
 
-        let query_handle = PerfQueryHandle::open_query()?;
-        let counter_handles = add_perf_counters(&query_handle, &counter_path)?;
+        let mut query_handle = PerfQueryHandle::open_query()?;
+        let wildcard_path = localize_counter_path(&query_handle, &counter_path)?;
+        let mut counters = CounterSet::new(wildcard_path, RECONCILE_INTERVAL_IN_TICKS);
+        counters
+            .reconcile(&mut query_handle)
+            .map_err(to_windows_error)?;
+        let name = get_name_from_pid(process_id)?;
+        let adapter_names = match grouping {
+            Grouping::Adapter => enumerate_adapters()?
+                .into_iter()
+                .map(|(luid, name)| {
+                    let luid = Luid {
+                        high: luid.HighPart as u32,
+                        low: luid.LowPart,
+                    };
+                    (luid, name)
+                })
+                .collect(),
+            Grouping::EngineType => BTreeMap::new(),
+        };
 
         Ok(Self {
+            process_id,
+            name,
             query_handle,
-            counter_handles,
+            counters,
+            engine_type_filter: engine_type_filter.map(str::to_owned),
+            grouping,
+            adapter_names,
+            reconcile_error: None,
+            last_values: HashMap::new(),
         })
     }
 
     pub fn start(&self) -> Result<()> {
-        self.collect_query_data()
+        self.query_handle.collect_data()
     }
 
-    pub fn get_current_value(&self) -> Result<f64> {
-        self.collect_query_data()?;
-
-        let mut utilization_value = 0.0;
-        for counter_handle in &self.counter_handles {
-            let counter_value = unsafe {
-                let mut counter_type = 0;
-                let mut counter_value = PDH_FMT_COUNTERVALUE::default();
-                PDH_FUNCTION(PdhGetFormattedCounterValue(
-                    *counter_handle,
-                    PDH_FMT_DOUBLE,
-                    Some(&mut counter_type),
-                    &mut counter_value,
-                ))
-                .ok()?;
-                counter_value
+    /// Returns the utilization of each engine type or adapter, depending
+    /// on the tracker's grouping.
+    pub fn get_current_values(&mut self) -> Result<Vec<(String, Sample)>> {
+        self.query_handle.collect_data()?;
+
+        let mut samples = Vec::with_capacity(self.counters.counters().len());
+        let mut last_values = HashMap::with_capacity(self.counters.counters().len());
+        for (path, counter_handle) in self.counters.counters() {
+            let Some(instance) = self.parse_instance(path) else {
+                continue;
             };
-            assert_eq!(counter_value.CStatus, PDH_CSTATUS_VALID_DATA);
-            let value = unsafe { counter_value.Anonymous.doubleValue };
-            utilization_value += value;
+            let sample = read_sample(*counter_handle, self.last_values.get(path).copied())?;
+            if let Some(value) = sample.value {
+                last_values.insert(path.clone(), value);
+            }
+            samples.push((instance, sample));
         }
-        Ok(utilization_value)
+        self.last_values = last_values;
+
+        // The samples are kept even if the engines can't be updated, which
+        // is tried again an interval later.
+        match self.counters.tick(&mut self.query_handle) {
+            Ok(Some(changes)) => {
+                self.reconcile_error = None;
+                if changes.added > 0 {
+                    // Rate counters need two samples before they have a
+                    // value, so take the first one now to have new engines
+                    // ready next tick. If collecting fails, so will the
+                    // next tick's.
+                    let _ = self.query_handle.collect_data();
+                }
+            }
+            Ok(None) => {}
+            Err(error) => {
+                self.reconcile_error = Some(format!("Failed to look for new engines: {}", error));
+            }
+        }
+
+        Ok(group_utilization(
+            samples.iter().map(|(instance, sample)| (instance, *sample)),
+            self.grouping,
+            &self.adapter_names,
+        ))
+    }
+
+    /// Returns the engine a counter measures, or `None` if it should be
+    /// left out of the chart.
+    fn parse_instance(&self, path: &str) -> Option<GpuEngineInstance> {
+        let path: CounterPath = path.parse().ok()?;
+        let instance: GpuEngineInstance = path.instance?.name.parse().ok()?;
+        let included = self
+            .engine_type_filter
+            .as_deref()
+            .is_none_or(|filter| instance.engine_type_name().eq_ignore_ascii_case(filter));
+        included.then_some(instance)
     }
 
     pub fn close(mut self) -> Result<()> {
         self.query_handle.close_query()
     }
+}
+
+impl MetricSource for PerfTracker {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn unit(&self) -> Unit {
+        Unit::Percent
+    }
+
+    fn start(&mut self) -> metric::Result<()> {
+        Ok(PerfTracker::start(self)?)
+    }
+
+    fn sample(&mut self) -> metric::Result<Vec<Reading>> {
+        let samples = self.get_current_values()?;
+        Ok(samples
+            .into_iter()
+            .map(|(series, sample)| Reading::new(series, sample))
+            .collect())
+    }
+
+    fn close(self: Box<Self>) -> metric::Result<()> {
+        Ok(PerfTracker::close(*self)?)
+    }
+
+    fn process(&self) -> Option<ProcessInfo> {
+        Some(ProcessInfo {
+            process_id: self.process_id,
+            name: self.name.clone(),
+        })
+    }
 
-    fn collect_query_data(&self) -> Result<()> {
-        unsafe { PDH_FUNCTION(PdhCollectQueryData(self.query_handle.0)).ok() }
+    fn warning(&self) -> Option<String> {
+        self.reconcile_error.clone()
     }
 }
@@ -0,0 +1,744 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    io::{self, BufRead, Seek, SeekFrom},
+    time::Duration,
+};
+
+use crate::counter_path::CounterPath;
+
+// `relog -f csv` writes one row per sample: a timestamp followed by the
+// value of every counter, all quoted. A header row comes first:
+//
+//   "(PDH-CSV 4.0) (Pacific Daylight Time)(420)","\\HOST\Memory\Available Bytes"
+//   "05/01/2024 13:45:30.250","8123456789"
+//
+// `relog -f tsv` writes the same with tabs between the fields, and
+// `(PDH-TSV 4.0)` in the header.
+//
+// Timestamps are in the local time of the machine that logged them, with
+// the date in its short date format, so the order of day and month has to
+// be worked out from the rows. Cells are blank (usually a single space)
+// where a counter had no value, e.g. before its instance existed.
+
+const CSV_PREFIX: &str = "(PDH-CSV 4.0)";
+const TSV_PREFIX: &str = "(PDH-TSV 4.0)";
+// How many rows to look at to find the sample interval.
+const INTERVAL_SAMPLE_ROWS: usize = 100;
+// Used when there aren't two rows to tell the interval from.
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(1);
+const MILLIS_PER_DAY: i64 = 86_400_000;
+
+#[derive(Debug)]
+pub enum PerfmonLogError {
+    Io(io::Error),
+    NotAPerfmonLog,
+    /// A line can't be parsed. Lines are counted from 1.
+    Invalid {
+        line: usize,
+        reason: &'static str,
+    },
+    /// The file no longer matches what was read when it was opened.
+    Changed,
+}
+
+impl fmt::Display for PerfmonLogError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PerfmonLogError::Io(error) => write!(f, "Failed to read the perfmon log: {}", error),
+            PerfmonLogError::NotAPerfmonLog => {
+                write!(
+                    f,
+                    "The file isn't a perfmon log in PDH-CSV 4.0 or PDH-TSV 4.0 format!"
+                )
+            }
+            PerfmonLogError::Invalid { line, reason } => {
+                write!(f, "Line {} of the perfmon log is invalid: {}", line, reason)
+            }
+            PerfmonLogError::Changed => write!(f, "The perfmon log changed while playing it!"),
+        }
+    }
+}
+
+impl std::error::Error for PerfmonLogError {}
+
+impl From<io::Error> for PerfmonLogError {
+    fn from(error: io::Error) -> Self {
+        PerfmonLogError::Io(error)
+    }
+}
+
+/// A counter logged in one column.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PerfmonColumn {
+    /// `None` if the header holds something that isn't a counter path.
+    pub path: Option<CounterPath>,
+    /// The name of the counter's series: its display name, or its whole
+    /// path if counters from several machines share a display name.
+    pub series: String,
+}
+
+/// The order of the parts of a date.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum DateOrder {
+    MonthDayYear,
+    DayMonthYear,
+    YearMonthDay,
+}
+
+/// A timestamp as written, before the order of its date is known.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct RawTimestamp {
+    date: [u32; 3],
+    /// Whether the date starts with a four digit year.
+    year_first: bool,
+    separator: char,
+    millis_of_day: u32,
+}
+
+/// Reads a perfmon log row by row. Only where each row starts is kept in
+/// memory, so logs spanning years can be played back.
+pub struct PerfmonLog<R: BufRead + Seek> {
+    reader: R,
+    /// `,` or a tab, as given by the header.
+    delimiter: char,
+    columns: Vec<PerfmonColumn>,
+    /// Where every row starts, and the tick it falls on.
+    rows: Vec<(u64, u64)>,
+    interval: Duration,
+    /// Where the reader is, if it's known.
+    reader_offset: Option<u64>,
+}
+
+impl<R: BufRead + Seek> PerfmonLog<R> {
+    pub fn new(mut reader: R) -> Result<Self, PerfmonLogError> {
+        reader.seek(SeekFrom::Start(0))?;
+        let mut line = Vec::new();
+        let mut offset = reader.read_until(b'\n', &mut line)? as u64;
+        let header = decode_line(&line);
+        let header = header.strip_prefix('\u{feff}').unwrap_or(&header);
+        let (prefix, delimiter) = if header.trim_start_matches('"').starts_with(TSV_PREFIX) {
+            (TSV_PREFIX, '\t')
+        } else {
+            (CSV_PREFIX, ',')
+        };
+        let fields =
+            split_fields(header, delimiter).map_err(|_| PerfmonLogError::NotAPerfmonLog)?;
+        if !fields
+            .first()
+            .is_some_and(|field| field.starts_with(prefix))
+        {
+            return Err(PerfmonLogError::NotAPerfmonLog);
+        }
+        let columns = parse_columns(&fields[1..]);
+        if columns.is_empty() {
+            return Err(PerfmonLogError::Invalid {
+                line: 1,
+                reason: "the log has no counters",
+            });
+        }
+
+        // Every timestamp has to be seen before any of them can be read.
+        let mut offsets = Vec::new();
+        let mut line_numbers = Vec::new();
+        let mut timestamps = Vec::new();
+        for line_number in 2.. {
+            line.clear();
+            let len = reader.read_until(b'\n', &mut line)? as u64;
+            if len == 0 {
+                break;
+            }
+            let text = decode_line(&line);
+            if !text.trim().is_empty() {
+                let invalid = |reason| PerfmonLogError::Invalid {
+                    line: line_number,
+                    reason,
+                };
+                let fields = split_fields(&text, delimiter).map_err(invalid)?;
+                if fields.len() != columns.len() + 1 {
+                    return Err(invalid("expected a value for every counter"));
+                }
+                for field in &fields[1..] {
+                    parse_value(field).map_err(invalid)?;
+                }
+                timestamps.push(parse_timestamp(&fields[0]).map_err(invalid)?);
+                offsets.push(offset);
+                line_numbers.push(line_number);
+            }
+            offset += len;
+        }
+
+        let order = date_order(&timestamps, &line_numbers)?;
+        let times = timestamps
+            .iter()
+            .zip(&line_numbers)
+            .map(|(timestamp, line)| {
+                local_millis(timestamp, order).map_err(|reason| PerfmonLogError::Invalid {
+                    line: *line,
+                    reason,
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let interval = sample_interval(&times);
+        let rows = offsets
+            .into_iter()
+            .zip(row_ticks(&times, interval))
+            .collect();
+
+        Ok(Self {
+            reader,
+            delimiter,
+            columns,
+            rows,
+            interval,
+            reader_offset: None,
+        })
+    }
+
+    pub fn columns(&self) -> &[PerfmonColumn] {
+        &self.columns
+    }
+
+    /// How often samples were logged.
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// How many ticks the log spans, including ticks where logging stopped.
+    pub fn tick_count(&self) -> u64 {
+        self.rows.last().map_or(0, |(_, tick)| tick + 1)
+    }
+
+    /// The tick `row` falls on, or `None` past the last row.
+    pub fn row_tick(&self, row: usize) -> Option<u64> {
+        self.rows.get(row).map(|(_, tick)| *tick)
+    }
+
+    /// The first row at or after `tick`.
+    pub fn find_row(&self, tick: u64) -> usize {
+        self.rows.partition_point(|(_, row_tick)| *row_tick < tick)
+    }
+
+    /// Reads the value of every counter in `row`, `None` where it's blank.
+    pub fn read_row(&mut self, row: usize) -> Result<Vec<Option<f64>>, PerfmonLogError> {
+        let offset = self.rows[row].0;
+        // Rows are mostly read in order, and seeking drops what's buffered.
+        if self.reader_offset != Some(offset) {
+            self.reader.seek(SeekFrom::Start(offset))?;
+        }
+        let mut line = Vec::new();
+        let len = self.reader.read_until(b'\n', &mut line)?;
+        self.reader_offset = Some(offset + len as u64);
+
+        // Every row was checked when the log was opened.
+        let fields = split_fields(&decode_line(&line), self.delimiter)
+            .map_err(|_| PerfmonLogError::Changed)?;
+        if fields.len() != self.columns.len() + 1 {
+            return Err(PerfmonLogError::Changed);
+        }
+        fields[1..]
+            .iter()
+            .map(|field| parse_value(field).map_err(|_| PerfmonLogError::Changed))
+            .collect()
+    }
+}
+
+/// Names every column after its counter, falling back to the header text
+/// if it can't be parsed.
+fn parse_columns(fields: &[String]) -> Vec<PerfmonColumn> {
+    let paths: Vec<Option<CounterPath>> = fields.iter().map(|field| field.parse().ok()).collect();
+    let names: Vec<String> = paths
+        .iter()
+        .zip(fields)
+        .map(|(path, field)| match path {
+            Some(path) => path.display_name(),
+            None => field.clone(),
+        })
+        .collect();
+    let mut name_counts = HashMap::new();
+    for name in &names {
+        *name_counts.entry(name.as_str()).or_insert(0) += 1;
+    }
+    paths
+        .iter()
+        .zip(&names)
+        .zip(fields)
+        .map(|((path, name), field)| PerfmonColumn {
+            path: path.clone(),
+            series: if name_counts[name.as_str()] > 1 {
+                field.clone()
+            } else {
+                name.clone()
+            },
+        })
+        .collect()
+}
+
+/// Lines are usually UTF-8, but machine and instance names in logs from
+/// older systems may not be, and aren't worth failing over.
+fn decode_line(line: &[u8]) -> String {
+    let text = String::from_utf8_lossy(line);
+    text.trim_end_matches(['\r', '\n']).to_owned()
+}
+
+/// Splits a line into its fields at every `delimiter`, unquoting the
+/// quoted ones.
+fn split_fields(line: &str, delimiter: char) -> Result<Vec<String>, &'static str> {
+    let mut fields = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        let mut field = String::new();
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            loop {
+                match chars.next() {
+                    Some('"') if chars.peek() == Some(&'"') => {
+                        chars.next();
+                        field.push('"');
+                    }
+                    Some('"') => break,
+                    Some(char) => field.push(char),
+                    None => return Err("a quoted field isn't closed"),
+                }
+            }
+            fields.push(field);
+            match chars.next() {
+                Some(char) if char == delimiter => {}
+                Some(_) => return Err("expected a delimiter after a quoted field"),
+                None => return Ok(fields),
+            }
+        } else {
+            loop {
+                match chars.next() {
+                    Some(char) if char == delimiter => break,
+                    Some(char) => field.push(char),
+                    None => {
+                        fields.push(field);
+                        return Ok(fields);
+                    }
+                }
+            }
+            fields.push(field);
+        }
+    }
+}
+
+/// Parses a counter value. Locales that use a decimal comma write values
+/// with one. Counters are always finite, so `inf` and `NaN` are rejected
+/// along with anything else that isn't a number.
+fn parse_value(field: &str) -> Result<Option<f64>, &'static str> {
+    let field = field.trim();
+    if field.is_empty() {
+        return Ok(None);
+    }
+    field
+        .parse()
+        .or_else(|_| field.replace(',', ".").parse())
+        .ok()
+        .filter(|value: &f64| value.is_finite())
+        .map(Some)
+        .ok_or("a value isn't a number")
+}
+
+/// Parses a timestamp such as `05/01/2024 13:45:30.250`,
+/// `01.05.2024 13:45:30,250` or `2024-05-01 1:45:30.250 PM`.
+fn parse_timestamp(text: &str) -> Result<RawTimestamp, &'static str> {
+    let (date, time) = text
+        .trim()
+        .split_once(' ')
+        .ok_or("expected a date and a time")?;
+
+    let separator = date
+        .chars()
+        .find(|char| !char.is_ascii_digit())
+        .ok_or("the date has no separators")?;
+    let parts: Vec<&str> = date.split(separator).collect();
+    let [first, second, third] = parts[..] else {
+        return Err("expected a date in three parts");
+    };
+    let parse_part = |part: &str| part.parse::<u32>().map_err(|_| "the date isn't numeric");
+    let date = [parse_part(first)?, parse_part(second)?, parse_part(third)?];
+
+    let (time, meridiem) = match time.trim().split_once(' ') {
+        Some((time, meridiem)) => (time, Some(meridiem.trim())),
+        None => (time, None),
+    };
+    let parts: Vec<&str> = time.split(':').collect();
+    let [hours, minutes, seconds] = parts[..] else {
+        return Err("expected a time in hours, minutes and seconds");
+    };
+    let (seconds, fraction) = match seconds.split_once(['.', ',']) {
+        Some((seconds, fraction)) => (seconds, fraction),
+        None => (seconds, ""),
+    };
+    let parse_part = |part: &str| part.parse::<u32>().map_err(|_| "the time isn't numeric");
+    let (mut hours, minutes, seconds) = (
+        parse_part(hours)?,
+        parse_part(minutes)?,
+        parse_part(seconds)?,
+    );
+    if !fraction.bytes().all(|byte| byte.is_ascii_digit()) {
+        return Err("the time isn't numeric");
+    }
+    // Only milliseconds are kept.
+    let millis = format!("{:0<3}", &fraction[..fraction.len().min(3)])
+        .parse::<u32>()
+        .unwrap();
+
+    if let Some(meridiem) = meridiem {
+        if hours == 0 || hours > 12 {
+            return Err("the hour is out of range");
+        }
+        hours %= 12;
+        if meridiem.eq_ignore_ascii_case("PM") {
+            hours += 12;
+        } else if !meridiem.eq_ignore_ascii_case("AM") {
+            return Err("expected AM or PM after the time");
+        }
+    }
+    // Leap seconds are allowed.
+    if hours > 23 || minutes > 59 || seconds > 60 {
+        return Err("the time is out of range");
+    }
+
+    Ok(RawTimestamp {
+        date,
+        year_first: first.len() == 4,
+        separator,
+        millis_of_day: ((hours * 60 + minutes) * 60 + seconds) * 1000 + millis,
+    })
+}
+
+/// Works out the date format of a log from all of its timestamps. Days
+/// past the 12th tell day and month apart. Failing that, dates separated
+/// by dots are taken to be day first as in most of Europe, and others to
+/// be month first as on US systems.
+fn date_order(
+    timestamps: &[RawTimestamp],
+    line_numbers: &[usize],
+) -> Result<DateOrder, PerfmonLogError> {
+    let Some(first) = timestamps.first() else {
+        return Ok(DateOrder::MonthDayYear);
+    };
+    if first.year_first {
+        return Ok(DateOrder::YearMonthDay);
+    }
+    let day_first = timestamps
+        .iter()
+        .position(|timestamp| timestamp.date[0] > 12);
+    let month_first = timestamps
+        .iter()
+        .position(|timestamp| timestamp.date[1] > 12);
+    match (day_first, month_first) {
+        (Some(day_first), Some(month_first)) => Err(PerfmonLogError::Invalid {
+            line: line_numbers[day_first.max(month_first)],
+            reason: "the dates mix day first and month first",
+        }),
+        (Some(_), None) => Ok(DateOrder::DayMonthYear),
+        (None, Some(_)) => Ok(DateOrder::MonthDayYear),
+        (None, None) if first.separator == '.' => Ok(DateOrder::DayMonthYear),
+        (None, None) => Ok(DateOrder::MonthDayYear),
+    }
+}
+
+/// Milliseconds since 1970-01-01 in local time.
+fn local_millis(timestamp: &RawTimestamp, order: DateOrder) -> Result<i64, &'static str> {
+    let [first, second, third] = timestamp.date;
+    let (year, month, day) = match order {
+        DateOrder::MonthDayYear => (third, first, second),
+        DateOrder::DayMonthYear => (third, second, first),
+        DateOrder::YearMonthDay => (first, second, third),
+    };
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return Err("the date is out of range");
+    }
+    // Short date formats with two digit years.
+    let year = if year < 100 { year + 2000 } else { year };
+    let days = days_from_civil(year as i64, month, day);
+    Ok(days * MILLIS_PER_DAY + timestamp.millis_of_day as i64)
+}
+
+/// The usual time between rows, ignoring the odd late sample or break in
+/// logging.
+fn sample_interval(times: &[i64]) -> Duration {
+    let mut deltas: Vec<i64> = times
+        .windows(2)
+        .take(INTERVAL_SAMPLE_ROWS)
+        .map(|pair| pair[1] - pair[0])
+        .filter(|delta| *delta > 0)
+        .collect();
+    if deltas.is_empty() {
+        return DEFAULT_INTERVAL;
+    }
+    deltas.sort_unstable();
+    let median = deltas[deltas.len() / 2];
+    // Perfmon samples whole seconds apart, give or take how long
+    // collecting took.
+    if median >= 1000 {
+        Duration::from_secs(((median + 500) / 1000) as u64)
+    } else {
+        Duration::from_millis(median as u64)
+    }
+}
+
+/// Puts every row on the tick closest to when it was logged, so breaks in
+/// logging show up as gaps. Rows never share a tick, and a row that goes
+/// back in time (e.g. when daylight saving time ends) follows the one
+/// before it.
+fn row_ticks(times: &[i64], interval: Duration) -> Vec<u64> {
+    let interval = interval.as_millis() as i64;
+    let mut ticks = Vec::with_capacity(times.len());
+    for (i, time) in times.iter().enumerate() {
+        let tick = match i.checked_sub(1) {
+            Some(previous) => {
+                let delta = time - times[previous];
+                let steps = (delta + interval / 2) / interval;
+                ticks[previous] + steps.max(1) as u64
+            }
+            None => 0,
+        };
+        ticks.push(tick);
+    }
+    ticks
+}
+
+/// Turns a proleptic Gregorian date into days since 1970-01-01, using
+/// Howard Hinnant's algorithm.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month_index = (month as i64 + 9) % 12;
+    let day_of_year = (153 * month_index + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    // Written the way `relog -f csv` writes them, with CRLF line endings.
+    const MONTH_FIRST: &[u8] = include_bytes!("../tests/fixtures/perfmon/mdy.csv");
+    const DAY_FIRST: &[u8] = include_bytes!("../tests/fixtures/perfmon/dmy.csv");
+    const DAY_FIRST_DOTS: &[u8] = include_bytes!("../tests/fixtures/perfmon/dmy_dots.csv");
+    const MALFORMED: &[u8] = include_bytes!("../tests/fixtures/perfmon/malformed.csv");
+    const NOT_FINITE: &[u8] = include_bytes!("../tests/fixtures/perfmon/not_finite.csv");
+    // The same as `MONTH_FIRST`, written by `relog -f tsv`.
+    const MONTH_FIRST_TSV: &[u8] = include_bytes!("../tests/fixtures/perfmon/mdy.tsv");
+
+    fn open(log: &[u8]) -> Result<PerfmonLog<Cursor<&[u8]>>, PerfmonLogError> {
+        PerfmonLog::new(Cursor::new(log))
+    }
+
+    fn series(log: &PerfmonLog<Cursor<&[u8]>>) -> Vec<String> {
+        log.columns()
+            .iter()
+            .map(|column| column.series.clone())
+            .collect()
+    }
+
+    fn ticks(log: &PerfmonLog<Cursor<&[u8]>>) -> Vec<u64> {
+        (0..).map_while(|row| log.row_tick(row)).collect()
+    }
+
+    #[test]
+    fn reads_month_first_logs() {
+        let mut log = open(MONTH_FIRST).unwrap();
+        assert_eq!(
+            series(&log),
+            [
+                r"Processor(_Total)\% Processor Time",
+                r"Process(app (1), x)\% Processor Time",
+                r"Memory\Available Bytes",
+            ]
+        );
+        let path = log.columns()[2].path.as_ref().unwrap();
+        assert_eq!(path.machine.as_deref(), Some("HOST"));
+        assert_eq!(path.counter, "Available Bytes");
+
+        // The year changes between the second and third rows, and logging
+        // stopped for a few seconds before the last one.
+        assert_eq!(log.interval(), Duration::from_secs(1));
+        assert_eq!(ticks(&log), [0, 1, 2, 6]);
+        assert_eq!(log.tick_count(), 7);
+
+        assert_eq!(
+            log.read_row(0).unwrap(),
+            [Some(12.5), None, Some(8123456789.0)]
+        );
+        assert_eq!(
+            log.read_row(2).unwrap(),
+            [None, Some(2.5), Some(8123455000.0)]
+        );
+        // Out of order, as after a seek.
+        assert_eq!(
+            log.read_row(1).unwrap(),
+            [Some(13.5), Some(1.0), Some(8123456000.0)]
+        );
+        assert_eq!(
+            log.read_row(3).unwrap(),
+            [Some(14.0), Some(3.0), Some(8123454000.0)]
+        );
+    }
+
+    #[test]
+    fn reads_day_first_logs() {
+        let mut log = open(DAY_FIRST).unwrap();
+        assert_eq!(
+            series(&log),
+            [
+                r"GPU Engine(pid_1234_luid_0x00000000_0x0000D1E5_phys_0_eng_0_engtype_3D)\Utilization Percentage"
+            ]
+        );
+        // The last row goes back in time, so it just follows the one
+        // before it.
+        assert_eq!(log.interval(), Duration::from_secs(15));
+        assert_eq!(ticks(&log), [0, 1, 2, 3]);
+        assert_eq!(log.read_row(0).unwrap(), [Some(1.5)]);
+        assert_eq!(log.read_row(2).unwrap(), [None]);
+        assert_eq!(log.read_row(3).unwrap(), [Some(4.0)]);
+    }
+
+    #[test]
+    fn reads_day_first_logs_with_decimal_commas() {
+        let mut log = open(DAY_FIRST_DOTS).unwrap();
+        // Both columns have the same display name, so they keep their
+        // whole paths.
+        assert_eq!(
+            series(&log),
+            [r"\\A\Memory\Available Bytes", r"\\B\Memory\Available Bytes"]
+        );
+        // Read month first, the rows would be a month apart.
+        assert_eq!(log.interval(), Duration::from_secs(2));
+        assert_eq!(ticks(&log), [0, 1]);
+        assert_eq!(log.read_row(0).unwrap(), [Some(1.5), Some(2.0)]);
+        assert_eq!(log.read_row(1).unwrap(), [Some(2.5), Some(3.0)]);
+    }
+
+    #[test]
+    fn finds_rows_by_tick() {
+        let log = open(MONTH_FIRST).unwrap();
+        assert_eq!(log.find_row(0), 0);
+        assert_eq!(log.find_row(2), 2);
+        // Ticks in a gap find the row after it.
+        assert_eq!(log.find_row(4), 3);
+        assert_eq!(log.find_row(7), 4);
+    }
+
+    #[test]
+    fn reads_tab_separated_logs() {
+        let mut tsv = open(MONTH_FIRST_TSV).unwrap();
+        let mut csv = open(MONTH_FIRST).unwrap();
+        assert_eq!(tsv.columns(), csv.columns());
+        assert_eq!(ticks(&tsv), ticks(&csv));
+        for row in 0..4 {
+            assert_eq!(tsv.read_row(row).unwrap(), csv.read_row(row).unwrap());
+        }
+
+        // Commas don't split fields in a TSV log, and tabs don't in a CSV
+        // one.
+        let tsv = open(b"\"(PDH-TSV 4.0)\",\"\\\\HOST\\Memory\\Available Bytes\"\r\n");
+        assert!(matches!(tsv, Err(PerfmonLogError::NotAPerfmonLog)));
+        let csv = open(b"\"(PDH-CSV 4.0)\"\t\"\\\\HOST\\Memory\\Available Bytes\"\r\n");
+        assert!(matches!(csv, Err(PerfmonLogError::NotAPerfmonLog)));
+    }
+
+    #[test]
+    fn rejects_malformed_rows() {
+        assert!(matches!(
+            open(MALFORMED),
+            Err(PerfmonLogError::Invalid { line: 3, .. })
+        ));
+        assert!(matches!(
+            open(NOT_FINITE),
+            Err(PerfmonLogError::Invalid {
+                line: 4,
+                reason: "a value isn't a number"
+            })
+        ));
+        for value in ["NaN", "infinity", "-inf", "1e999"] {
+            assert!(parse_value(value).is_err(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_logs() {
+        let invalid = |log: &str| match open(log.as_bytes()) {
+            Err(PerfmonLogError::Invalid { line, .. }) => Some(line),
+            _ => None,
+        };
+        let header = "\"(PDH-CSV 4.0)\",\"\\\\HOST\\Memory\\Available Bytes\"\r\n";
+        assert_eq!(
+            invalid(&format!("{}\"05/01/2024 10:00:00.000\",\"abc\"", header)),
+            Some(2)
+        );
+        assert_eq!(
+            invalid(&format!("{}\"05/01/2024 25:00:00.000\",\"1\"", header)),
+            Some(2)
+        );
+        assert_eq!(
+            invalid(&format!("{}\"05/01/2024 10:00:00.000,\"1\"", header)),
+            Some(2)
+        );
+        assert_eq!(
+            invalid(&format!(
+                "{}\"13/05/2024 10:00:00.000\",\"1\"\r\n\"05/14/2024 10:00:00.000\",\"2\"",
+                header
+            )),
+            Some(3)
+        );
+        assert_eq!(invalid("\"(PDH-CSV 4.0)\"\r\n"), Some(1));
+
+        assert!(matches!(
+            open(b"timestamp,elapsed,series,value,status\n"),
+            Err(PerfmonLogError::NotAPerfmonLog)
+        ));
+        assert!(matches!(open(b""), Err(PerfmonLogError::NotAPerfmonLog)));
+    }
+
+    #[test]
+    fn an_empty_log_has_no_ticks() {
+        let log = open(b"\"(PDH-CSV 4.0)\",\"\\\\HOST\\Memory\\Available Bytes\"\r\n").unwrap();
+        assert_eq!(log.tick_count(), 0);
+        assert_eq!(log.interval(), DEFAULT_INTERVAL);
+    }
+
+    #[test]
+    fn parses_timestamps() {
+        let timestamp = parse_timestamp("2024-05-01 1:45:30.25 PM").unwrap();
+        assert_eq!(timestamp.date, [2024, 5, 1]);
+        assert!(timestamp.year_first);
+        assert_eq!(
+            timestamp.millis_of_day,
+            (13 * 3600 + 45 * 60 + 30) * 1000 + 250
+        );
+        assert_eq!(
+            parse_timestamp("01.05.2024 12:00:00,5")
+                .unwrap()
+                .millis_of_day,
+            12 * 3600 * 1000 + 500
+        );
+        assert_eq!(
+            parse_timestamp("5/1/2024 12:00:00 AM")
+                .unwrap()
+                .millis_of_day,
+            0
+        );
+        assert!(parse_timestamp("5/1/2024 13:00:00 PM").is_err());
+        assert!(parse_timestamp("5/1/2024").is_err());
+        assert!(parse_timestamp("5/1 10:00:00").is_err());
+    }
+
+    #[test]
+    fn converts_dates() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(2000, 3, 1), 11017);
+        assert_eq!(
+            days_from_civil(2024, 1, 1) - days_from_civil(2023, 12, 31),
+            1
+        );
+    }
+}
@@ -1,6 +1,11 @@
+#[cfg(target_os = "linux")]
+use std::{fs, io, path::Path};
+
+#[cfg(windows)]
 use processdumper::{
     find_process_id_with_name_in_session, get_session_for_current_process, process::ProcessIterator,
 };
+#[cfg(windows)]
 use windows::Win32::Foundation::E_FAIL;
 
 pub fn parse_pid(value: &str) -> Result<u32, std::num::ParseIntError> {
@@ -11,6 +16,7 @@ pub fn parse_pid(value: &str) -> Result<u32, std::num::ParseIntError> {
     }
 }
 
+#[cfg(windows)]
 pub fn get_current_dwm_pid() -> windows::core::Result<u32> {
     // During RDP sessions, you'll have multiple sessions and muiltple
     // DWMs. We want the one the user is currently using, so find the
@@ -29,6 +35,7 @@ pub fn get_current_dwm_pid() -> windows::core::Result<u32> {
     Ok(process_id)
 }
 
+#[cfg(windows)]
 pub fn get_name_from_pid(pid: u32) -> windows::core::Result<String> {
     // This is overkill, but even PROCESS_QUERY_LIMITED_INFORMATION fails for
     // some processes. This seems to be the best way to get the process name
@@ -41,3 +48,10 @@ pub fn get_name_from_pid(pid: u32) -> windows::core::Result<String> {
     }
     Ok("<Unknown>".to_owned())
 }
+
+/// Reads the process name from `<proc_root>/<pid>/comm`.
+#[cfg(target_os = "linux")]
+pub fn read_process_name(proc_root: &Path, pid: u32) -> io::Result<String> {
+    let comm = fs::read_to_string(proc_root.join(pid.to_string()).join("comm"))?;
+    Ok(comm.trim_end_matches('\n').to_owned())
+}
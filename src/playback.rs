@@ -0,0 +1,410 @@
+use std::time::Duration;
+
+use crate::{
+    chart_model::ChartModel,
+    metric::{self, MetricSource, Playback, PlaybackSpeed, Reading},
+};
+
+/// The multiples of real time that `Faster` and `Slower` step through.
+/// Going faster than the last one plays back instantly.
+const SPEEDS: [f64; 9] = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0, 16.0, 32.0, 64.0];
+
+/// What the transport controls ask a playback to do.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PlaybackCommand {
+    TogglePause,
+    /// Pauses and moves one tick forward.
+    StepForward,
+    /// Pauses and moves one tick back.
+    StepBack,
+    /// Jumps by a number of ticks, back if negative.
+    SeekBy(i64),
+    SeekTo(u64),
+    Faster,
+    Slower,
+}
+
+/// How to bring a chart up to date with the clock.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ClockAction {
+    Nothing,
+    /// Play this many ticks from where the chart left off.
+    Play(u64),
+    /// Start over, showing the ticks leading up to this position.
+    Seek(u64),
+}
+
+/// Keeps time for a playback: which tick is next, how fast time passes,
+/// and whether it passes at all. It only does arithmetic, so whatever
+/// drives it supplies the real time that passed and carries out the
+/// actions it returns.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlaybackClock {
+    playback: Playback,
+    /// How many ticks the chart shows. Playing more than that at once is
+    /// the same as seeking.
+    history: u64,
+    /// How many ticks have been played, which is also the next one to play.
+    position: u64,
+    paused: bool,
+    /// Playback time that passed since the last tick was played.
+    banked: Duration,
+}
+
+impl PlaybackClock {
+    /// `history` is how many ticks the chart shows.
+    pub fn new(playback: Playback, history: usize) -> Self {
+        Self {
+            playback,
+            history: history as u64,
+            position: 0,
+            paused: false,
+            banked: Duration::ZERO,
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.position >= self.playback.length
+    }
+
+    /// How long to wait between ticks at the current speed.
+    pub fn tick_interval(&self) -> Duration {
+        self.playback.tick_interval()
+    }
+
+    /// Moves the clock on by `elapsed` of real time.
+    pub fn advance(&mut self, elapsed: Duration) -> ClockAction {
+        if self.paused || self.is_finished() {
+            return ClockAction::Nothing;
+        }
+        let remaining = self.playback.length - self.position;
+        let due = match self.playback.speed {
+            PlaybackSpeed::Multiplier(multiplier) => {
+                self.banked += elapsed.mul_f64(multiplier);
+                let interval = self.playback.interval.as_nanos().max(1);
+                let due = (self.banked.as_nanos() / interval) as u64;
+                self.banked -= Duration::from_nanos((due as u128 * interval) as u64);
+                due.min(remaining)
+            }
+            PlaybackSpeed::Instant => remaining,
+        };
+        if self.is_finished_after(due) {
+            self.banked = Duration::ZERO;
+        }
+        self.play(due)
+    }
+
+    pub fn apply(&mut self, command: PlaybackCommand) -> ClockAction {
+        match command {
+            PlaybackCommand::TogglePause => {
+                self.paused = !self.paused;
+                // Time doesn't pass while paused.
+                self.banked = Duration::ZERO;
+                ClockAction::Nothing
+            }
+            PlaybackCommand::StepForward => {
+                self.paused = true;
+                let due = if self.is_finished() { 0 } else { 1 };
+                self.play(due)
+            }
+            PlaybackCommand::StepBack => {
+                self.paused = true;
+                self.seek_to(self.position.saturating_sub(1))
+            }
+            PlaybackCommand::SeekBy(ticks) => {
+                self.seek_to(self.position.saturating_add_signed(ticks))
+            }
+            PlaybackCommand::SeekTo(position) => self.seek_to(position),
+            PlaybackCommand::Faster => {
+                self.playback.speed = match self.playback.speed {
+                    PlaybackSpeed::Multiplier(multiplier) => SPEEDS
+                        .iter()
+                        .find(|speed| **speed > multiplier)
+                        .map_or(PlaybackSpeed::Instant, |speed| {
+                            PlaybackSpeed::Multiplier(*speed)
+                        }),
+                    PlaybackSpeed::Instant => PlaybackSpeed::Instant,
+                };
+                ClockAction::Nothing
+            }
+            PlaybackCommand::Slower => {
+                self.playback.speed = match self.playback.speed {
+                    PlaybackSpeed::Multiplier(multiplier) => PlaybackSpeed::Multiplier(
+                        SPEEDS
+                            .iter()
+                            .rev()
+                            .find(|speed| **speed < multiplier)
+                            .copied()
+                            .unwrap_or(multiplier),
+                    ),
+                    PlaybackSpeed::Instant => PlaybackSpeed::Multiplier(SPEEDS[SPEEDS.len() - 1]),
+                };
+                ClockAction::Nothing
+            }
+        }
+    }
+
+    /// Where playback is, e.g. `1:05 / 10:00, 4x` or `0:12 / 0:30, paused`.
+    pub fn status(&self) -> String {
+        let state = if self.paused {
+            "paused".to_owned()
+        } else if self.is_finished() {
+            "ended".to_owned()
+        } else {
+            match self.playback.speed {
+                PlaybackSpeed::Multiplier(multiplier) => format!("{}x", multiplier),
+                PlaybackSpeed::Instant => "instant".to_owned(),
+            }
+        };
+        format!(
+            "{} / {}, {}",
+            format_position(self.playback.interval.mul_f64(self.position as f64)),
+            format_position(self.playback.interval.mul_f64(self.playback.length as f64)),
+            state
+        )
+    }
+
+    fn is_finished_after(&self, ticks: u64) -> bool {
+        self.position + ticks >= self.playback.length
+    }
+
+    fn play(&mut self, ticks: u64) -> ClockAction {
+        if ticks == 0 {
+            return ClockAction::Nothing;
+        }
+        self.position += ticks;
+        if ticks >= self.history {
+            ClockAction::Seek(self.position)
+        } else {
+            ClockAction::Play(ticks)
+        }
+    }
+
+    fn seek_to(&mut self, position: u64) -> ClockAction {
+        self.position = position.min(self.playback.length);
+        self.banked = Duration::ZERO;
+        ClockAction::Seek(self.position)
+    }
+}
+
+/// Carries out `action` by sampling `source` into `chart`. Returns the
+/// readings of the last tick played, if any.
+pub fn apply_action(
+    action: ClockAction,
+    source: &mut dyn MetricSource,
+    chart: &mut ChartModel,
+) -> metric::Result<Option<Vec<Reading>>> {
+    let ticks = match action {
+        ClockAction::Nothing => return Ok(None),
+        ClockAction::Play(ticks) => ticks,
+        ClockAction::Seek(position) => {
+            let start = position.saturating_sub(chart.point_count() as u64);
+            source.seek(start)?;
+            chart.clear();
+            position - start
+        }
+    };
+    let mut last = None;
+    for _ in 0..ticks {
+        let readings = source.sample()?;
+        chart.add_readings(&readings);
+        last = Some(readings);
+    }
+    Ok(last)
+}
+
+/// Formats a position in a playback as `m:ss`, or `h:mm:ss` past an hour.
+fn format_position(position: Duration) -> String {
+    let seconds = position.as_secs();
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, seconds)
+    } else {
+        format!("{}:{:02}", minutes, seconds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A minute of one-second ticks, charted ten at a time.
+    fn clock(speed: PlaybackSpeed) -> PlaybackClock {
+        let playback = Playback {
+            interval: Duration::from_secs(1),
+            speed,
+            length: 60,
+        };
+        PlaybackClock::new(playback, 10)
+    }
+
+    fn real_time() -> PlaybackClock {
+        clock(PlaybackSpeed::Multiplier(1.0))
+    }
+
+    fn millis(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn plays_a_tick_per_interval() {
+        let mut clock = real_time();
+        assert_eq!(clock.advance(millis(400)), ClockAction::Nothing);
+        assert_eq!(clock.advance(millis(700)), ClockAction::Play(1));
+        // The 100ms left over count towards the next tick.
+        assert_eq!(clock.advance(millis(900)), ClockAction::Play(1));
+        assert_eq!(clock.advance(millis(3000)), ClockAction::Play(3));
+        assert_eq!(clock.status(), "0:05 / 1:00, 1x");
+    }
+
+    #[test]
+    fn falling_behind_seeks() {
+        let mut clock = real_time();
+        assert_eq!(clock.advance(millis(9999)), ClockAction::Play(9));
+        assert_eq!(
+            clock.advance(Duration::from_secs(10)),
+            ClockAction::Seek(19)
+        );
+    }
+
+    #[test]
+    fn stops_at_the_end() {
+        let mut clock = real_time();
+        assert_eq!(
+            clock.advance(Duration::from_secs(55)),
+            ClockAction::Seek(55)
+        );
+        assert_eq!(clock.advance(Duration::from_secs(9)), ClockAction::Play(5));
+        assert!(clock.is_finished());
+        assert_eq!(clock.advance(Duration::from_secs(1)), ClockAction::Nothing);
+        assert_eq!(clock.status(), "1:00 / 1:00, ended");
+    }
+
+    #[test]
+    fn instant_plays_everything() {
+        let mut clock = clock(PlaybackSpeed::Instant);
+        assert_eq!(clock.tick_interval(), Duration::ZERO);
+        assert_eq!(clock.advance(Duration::ZERO), ClockAction::Seek(60));
+        assert!(clock.is_finished());
+    }
+
+    #[test]
+    fn pausing_stops_time() {
+        let mut clock = real_time();
+        clock.advance(millis(1500));
+        assert_eq!(
+            clock.apply(PlaybackCommand::TogglePause),
+            ClockAction::Nothing
+        );
+        assert!(clock.is_paused());
+        assert_eq!(clock.advance(Duration::from_secs(5)), ClockAction::Nothing);
+        assert_eq!(clock.status(), "0:01 / 1:00, paused");
+
+        // Time banked before pausing is forgotten too.
+        clock.apply(PlaybackCommand::TogglePause);
+        assert!(!clock.is_paused());
+        assert_eq!(clock.advance(millis(600)), ClockAction::Nothing);
+        assert_eq!(clock.advance(millis(400)), ClockAction::Play(1));
+    }
+
+    #[test]
+    fn steps_pause() {
+        let mut clock = real_time();
+        assert_eq!(
+            clock.apply(PlaybackCommand::StepForward),
+            ClockAction::Play(1)
+        );
+        assert!(clock.is_paused());
+        assert_eq!(
+            clock.apply(PlaybackCommand::StepForward),
+            ClockAction::Play(1)
+        );
+        assert_eq!(clock.apply(PlaybackCommand::StepBack), ClockAction::Seek(1));
+        assert_eq!(clock.apply(PlaybackCommand::StepBack), ClockAction::Seek(0));
+        assert_eq!(clock.apply(PlaybackCommand::StepBack), ClockAction::Seek(0));
+
+        clock.apply(PlaybackCommand::SeekTo(60));
+        assert_eq!(
+            clock.apply(PlaybackCommand::StepForward),
+            ClockAction::Nothing
+        );
+    }
+
+    #[test]
+    fn seeks_stay_in_the_recording() {
+        let mut clock = real_time();
+        assert_eq!(
+            clock.apply(PlaybackCommand::SeekBy(30)),
+            ClockAction::Seek(30)
+        );
+        assert_eq!(
+            clock.apply(PlaybackCommand::SeekBy(-9)),
+            ClockAction::Seek(21)
+        );
+        assert_eq!(
+            clock.apply(PlaybackCommand::SeekBy(-100)),
+            ClockAction::Seek(0)
+        );
+        assert_eq!(
+            clock.apply(PlaybackCommand::SeekBy(100)),
+            ClockAction::Seek(60)
+        );
+        assert_eq!(
+            clock.apply(PlaybackCommand::SeekTo(1000)),
+            ClockAction::Seek(60)
+        );
+        assert_eq!(
+            clock.apply(PlaybackCommand::SeekTo(5)),
+            ClockAction::Seek(5)
+        );
+        assert!(!clock.is_paused());
+
+        // Seeking forgets time banked towards the next tick.
+        clock.advance(millis(900));
+        clock.apply(PlaybackCommand::SeekTo(5));
+        assert_eq!(clock.advance(millis(900)), ClockAction::Nothing);
+    }
+
+    #[test]
+    fn changes_speed() {
+        let mut clock = real_time();
+        clock.apply(PlaybackCommand::Faster);
+        assert_eq!(clock.tick_interval(), millis(500));
+        assert_eq!(clock.advance(millis(1000)), ClockAction::Play(2));
+        assert_eq!(clock.status(), "0:02 / 1:00, 2x");
+
+        for _ in 0..3 {
+            clock.apply(PlaybackCommand::Slower);
+        }
+        assert_eq!(clock.tick_interval(), Duration::from_secs(4));
+        clock.apply(PlaybackCommand::Slower);
+        assert_eq!(clock.tick_interval(), Duration::from_secs(4));
+        assert_eq!(clock.advance(Duration::from_secs(3)), ClockAction::Nothing);
+        assert_eq!(clock.advance(Duration::from_secs(1)), ClockAction::Play(1));
+    }
+
+    #[test]
+    fn goes_instant_past_the_fastest_speed() {
+        let mut clock = clock(PlaybackSpeed::Multiplier(64.0));
+        clock.apply(PlaybackCommand::Faster);
+        assert_eq!(clock.status(), "0:00 / 1:00, instant");
+        clock.apply(PlaybackCommand::Faster);
+        assert_eq!(clock.tick_interval(), Duration::ZERO);
+        clock.apply(PlaybackCommand::Slower);
+        assert_eq!(clock.status(), "0:00 / 1:00, 64x");
+    }
+
+    #[test]
+    fn formats_positions() {
+        assert_eq!(format_position(Duration::ZERO), "0:00");
+        assert_eq!(format_position(Duration::from_secs(65)), "1:05");
+        assert_eq!(
+            format_position(Duration::from_secs(3 * 3600 + 62)),
+            "3:01:02"
+        );
+    }
+}
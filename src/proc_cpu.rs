@@ -0,0 +1,274 @@
+use std::{
+    fs, io,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    metric::{self, MetricSource, ProcessInfo, Reading, Sample, SampleStatus, Unit},
+    pid::read_process_name,
+};
+
+/// CPU utilization of a single process, read from /proc/<pid>/stat. Like
+/// Task Manager, 100% means every CPU on the machine is busy.
+pub struct ProcCpuSource {
+    root: PathBuf,
+    process_id: u32,
+    name: String,
+    cpu_count: usize,
+    /// USER_HZ, the unit of the times in /proc.
+    ticks_per_second: f64,
+    last: Option<(u64, Instant)>,
+}
+
+impl ProcCpuSource {
+    pub fn new(process_id: u32) -> io::Result<Self> {
+        Self::with_root("/proc", process_id)
+    }
+
+    /// Reads from a /proc tree rooted somewhere other than /proc.
+    pub fn with_root<P: Into<PathBuf>>(root: P, process_id: u32) -> io::Result<Self> {
+        let root = root.into();
+        let name = read_process_name(&root, process_id)?;
+        let cpu_count = count_cpus(&fs::read_to_string(root.join("stat"))?);
+        Ok(Self {
+            root,
+            process_id,
+            name,
+            cpu_count,
+            ticks_per_second: clock_ticks_per_second()?,
+            last: None,
+        })
+    }
+
+    fn read_ticks(&self) -> io::Result<u64> {
+        let path = self.root.join(self.process_id.to_string()).join("stat");
+        let stat = fs::read_to_string(&path)?;
+        parse_process_ticks(&stat).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unexpected format in {}", path.display()),
+            )
+        })
+    }
+
+    /// Samples the process's utilization since the last sample, as of
+    /// `now`.
+    fn sample_at(&mut self, now: Instant) -> metric::Result<Vec<Reading>> {
+        let ticks = match self.read_ticks() {
+            Ok(ticks) => ticks,
+            // The process exited.
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                self.last = None;
+                let sample = Sample::missing(SampleStatus::NoInstance);
+                return Ok(vec![Reading::new("CPU", sample)]);
+            }
+            Err(error) => return Err(error.into()),
+        };
+        let sample = match self.last {
+            Some((last_ticks, last_time)) => Sample::valid(utilization(
+                ticks.saturating_sub(last_ticks),
+                now - last_time,
+                self.cpu_count,
+                self.ticks_per_second,
+            )),
+            None => Sample::missing(SampleStatus::Invalid),
+        };
+        self.last = Some((ticks, now));
+        Ok(vec![Reading::new("CPU", sample)])
+    }
+}
+
+impl MetricSource for ProcCpuSource {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn unit(&self) -> Unit {
+        Unit::Percent
+    }
+
+    fn start(&mut self) -> metric::Result<()> {
+        self.last = Some((self.read_ticks()?, Instant::now()));
+        Ok(())
+    }
+
+    fn sample(&mut self) -> metric::Result<Vec<Reading>> {
+        self.sample_at(Instant::now())
+    }
+
+    fn close(self: Box<Self>) -> metric::Result<()> {
+        Ok(())
+    }
+
+    fn process(&self) -> Option<ProcessInfo> {
+        Some(ProcessInfo {
+            process_id: self.process_id,
+            name: self.name.clone(),
+        })
+    }
+}
+
+/// Returns utime + stime from the contents of /proc/<pid>/stat.
+pub fn parse_process_ticks(stat: &str) -> Option<u64> {
+    // The command name is in parentheses and may itself contain spaces or
+    // parentheses, so start after the last ')'. The first field after it is
+    // the state (field 3), which puts utime and stime (fields 14 and 15) at
+    // offsets 11 and 12.
+    let (_, fields) = stat.rsplit_once(')')?;
+    let mut fields = fields.split_whitespace().skip(11);
+    let user: u64 = fields.next()?.parse().ok()?;
+    let system: u64 = fields.next()?.parse().ok()?;
+    Some(user + system)
+}
+
+/// Counts the per-CPU lines in the contents of /proc/stat.
+pub fn count_cpus(stat: &str) -> usize {
+    let count = stat
+        .lines()
+        .filter(|line| {
+            line.strip_prefix("cpu")
+                .and_then(|rest| rest.chars().next())
+                .is_some_and(|c| c.is_ascii_digit())
+        })
+        .count();
+    count.max(1)
+}
+
+/// Returns USER_HZ, which is 100 almost everywhere but not guaranteed to
+/// be.
+fn clock_ticks_per_second() -> io::Result<f64> {
+    // SAFETY: sysconf has no preconditions.
+    let ticks = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+    if ticks <= 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(ticks as f64)
+}
+
+/// Converts a delta in clock ticks over `elapsed` wall clock time into a
+/// percentage of the machine's total CPU time.
+pub fn utilization(ticks: u64, elapsed: Duration, cpu_count: usize, ticks_per_second: f64) -> f64 {
+    let elapsed = elapsed.as_secs_f64();
+    if elapsed <= 0.0 {
+        return 0.0;
+    }
+    let busy = ticks as f64 / ticks_per_second;
+    (busy / (elapsed * cpu_count as f64) * 100.0).clamp(0.0, 100.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake_proc::FakeProc;
+
+    const PROCESS_ID: u32 = 1234;
+
+    const STAT: &str = "cpu  10132153 290696 3084719 46828483 16683 0 25195 0 0 0\n\
+                        cpu0 1393280 32966 572056 13343292 6130 0 17875 0 0 0\n\
+                        cpu1 1335985 28632 404717 13464225 3489 0 2099 0 0 0\n\
+                        cpu2 1354913 29862 392233 13497312 3286 0 1905 0 0 0\n\
+                        cpu3 1384214 28963 405285 13424654 3776 0 1593 0 0 0\n\
+                        intr 1462898 0 9 0\nctxt 34829572\nbtime 1700000000\n\
+                        processes 26442\nprocs_running 1\nprocs_blocked 0\n";
+
+    /// A /proc/<pid>/stat line for a process with an awkward name, which
+    /// spent `user` and `system` ticks on the CPU.
+    fn process_stat(user: u64, system: u64) -> String {
+        format!(
+            "{} (Web (Content) 2) S 1 1234 1234 0 -1 4194560 42096 0 1 0 {} {} 0 0 20 0 \
+             31 0 8127 2983772160 61347 18446744073709551615 1 1 0 0 0 0 0 4096 1260 0 0 0 \
+             17 2 0 0 0 0 0\n",
+            PROCESS_ID, user, system
+        )
+    }
+
+    fn fake_process(user: u64, system: u64) -> FakeProc {
+        let proc = FakeProc::new();
+        proc.write("stat", STAT);
+        proc.write(&format!("{}/comm", PROCESS_ID), "Web (Content) 2\n");
+        proc.write(&format!("{}/stat", PROCESS_ID), &process_stat(user, system));
+        proc
+    }
+
+    #[test]
+    fn parses_process_ticks() {
+        assert_eq!(parse_process_ticks(&process_stat(150, 25)), Some(175));
+        assert_eq!(
+            parse_process_ticks("1 (a) b) c) S 1 1 1 0 -1 0 0 0 0 0 7 8 0 0"),
+            Some(15)
+        );
+        assert_eq!(parse_process_ticks("1 (bash) S 1 1 1"), None);
+        assert_eq!(
+            parse_process_ticks("1 bash S 1 1 1 0 -1 0 0 0 0 0 7 8"),
+            None
+        );
+        assert_eq!(parse_process_ticks(""), None);
+    }
+
+    #[test]
+    fn counts_cpus() {
+        assert_eq!(count_cpus(STAT), 4);
+        // Only the total line, as in some containers.
+        assert_eq!(count_cpus("cpu  1 2 3 4\nintr 0\n"), 1);
+    }
+
+    #[test]
+    fn converts_ticks_to_percentages() {
+        let second = Duration::from_secs(1);
+        assert_eq!(utilization(100, second, 4, 100.0), 25.0);
+        assert_eq!(utilization(250, second, 4, 250.0), 25.0);
+        assert_eq!(utilization(1000, second, 4, 100.0), 100.0);
+        assert_eq!(utilization(100, Duration::ZERO, 4, 100.0), 0.0);
+    }
+
+    #[test]
+    fn reads_clock_ticks() {
+        let ticks = clock_ticks_per_second().unwrap();
+        assert!(ticks >= 1.0);
+    }
+
+    #[test]
+    fn samples_a_process() {
+        let proc = fake_process(150, 25);
+        let mut source = ProcCpuSource::with_root(proc.root(), PROCESS_ID).unwrap();
+        assert_eq!(source.name(), "Web (Content) 2");
+        assert_eq!(source.cpu_count, 4);
+        assert_eq!(source.read_ticks().unwrap(), 175);
+
+        // 50 ticks at 100 a second in 2 seconds on 4 CPUs.
+        source.ticks_per_second = 100.0;
+        source.start().unwrap();
+        let (_, started) = source.last.unwrap();
+        proc.write(&format!("{}/stat", PROCESS_ID), &process_stat(190, 35));
+        assert_eq!(
+            source.sample_at(started + Duration::from_secs(2)).unwrap(),
+            [Reading::new("CPU", Sample::valid(6.25))]
+        );
+
+        proc.remove(&PROCESS_ID.to_string());
+        assert_eq!(
+            source.sample().unwrap(),
+            [Reading::new(
+                "CPU",
+                Sample::missing(SampleStatus::NoInstance)
+            )]
+        );
+    }
+
+    #[test]
+    fn rejects_unexpected_stat_files() {
+        let proc = fake_process(0, 0);
+        proc.write(&format!("{}/stat", PROCESS_ID), "1234 (Web Content) S 1\n");
+        let source = ProcCpuSource::with_root(proc.root(), PROCESS_ID).unwrap();
+        let error = source.read_ticks().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn needs_the_process() {
+        let proc = FakeProc::new();
+        proc.write("stat", STAT);
+        assert!(ProcCpuSource::with_root(proc.root(), PROCESS_ID).is_err());
+    }
+}
@@ -0,0 +1,427 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::Path,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    metric::{
+        self, MetricSource, Playback, ProcessInfo, Reading, SampleStatus, SeriesOrigin, Unit,
+    },
+    recording_file::{RecordingHeader, RecordingWriter},
+    trace_export::TraceWriter,
+};
+
+const CSV_HEADER: &str = "timestamp,elapsed,series,value,status";
+
+/// Writes samples as CSV, one row per reading:
+/// `timestamp,elapsed,series,value,status`. Timestamps are UTC in RFC 3339
+/// format, elapsed times are in seconds, and missing values are left
+/// empty.
+pub struct CsvRecorder<W: Write> {
+    writer: W,
+}
+
+impl CsvRecorder<BufWriter<File>> {
+    /// Appends to the file at `path`, creating it with a header row if it
+    /// doesn't exist yet.
+    pub fn append(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let is_empty = file.metadata()?.len() == 0;
+        Self::new(BufWriter::new(file), is_empty)
+    }
+}
+
+impl<W: Write> CsvRecorder<W> {
+    pub fn new(mut writer: W, write_header: bool) -> io::Result<Self> {
+        if write_header {
+            writeln!(writer, "{}", CSV_HEADER)?;
+        }
+        Ok(Self { writer })
+    }
+
+    /// Writes one tick worth of readings, all taken at `timestamp`.
+    pub fn record(
+        &mut self,
+        timestamp: SystemTime,
+        elapsed: Duration,
+        readings: &[Reading],
+    ) -> io::Result<()> {
+        let timestamp = format_timestamp(timestamp);
+        for reading in readings {
+            let value = match reading.sample.value {
+                Some(value) => value.to_string(),
+                None => String::new(),
+            };
+            writeln!(
+                self.writer,
+                "{},{:.3},{},{},{}",
+                timestamp,
+                elapsed.as_secs_f64(),
+                escape_csv_field(&reading.series),
+                value,
+                status_name(reading.sample.status)
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Somewhere samples get written to as they come in.
+trait SampleSink {
+    fn write_tick(
+        &mut self,
+        source: &dyn MetricSource,
+        elapsed: Duration,
+        readings: &[Reading],
+    ) -> io::Result<()>;
+    fn finish(self: Box<Self>) -> io::Result<()>;
+}
+
+impl SampleSink for CsvRecorder<BufWriter<File>> {
+    fn write_tick(
+        &mut self,
+        _source: &dyn MetricSource,
+        elapsed: Duration,
+        readings: &[Reading],
+    ) -> io::Result<()> {
+        self.record(SystemTime::now(), elapsed, readings)
+    }
+
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        CsvRecorder::finish(*self)?;
+        Ok(())
+    }
+}
+
+impl SampleSink for RecordingWriter<BufWriter<File>> {
+    fn write_tick(
+        &mut self,
+        source: &dyn MetricSource,
+        elapsed: Duration,
+        readings: &[Reading],
+    ) -> io::Result<()> {
+        RecordingWriter::write_tick(self, elapsed, readings, |series| {
+            source.series_origin(series)
+        })
+    }
+
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        RecordingWriter::finish(*self)?;
+        Ok(())
+    }
+}
+
+impl SampleSink for TraceWriter<BufWriter<File>> {
+    fn write_tick(
+        &mut self,
+        source: &dyn MetricSource,
+        elapsed: Duration,
+        readings: &[Reading],
+    ) -> io::Result<()> {
+        TraceWriter::write_tick(self, elapsed, readings, |series| {
+            source.series_origin(series)
+        })
+    }
+
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        TraceWriter::finish(*self)?;
+        Ok(())
+    }
+}
+
+/// Passes samples through from another source, recording each one along
+/// the way. The recording is finished when the source is closed.
+pub struct RecordingSource {
+    source: Box<dyn MetricSource>,
+    sink: Box<dyn SampleSink>,
+    start: Instant,
+    /// The tick a playback is at, which is what its samples are timed by.
+    position: u64,
+}
+
+impl RecordingSource {
+    /// Records to a CSV file if `path` ends in `.csv`, to a Chrome trace
+    /// if it ends in `.json`, and to a binary recording otherwise. CSV
+    /// files are appended to, while the others are overwritten.
+    /// `interval` is how often the source will be sampled.
+    pub fn new(source: Box<dyn MetricSource>, path: &Path, interval: Duration) -> io::Result<Self> {
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_ascii_lowercase());
+        let sink: Box<dyn SampleSink> = match extension.as_deref() {
+            Some("csv") => Box::new(CsvRecorder::append(path)?),
+            Some("json") => Box::new(TraceWriter::new(BufWriter::new(File::create(path)?))?),
+            _ => {
+                let header = RecordingHeader {
+                    name: source.name().to_owned(),
+                    unit: source.unit(),
+                    interval,
+                    start_time: SystemTime::now(),
+                };
+                let file = BufWriter::new(File::create(path)?);
+                Box::new(RecordingWriter::new(file, &header)?)
+            }
+        };
+        Ok(Self {
+            source,
+            sink,
+            start: Instant::now(),
+            position: 0,
+        })
+    }
+
+    /// How long after the start the next sample is. Playbacks go by when
+    /// their samples were taken, however fast they're played.
+    fn elapsed(&self) -> Duration {
+        match self.source.playback() {
+            Some(playback) => playback
+                .interval
+                .saturating_mul(self.position.try_into().unwrap_or(u32::MAX)),
+            None => self.start.elapsed(),
+        }
+    }
+}
+
+/// Records all of a playback as fast as it can be read, e.g. to convert
+/// it to another format.
+pub fn convert(mut source: Box<dyn MetricSource>) -> metric::Result<()> {
+    source.start()?;
+    while !source.is_finished() {
+        source.sample()?;
+    }
+    source.close()
+}
+
+impl MetricSource for RecordingSource {
+    fn name(&self) -> &str {
+        self.source.name()
+    }
+
+    fn unit(&self) -> Unit {
+        self.source.unit()
+    }
+
+    fn start(&mut self) -> metric::Result<()> {
+        self.source.start()
+    }
+
+    fn sample(&mut self) -> metric::Result<Vec<Reading>> {
+        let elapsed = self.elapsed();
+        let readings = self.source.sample()?;
+        self.position += 1;
+        self.sink
+            .write_tick(self.source.as_ref(), elapsed, &readings)?;
+        Ok(readings)
+    }
+
+    fn close(self: Box<Self>) -> metric::Result<()> {
+        // Keep what was recorded even if the source fails to close.
+        let finished = self.sink.finish();
+        self.source.close()?;
+        finished?;
+        Ok(())
+    }
+
+    fn process(&self) -> Option<ProcessInfo> {
+        self.source.process()
+    }
+
+    fn series_origin(&self, series: &str) -> SeriesOrigin {
+        self.source.series_origin(series)
+    }
+
+    fn playback(&self) -> Option<Playback> {
+        self.source.playback()
+    }
+
+    fn is_finished(&self) -> bool {
+        self.source.is_finished()
+    }
+
+    fn warning(&self) -> Option<String> {
+        self.source.warning()
+    }
+
+    fn seek(&mut self, position: u64) -> metric::Result<()> {
+        self.source.seek(position)?;
+        self.position = position;
+        Ok(())
+    }
+}
+
+fn status_name(status: SampleStatus) -> &'static str {
+    match status {
+        SampleStatus::Valid => "valid",
+        SampleStatus::Stale => "stale",
+        SampleStatus::Invalid => "invalid",
+        SampleStatus::NoInstance => "no_instance",
+    }
+}
+
+/// Quotes a field if it contains anything CSV gives a meaning to, doubling
+/// any quotes inside it.
+fn escape_csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+/// Formats a time as UTC with millisecond precision, e.g.
+/// `2024-05-01T13:45:30.250Z`. Times before 1970 are clamped to it.
+fn format_timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = since_epoch.as_secs();
+    let (year, month, day) = civil_from_days((seconds / 86400) as i64);
+    let seconds_of_day = seconds % 86400;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        seconds_of_day / 3600,
+        seconds_of_day / 60 % 60,
+        seconds_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+/// Turns days since 1970-01-01 into a (year, month, day) date, using
+/// Howard Hinnant's algorithm for the proleptic Gregorian calendar.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metric::Sample;
+
+    fn at(seconds: u64, millis: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(seconds) + Duration::from_millis(millis)
+    }
+
+    fn recorded(write_header: bool, readings: &[Reading]) -> String {
+        let mut recorder = CsvRecorder::new(Vec::new(), write_header).unwrap();
+        recorder
+            .record(
+                at(1_714_571_130, 250),
+                Duration::from_millis(1500),
+                readings,
+            )
+            .unwrap();
+        String::from_utf8(recorder.finish().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn records_a_row_per_reading() {
+        let readings = [
+            Reading::new("3D", Sample::valid(12.5)),
+            Reading::new("Copy", Sample::missing(SampleStatus::NoInstance)),
+            Reading::new(
+                "Video, \"Decode\"",
+                Sample {
+                    value: Some(3.0),
+                    status: SampleStatus::Stale,
+                },
+            ),
+            Reading::new("Other", Sample::missing(SampleStatus::Invalid)),
+        ];
+        assert_eq!(
+            recorded(true, &readings),
+            "timestamp,elapsed,series,value,status\n\
+             2024-05-01T13:45:30.250Z,1.500,3D,12.5,valid\n\
+             2024-05-01T13:45:30.250Z,1.500,Copy,,no_instance\n\
+             2024-05-01T13:45:30.250Z,1.500,\"Video, \"\"Decode\"\"\",3,stale\n\
+             2024-05-01T13:45:30.250Z,1.500,Other,,invalid\n"
+        );
+    }
+
+    #[test]
+    fn writes_the_header_only_to_new_files() {
+        assert_eq!(recorded(false, &[]), "");
+
+        let path = std::env::temp_dir().join(format!("chartfun-{}.csv", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        for value in [1.0, 2.0] {
+            let mut recorder = CsvRecorder::append(&path).unwrap();
+            let readings = [Reading::new("3D", Sample::valid(value))];
+            recorder
+                .record(at(0, 0), Duration::ZERO, &readings)
+                .unwrap();
+            recorder.finish().unwrap();
+        }
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            contents,
+            "timestamp,elapsed,series,value,status\n\
+             1970-01-01T00:00:00.000Z,0.000,3D,1,valid\n\
+             1970-01-01T00:00:00.000Z,0.000,3D,2,valid\n"
+        );
+    }
+
+    #[test]
+    fn quotes_fields_only_when_needed() {
+        assert_eq!(escape_csv_field("3D"), "3D");
+        assert_eq!(escape_csv_field("a,b"), "\"a,b\"");
+        assert_eq!(escape_csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(escape_csv_field("two\nlines"), "\"two\nlines\"");
+    }
+
+    #[test]
+    fn formats_timestamps() {
+        assert_eq!(format_timestamp(at(0, 0)), "1970-01-01T00:00:00.000Z");
+        // The last second of a leap day.
+        assert_eq!(
+            format_timestamp(at(1_709_251_199, 999)),
+            "2024-02-29T23:59:59.999Z"
+        );
+        // Times before 1970 are clamped.
+        assert_eq!(
+            format_timestamp(UNIX_EPOCH - Duration::from_secs(1)),
+            "1970-01-01T00:00:00.000Z"
+        );
+    }
+
+    #[test]
+    fn converts_days_to_dates() {
+        let cases = [
+            (-1, (1969, 12, 31)),
+            (0, (1970, 1, 1)),
+            (11016, (2000, 2, 29)),
+            (19722, (2023, 12, 31)),
+            (19723, (2024, 1, 1)),
+            (19782, (2024, 2, 29)),
+            (19783, (2024, 3, 1)),
+            // 2100 isn't a leap year.
+            (47540, (2100, 2, 28)),
+            (47541, (2100, 3, 1)),
+        ];
+        for (days, date) in cases {
+            assert_eq!(civil_from_days(days), date, "{}", days);
+        }
+    }
+}
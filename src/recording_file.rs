@@ -0,0 +1,1032 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    io::{self, Read, Seek, SeekFrom, Write},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::metric::{ProcessInfo, Reading, Sample, SampleStatus, SeriesOrigin, Unit};
+
+// A recording starts with `MAGIC` and the format version, followed by a
+// sequence of blocks. Every block is a kind byte, the payload length as a
+// u32, the payload and a CRC-32 of everything before it in the block. All
+// numbers are little endian.
+//
+// - A header block comes first, with the name and unit of the source, the
+//   sample interval and when recording started.
+// - A series block describes a series before the first chunk that uses
+//   it.
+// - A chunk block holds up to `TICKS_PER_CHUNK` ticks. Values are XORed
+//   with the previous value of their series and elapsed times are stored
+//   as deltas, both starting over in every chunk so chunks can be decoded
+//   on their own.
+// - An index block comes last, listing where every series and chunk
+//   block is. It is followed by its offset and `INDEX_MAGIC`, so readers
+//   can find it from the end of the file.
+//
+// A recording that was never finished has no index. Readers rebuild it by
+// scanning the blocks, ignoring a block cut off at the end of the file.
+
+const MAGIC: [u8; 8] = *b"CHARTREC";
+const INDEX_MAGIC: [u8; 8] = *b"CHARTIDX";
+pub const VERSION: u16 = 1;
+
+// Seeking decodes at most one chunk, and an unfinished recording loses at
+// most one chunk.
+const TICKS_PER_CHUNK: u32 = 256;
+
+const BLOCK_HEADER: u8 = 0;
+const BLOCK_SERIES: u8 = 1;
+const BLOCK_CHUNK: u8 = 2;
+const BLOCK_INDEX: u8 = 3;
+// The kind and length before a block's payload.
+const BLOCK_PREFIX_LEN: u64 = 5;
+const BLOCK_CHECKSUM_LEN: u64 = 4;
+// The index offset and `INDEX_MAGIC` at the very end.
+const TRAILER_LEN: u64 = 16;
+
+// Flags of each reading in a chunk. The low two bits are the status.
+const STATUS_MASK: u8 = 0b0011;
+const HAS_VALUE: u8 = 0b0100;
+const SAME_VALUE: u8 = 0b1000;
+
+#[derive(Debug)]
+pub enum RecordingError {
+    Io(io::Error),
+    NotARecording,
+    UnsupportedVersion(u16),
+    /// The file is damaged, e.g. a checksum doesn't match.
+    Corrupt(&'static str),
+}
+
+impl fmt::Display for RecordingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecordingError::Io(error) => write!(f, "Failed to read the recording: {}", error),
+            RecordingError::NotARecording => write!(f, "The file isn't a chartfun recording!"),
+            RecordingError::UnsupportedVersion(version) => {
+                write!(f, "Recording version {} isn't supported!", version)
+            }
+            RecordingError::Corrupt(reason) => write!(f, "The recording is corrupt: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for RecordingError {}
+
+impl From<io::Error> for RecordingError {
+    fn from(error: io::Error) -> Self {
+        RecordingError::Io(error)
+    }
+}
+
+/// What was recorded, and when.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecordingHeader {
+    /// The name of the source, shown as the title when played back.
+    pub name: String,
+    pub unit: Unit,
+    /// How far apart the samples were meant to be taken.
+    pub interval: Duration,
+    pub start_time: SystemTime,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SeriesInfo {
+    pub name: String,
+    pub unit: Unit,
+    pub origin: SeriesOrigin,
+}
+
+/// The readings of one tick, `elapsed` after recording started.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecordedTick {
+    pub elapsed: Duration,
+    pub readings: Vec<Reading>,
+}
+
+/// Where a chunk is, and the ticks in it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ChunkEntry {
+    pub first_tick: u64,
+    pub tick_count: u32,
+    /// When the chunk's first tick was taken.
+    pub elapsed: Duration,
+    offset: u64,
+}
+
+/// Writes a recording as samples come in. Nothing is written for the
+/// ticks of a chunk until it fills up or the recording is finished.
+pub struct RecordingWriter<W: Write> {
+    writer: W,
+    offset: u64,
+    unit: Unit,
+    series_ids: HashMap<String, u32>,
+    series_offsets: Vec<u64>,
+    chunks: Vec<ChunkEntry>,
+    chunk: ChunkEncoder,
+    tick_count: u64,
+}
+
+impl<W: Write> RecordingWriter<W> {
+    pub fn new(mut writer: W, header: &RecordingHeader) -> io::Result<Self> {
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        let mut offset = (MAGIC.len() + 2) as u64;
+
+        let mut payload = Vec::new();
+        put_str(&mut payload, &header.name);
+        payload.push(encode_unit(header.unit));
+        put_u64(&mut payload, header.interval.as_nanos() as u64);
+        let start_time = header
+            .start_time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        put_u64(&mut payload, start_time.as_micros() as u64);
+        offset += write_block(&mut writer, BLOCK_HEADER, &payload)?;
+
+        Ok(Self {
+            writer,
+            offset,
+            unit: header.unit,
+            series_ids: HashMap::new(),
+            series_offsets: Vec::new(),
+            chunks: Vec::new(),
+            chunk: ChunkEncoder::new(0, Duration::ZERO),
+            tick_count: 0,
+        })
+    }
+
+    /// Adds a tick. `origin` describes series that haven't been seen
+    /// before.
+    pub fn write_tick<F: Fn(&str) -> SeriesOrigin>(
+        &mut self,
+        elapsed: Duration,
+        readings: &[Reading],
+        origin: F,
+    ) -> io::Result<()> {
+        if self.chunk.tick_count == 0 {
+            self.chunk = ChunkEncoder::new(self.tick_count, elapsed);
+        }
+        let mut ids = Vec::with_capacity(readings.len());
+        for reading in readings {
+            let id = match self.series_ids.get(&reading.series) {
+                Some(id) => *id,
+                None => self.add_series(&reading.series, origin(&reading.series))?,
+            };
+            ids.push(id);
+        }
+        self.chunk.push(elapsed, ids.into_iter().zip(readings));
+        self.tick_count += 1;
+        if self.chunk.tick_count == TICKS_PER_CHUNK {
+            self.write_chunk()?;
+        }
+        Ok(())
+    }
+
+    /// Writes the last chunk and the index.
+    pub fn finish(mut self) -> io::Result<W> {
+        if self.chunk.tick_count > 0 {
+            self.write_chunk()?;
+        }
+        let mut payload = Vec::new();
+        put_varint(&mut payload, self.series_offsets.len() as u64);
+        for offset in &self.series_offsets {
+            put_u64(&mut payload, *offset);
+        }
+        put_varint(&mut payload, self.chunks.len() as u64);
+        for chunk in &self.chunks {
+            put_u64(&mut payload, chunk.first_tick);
+            put_varint(&mut payload, chunk.tick_count as u64);
+            put_u64(&mut payload, chunk.elapsed.as_micros() as u64);
+            put_u64(&mut payload, chunk.offset);
+        }
+        let index_offset = self.offset;
+        self.offset += write_block(&mut self.writer, BLOCK_INDEX, &payload)?;
+        self.writer.write_all(&index_offset.to_le_bytes())?;
+        self.writer.write_all(&INDEX_MAGIC)?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    fn add_series(&mut self, name: &str, origin: SeriesOrigin) -> io::Result<u32> {
+        let id = self.series_offsets.len() as u32;
+        let info = SeriesInfo {
+            name: name.to_owned(),
+            unit: self.unit,
+            origin,
+        };
+        self.series_offsets.push(self.offset);
+        self.offset += write_block(&mut self.writer, BLOCK_SERIES, &encode_series(id, &info))?;
+        self.series_ids.insert(name.to_owned(), id);
+        Ok(id)
+    }
+
+    fn write_chunk(&mut self) -> io::Result<()> {
+        let chunk = std::mem::replace(&mut self.chunk, ChunkEncoder::new(0, Duration::ZERO));
+        self.chunks.push(ChunkEntry {
+            first_tick: chunk.first_tick,
+            tick_count: chunk.tick_count,
+            elapsed: chunk.first_elapsed,
+            offset: self.offset,
+        });
+        self.offset += write_block(&mut self.writer, BLOCK_CHUNK, &chunk.finish())?;
+        Ok(())
+    }
+}
+
+/// Builds the payload of a chunk block one tick at a time.
+struct ChunkEncoder {
+    first_tick: u64,
+    first_elapsed: Duration,
+    last_elapsed: Duration,
+    tick_count: u32,
+    /// The bits of the last value of each series, by id.
+    last_values: HashMap<u32, u64>,
+    ticks: Vec<u8>,
+}
+
+impl ChunkEncoder {
+    fn new(first_tick: u64, first_elapsed: Duration) -> Self {
+        Self {
+            first_tick,
+            first_elapsed,
+            last_elapsed: first_elapsed,
+            tick_count: 0,
+            last_values: HashMap::new(),
+            ticks: Vec::new(),
+        }
+    }
+
+    fn push<'a, I: ExactSizeIterator<Item = (u32, &'a Reading)>>(
+        &mut self,
+        elapsed: Duration,
+        readings: I,
+    ) {
+        let delta = elapsed.saturating_sub(self.last_elapsed);
+        put_varint(&mut self.ticks, delta.as_micros() as u64);
+        self.last_elapsed = self.last_elapsed.max(elapsed);
+
+        put_varint(&mut self.ticks, readings.len() as u64);
+        for (id, reading) in readings {
+            put_varint(&mut self.ticks, id as u64);
+            let mut flags = encode_status(reading.sample.status);
+            let Some(value) = reading.sample.value else {
+                self.ticks.push(flags);
+                continue;
+            };
+            flags |= HAS_VALUE;
+            let bits = value.to_bits();
+            let last = self.last_values.insert(id, bits).unwrap_or(0);
+            // Values that barely change share their sign, exponent and
+            // high mantissa bits, which leaves few bits to store.
+            let xor = bits ^ last;
+            if xor == 0 {
+                self.ticks.push(flags | SAME_VALUE);
+                continue;
+            }
+            let trailing_zeros = xor.trailing_zeros();
+            self.ticks.push(flags);
+            self.ticks.push(trailing_zeros as u8);
+            put_varint(&mut self.ticks, xor >> trailing_zeros);
+        }
+        self.tick_count += 1;
+    }
+
+    fn finish(self) -> Vec<u8> {
+        let mut payload = Vec::with_capacity(self.ticks.len() + 24);
+        put_u64(&mut payload, self.first_tick);
+        put_varint(&mut payload, self.tick_count as u64);
+        put_u64(&mut payload, self.first_elapsed.as_micros() as u64);
+        payload.extend_from_slice(&self.ticks);
+        payload
+    }
+}
+
+/// Reads a recording, one chunk at a time.
+pub struct RecordingReader<R: Read + Seek> {
+    reader: R,
+    header: RecordingHeader,
+    /// By id.
+    series: Vec<SeriesInfo>,
+    chunks: Vec<ChunkEntry>,
+    /// The length of the file, which no block can reach past.
+    len: u64,
+}
+
+impl<R: Read + Seek> RecordingReader<R> {
+    pub fn new(mut reader: R) -> Result<Self, RecordingError> {
+        let mut magic = [0; 8];
+        let len = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(0))?;
+        if let Err(error) = reader.read_exact(&mut magic) {
+            return Err(match error.kind() {
+                io::ErrorKind::UnexpectedEof => RecordingError::NotARecording,
+                _ => error.into(),
+            });
+        }
+        if magic != MAGIC {
+            return Err(RecordingError::NotARecording);
+        }
+        let mut version = [0; 2];
+        reader.read_exact(&mut version)?;
+        let version = u16::from_le_bytes(version);
+        if version != VERSION {
+            return Err(RecordingError::UnsupportedVersion(version));
+        }
+
+        let header = match read_block(&mut reader, len)? {
+            Some((BLOCK_HEADER, payload)) => decode_header(&payload)?,
+            _ => return Err(RecordingError::Corrupt("the header is missing")),
+        };
+        let blocks_start = reader.stream_position()?;
+
+        let mut recording = Self {
+            reader,
+            header,
+            series: Vec::new(),
+            chunks: Vec::new(),
+            len,
+        };
+        match recording.find_index()? {
+            Some(index_offset) => recording.read_index(index_offset)?,
+            None => recording.scan_blocks(blocks_start)?,
+        }
+        Ok(recording)
+    }
+
+    pub fn header(&self) -> &RecordingHeader {
+        &self.header
+    }
+
+    pub fn series(&self) -> &[SeriesInfo] {
+        &self.series
+    }
+
+    pub fn chunks(&self) -> &[ChunkEntry] {
+        &self.chunks
+    }
+
+    /// How many ticks were recorded.
+    pub fn tick_count(&self) -> u64 {
+        self.chunks
+            .last()
+            .map_or(0, |chunk| chunk.first_tick + chunk.tick_count as u64)
+    }
+
+    /// The index of the chunk holding `tick`, if it was recorded.
+    pub fn find_chunk(&self, tick: u64) -> Option<usize> {
+        let index = self
+            .chunks
+            .partition_point(|chunk| chunk.first_tick <= tick)
+            .checked_sub(1)?;
+        let chunk = &self.chunks[index];
+        (tick < chunk.first_tick + chunk.tick_count as u64).then_some(index)
+    }
+
+    pub fn read_chunk(&mut self, index: usize) -> Result<Vec<RecordedTick>, RecordingError> {
+        let entry = self.chunks[index];
+        self.reader.seek(SeekFrom::Start(entry.offset))?;
+        let payload = match read_block(&mut self.reader, self.len)? {
+            Some((BLOCK_CHUNK, payload)) => payload,
+            _ => {
+                return Err(RecordingError::Corrupt(
+                    "the index points to a missing chunk",
+                ))
+            }
+        };
+        let ticks = decode_chunk(&payload, &self.series)?;
+        // Seeking trusts the index to say how many ticks a chunk holds.
+        if ticks.len() != entry.tick_count as usize {
+            return Err(RecordingError::Corrupt(
+                "a chunk doesn't hold as many ticks as the index says",
+            ));
+        }
+        Ok(ticks)
+    }
+
+    /// The offset of the index, if the recording was finished.
+    fn find_index(&mut self) -> Result<Option<u64>, RecordingError> {
+        let len = self.len;
+        if len < TRAILER_LEN {
+            return Ok(None);
+        }
+        self.reader.seek(SeekFrom::End(-(TRAILER_LEN as i64)))?;
+        let mut trailer = [0; TRAILER_LEN as usize];
+        self.reader.read_exact(&mut trailer)?;
+        if trailer[8..] != INDEX_MAGIC {
+            return Ok(None);
+        }
+        let offset = u64::from_le_bytes(trailer[..8].try_into().unwrap());
+        if offset >= len - TRAILER_LEN {
+            return Err(RecordingError::Corrupt("the index is out of bounds"));
+        }
+        Ok(Some(offset))
+    }
+
+    fn read_index(&mut self, offset: u64) -> Result<(), RecordingError> {
+        self.reader.seek(SeekFrom::Start(offset))?;
+        let payload = match read_block(&mut self.reader, self.len)? {
+            Some((BLOCK_INDEX, payload)) => payload,
+            _ => return Err(RecordingError::Corrupt("the index is missing")),
+        };
+        let mut bytes = ByteReader::new(&payload);
+        let series_offsets = (0..bytes.varint()?)
+            .map(|_| bytes.u64())
+            .collect::<Result<Vec<_>, _>>()?;
+        for _ in 0..bytes.varint()? {
+            self.chunks.push(ChunkEntry {
+                first_tick: bytes.u64()?,
+                tick_count: bytes.varint()? as u32,
+                elapsed: Duration::from_micros(bytes.u64()?),
+                offset: bytes.u64()?,
+            });
+        }
+        for offset in series_offsets {
+            self.reader.seek(SeekFrom::Start(offset))?;
+            match read_block(&mut self.reader, self.len)? {
+                Some((BLOCK_SERIES, payload)) => self.add_series(&payload)?,
+                _ => {
+                    return Err(RecordingError::Corrupt(
+                        "the index points to a missing series",
+                    ))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Rebuilds the index of a recording that was never finished.
+    fn scan_blocks(&mut self, mut offset: u64) -> Result<(), RecordingError> {
+        let len = self.len;
+        self.reader.seek(SeekFrom::Start(offset))?;
+        while offset + BLOCK_PREFIX_LEN <= len {
+            let mut prefix = [0; BLOCK_PREFIX_LEN as usize];
+            self.reader.read_exact(&mut prefix)?;
+            let payload_len = u32::from_le_bytes(prefix[1..].try_into().unwrap()) as u64;
+            let block_len = BLOCK_PREFIX_LEN + payload_len + BLOCK_CHECKSUM_LEN;
+            // The recording stopped while this block was being written.
+            if offset + block_len > len {
+                break;
+            }
+            self.reader.seek(SeekFrom::Start(offset))?;
+            let (kind, payload) = read_block(&mut self.reader, len)?
+                .ok_or(RecordingError::Corrupt("a block is cut off"))?;
+            match kind {
+                BLOCK_SERIES => self.add_series(&payload)?,
+                BLOCK_CHUNK => {
+                    let mut bytes = ByteReader::new(&payload);
+                    self.chunks.push(ChunkEntry {
+                        first_tick: bytes.u64()?,
+                        tick_count: bytes.varint()? as u32,
+                        elapsed: Duration::from_micros(bytes.u64()?),
+                        offset,
+                    });
+                }
+                BLOCK_INDEX => break,
+                _ => return Err(RecordingError::Corrupt("unknown block")),
+            }
+            offset += block_len;
+        }
+        Ok(())
+    }
+
+    fn add_series(&mut self, payload: &[u8]) -> Result<(), RecordingError> {
+        let (id, info) = decode_series(payload)?;
+        // Series are numbered in the order they are written.
+        if id as usize != self.series.len() {
+            return Err(RecordingError::Corrupt("series are out of order"));
+        }
+        self.series.push(info);
+        Ok(())
+    }
+}
+
+/// Writes a block and returns how many bytes it took.
+fn write_block<W: Write>(writer: &mut W, kind: u8, payload: &[u8]) -> io::Result<u64> {
+    let mut block = Vec::with_capacity(payload.len() + 9);
+    block.push(kind);
+    block.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    block.extend_from_slice(payload);
+    let checksum = crc32(&block);
+    block.extend_from_slice(&checksum.to_le_bytes());
+    writer.write_all(&block)?;
+    Ok(block.len() as u64)
+}
+
+/// Reads the block at the current position and checks its checksum.
+/// Returns `None` at the end of the file, which is `file_len` long.
+fn read_block<R: Read + Seek>(
+    reader: &mut R,
+    file_len: u64,
+) -> Result<Option<(u8, Vec<u8>)>, RecordingError> {
+    let mut prefix = [0; BLOCK_PREFIX_LEN as usize];
+    match reader.read_exact(&mut prefix) {
+        Ok(()) => {}
+        Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(error) => return Err(error.into()),
+    }
+    let payload_len = u32::from_le_bytes(prefix[1..].try_into().unwrap()) as usize;
+    // A damaged length mustn't make us allocate more than the file holds.
+    let remaining = file_len.saturating_sub(reader.stream_position()?);
+    if payload_len as u64 + BLOCK_CHECKSUM_LEN > remaining {
+        return Err(RecordingError::Corrupt("a block is cut off"));
+    }
+    let mut rest = vec![0; payload_len + BLOCK_CHECKSUM_LEN as usize];
+    match reader.read_exact(&mut rest) {
+        Ok(()) => {}
+        Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => {
+            return Err(RecordingError::Corrupt("a block is cut off"))
+        }
+        Err(error) => return Err(error.into()),
+    }
+    let checksum = u32::from_le_bytes(rest[payload_len..].try_into().unwrap());
+    rest.truncate(payload_len);
+    let mut block = prefix.to_vec();
+    block.extend_from_slice(&rest);
+    if crc32(&block) != checksum {
+        return Err(RecordingError::Corrupt("a checksum doesn't match"));
+    }
+    Ok(Some((prefix[0], rest)))
+}
+
+fn decode_header(payload: &[u8]) -> Result<RecordingHeader, RecordingError> {
+    let mut bytes = ByteReader::new(payload);
+    let name = bytes.str()?;
+    let unit = decode_unit(bytes.u8()?)?;
+    let interval = Duration::from_nanos(bytes.u64()?);
+    // Nothing can be charted without time passing between samples.
+    if interval.is_zero() {
+        return Err(RecordingError::Corrupt("the sample interval is zero"));
+    }
+    Ok(RecordingHeader {
+        name,
+        unit,
+        interval,
+        start_time: UNIX_EPOCH + Duration::from_micros(bytes.u64()?),
+    })
+}
+
+fn encode_series(id: u32, info: &SeriesInfo) -> Vec<u8> {
+    let mut payload = Vec::new();
+    put_varint(&mut payload, id as u64);
+    put_str(&mut payload, &info.name);
+    payload.push(encode_unit(info.unit));
+    put_str(&mut payload, &info.origin.source);
+    match &info.origin.process {
+        Some(process) => {
+            payload.push(1);
+            payload.extend_from_slice(&process.process_id.to_le_bytes());
+            put_str(&mut payload, &process.name);
+        }
+        None => payload.push(0),
+    }
+    payload
+}
+
+fn decode_series(payload: &[u8]) -> Result<(u32, SeriesInfo), RecordingError> {
+    let mut bytes = ByteReader::new(payload);
+    let id = bytes.varint()? as u32;
+    let name = bytes.str()?;
+    let unit = decode_unit(bytes.u8()?)?;
+    let source = bytes.str()?;
+    let process = match bytes.u8()? {
+        0 => None,
+        _ => Some(ProcessInfo {
+            process_id: bytes.u32()?,
+            name: bytes.str()?,
+        }),
+    };
+    let origin = SeriesOrigin { source, process };
+    Ok((id, SeriesInfo { name, unit, origin }))
+}
+
+fn decode_chunk(
+    payload: &[u8],
+    series: &[SeriesInfo],
+) -> Result<Vec<RecordedTick>, RecordingError> {
+    let mut bytes = ByteReader::new(payload);
+    let _first_tick = bytes.u64()?;
+    let tick_count = bytes.varint()?;
+    let mut elapsed = Duration::from_micros(bytes.u64()?);
+    let mut last_values: HashMap<u32, u64> = HashMap::new();
+    let mut ticks = Vec::new();
+    for _ in 0..tick_count {
+        elapsed += Duration::from_micros(bytes.varint()?);
+        let reading_count = bytes.varint()?;
+        let mut readings = Vec::new();
+        for _ in 0..reading_count {
+            let id = bytes.varint()? as u32;
+            let info = series
+                .get(id as usize)
+                .ok_or(RecordingError::Corrupt("a reading has an unknown series"))?;
+            let flags = bytes.u8()?;
+            let status = decode_status(flags & STATUS_MASK);
+            let value = if flags & HAS_VALUE == 0 {
+                None
+            } else {
+                let last = last_values.get(&id).copied().unwrap_or(0);
+                let bits = if flags & SAME_VALUE != 0 {
+                    last
+                } else {
+                    let trailing_zeros = bytes.u8()?;
+                    let xor = bytes
+                        .varint()?
+                        .checked_shl(trailing_zeros as u32)
+                        .ok_or(RecordingError::Corrupt("a value is out of range"))?;
+                    last ^ xor
+                };
+                last_values.insert(id, bits);
+                Some(f64::from_bits(bits))
+            };
+            readings.push(Reading::new(info.name.clone(), Sample { value, status }));
+        }
+        ticks.push(RecordedTick { elapsed, readings });
+    }
+    Ok(ticks)
+}
+
+fn encode_unit(unit: Unit) -> u8 {
+    match unit {
+        Unit::Percent => 0,
+        Unit::Count => 1,
+    }
+}
+
+fn decode_unit(value: u8) -> Result<Unit, RecordingError> {
+    match value {
+        0 => Ok(Unit::Percent),
+        1 => Ok(Unit::Count),
+        _ => Err(RecordingError::Corrupt("unknown unit")),
+    }
+}
+
+fn encode_status(status: SampleStatus) -> u8 {
+    match status {
+        SampleStatus::Valid => 0,
+        SampleStatus::Stale => 1,
+        SampleStatus::Invalid => 2,
+        SampleStatus::NoInstance => 3,
+    }
+}
+
+fn decode_status(value: u8) -> SampleStatus {
+    match value {
+        0 => SampleStatus::Valid,
+        1 => SampleStatus::Stale,
+        2 => SampleStatus::Invalid,
+        _ => SampleStatus::NoInstance,
+    }
+}
+
+fn put_u64(bytes: &mut Vec<u8>, value: u64) {
+    bytes.extend_from_slice(&value.to_le_bytes());
+}
+
+/// LEB128: seven bits at a time, lowest first, with the high bit set on
+/// all but the last byte.
+fn put_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+fn put_str(bytes: &mut Vec<u8>, value: &str) {
+    put_varint(bytes, value.len() as u64);
+    bytes.extend_from_slice(value.as_bytes());
+}
+
+/// Reads the fields of a block's payload. Running past the end means the
+/// payload doesn't match its kind.
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> ByteReader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], RecordingError> {
+        if len > self.bytes.len() {
+            return Err(RecordingError::Corrupt("a block is shorter than expected"));
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8, RecordingError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, RecordingError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, RecordingError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn varint(&mut self) -> Result<u64, RecordingError> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.u8()?;
+            value |= ((byte & 0x7F) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(RecordingError::Corrupt("a number is too long"))
+    }
+
+    fn str(&mut self) -> Result<String, RecordingError> {
+        let len = self.varint()? as usize;
+        String::from_utf8(self.take(len)?.to_vec())
+            .map_err(|_| RecordingError::Corrupt("a name isn't valid UTF-8"))
+    }
+}
+
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                0xEDB88320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// The CRC-32 used by zip and PNG.
+fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0, |crc, byte| {
+        CRC32_TABLE[((crc ^ *byte as u32) & 0xFF) as usize] ^ (crc >> 8)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    const TICKS: u64 = 600;
+
+    fn header() -> RecordingHeader {
+        RecordingHeader {
+            name: "dwm.exe".to_owned(),
+            unit: Unit::Percent,
+            interval: Duration::from_secs(1),
+            start_time: UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+        }
+    }
+
+    fn origin(series: &str) -> SeriesOrigin {
+        SeriesOrigin {
+            source: "GPU".to_owned(),
+            process: series.starts_with("3D").then(|| ProcessInfo {
+                process_id: 1234,
+                name: "dwm.exe".to_owned(),
+            }),
+        }
+    }
+
+    /// A tick with a bit of everything: repeated values, values that
+    /// change, missing values with every status, series that skip ticks
+    /// and one that only shows up halfway.
+    fn tick(i: u64) -> RecordedTick {
+        let statuses = [
+            SampleStatus::Valid,
+            SampleStatus::Stale,
+            SampleStatus::Invalid,
+            SampleStatus::NoInstance,
+        ];
+        let mut readings = Vec::new();
+        if i % 7 != 3 {
+            readings.push(Reading::new("3D", Sample::valid((i / 10) as f64 * 1.5)));
+        }
+        readings.push(Reading::new(
+            "Copy",
+            match i % 5 {
+                0 => Sample::missing(statuses[(i / 5 % 4) as usize]),
+                1 => Sample {
+                    value: Some(-0.25),
+                    status: SampleStatus::Stale,
+                },
+                _ => Sample::valid(i as f64 / 3.0),
+            },
+        ));
+        if i >= TICKS / 2 {
+            readings.push(Reading::new("Video, \"Decode\"", Sample::valid(f64::MAX)));
+        }
+        RecordedTick {
+            elapsed: Duration::from_millis(i * 1000 + i % 3),
+            readings,
+        }
+    }
+
+    fn write(ticks: u64) -> Vec<u8> {
+        let mut writer = RecordingWriter::new(Cursor::new(Vec::new()), &header()).unwrap();
+        for i in 0..ticks {
+            let tick = tick(i);
+            writer
+                .write_tick(tick.elapsed, &tick.readings, origin)
+                .unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    fn read_all(reader: &mut RecordingReader<Cursor<Vec<u8>>>) -> Vec<RecordedTick> {
+        (0..reader.chunks().len())
+            .flat_map(|index| reader.read_chunk(index).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn round_trips() {
+        let mut reader = RecordingReader::new(Cursor::new(write(TICKS))).unwrap();
+        assert_eq!(reader.header(), &header());
+        let names: Vec<&str> = reader
+            .series()
+            .iter()
+            .map(|info| info.name.as_str())
+            .collect();
+        assert_eq!(names, ["3D", "Copy", "Video, \"Decode\""]);
+        assert_eq!(reader.series()[0].origin, origin("3D"));
+        assert_eq!(reader.series()[1].origin, origin("Copy"));
+
+        let ticks = read_all(&mut reader);
+        let expected: Vec<RecordedTick> = (0..TICKS).map(tick).collect();
+        assert_eq!(ticks, expected);
+    }
+
+    #[test]
+    fn finds_chunks() {
+        let reader = RecordingReader::new(Cursor::new(write(TICKS))).unwrap();
+        assert_eq!(reader.tick_count(), TICKS);
+        assert_eq!(reader.chunks().len(), 3);
+        assert_eq!(reader.find_chunk(0), Some(0));
+        assert_eq!(reader.find_chunk(TICKS_PER_CHUNK as u64 - 1), Some(0));
+        assert_eq!(reader.find_chunk(TICKS_PER_CHUNK as u64), Some(1));
+        assert_eq!(reader.find_chunk(TICKS - 1), Some(2));
+        assert_eq!(reader.find_chunk(TICKS), None);
+        assert_eq!(reader.chunks()[1].elapsed, tick(256).elapsed);
+    }
+
+    #[test]
+    fn empty_recording() {
+        let mut writer = RecordingWriter::new(Cursor::new(Vec::new()), &header()).unwrap();
+        writer.write_tick(Duration::ZERO, &[], origin).unwrap();
+        let bytes = writer.finish().unwrap().into_inner();
+        let mut reader = RecordingReader::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(reader.tick_count(), 1);
+        assert_eq!(read_all(&mut reader)[0].readings, []);
+    }
+
+    #[test]
+    fn rebuilds_the_index_of_unfinished_recordings() {
+        let bytes = write(TICKS);
+        let index_offset =
+            u64::from_le_bytes(bytes[bytes.len() - 16..bytes.len() - 8].try_into().unwrap());
+
+        // Every chunk made it, but the index didn't.
+        let unfinished = bytes[..index_offset as usize].to_vec();
+        let mut reader = RecordingReader::new(Cursor::new(unfinished)).unwrap();
+        assert_eq!(reader.tick_count(), TICKS);
+        assert_eq!(reader.series().len(), 3);
+        assert_eq!(
+            read_all(&mut reader),
+            (0..TICKS).map(tick).collect::<Vec<_>>()
+        );
+
+        // The last chunk was cut off while being written.
+        let last_chunk = RecordingReader::new(Cursor::new(bytes.clone()))
+            .unwrap()
+            .chunks()[2]
+            .offset;
+        let cut_off = bytes[..last_chunk as usize + 10].to_vec();
+        let mut reader = RecordingReader::new(Cursor::new(cut_off)).unwrap();
+        assert_eq!(reader.tick_count(), 2 * TICKS_PER_CHUNK as u64);
+        assert_eq!(
+            read_all(&mut reader),
+            (0..2 * TICKS_PER_CHUNK as u64)
+                .map(tick)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn detects_corruption() {
+        let bytes = write(TICKS);
+        let chunk = RecordingReader::new(Cursor::new(bytes.clone()))
+            .unwrap()
+            .chunks()[1]
+            .offset as usize;
+
+        let mut damaged = bytes.clone();
+        damaged[chunk + BLOCK_PREFIX_LEN as usize + 20] ^= 0x10;
+        let mut reader = RecordingReader::new(Cursor::new(damaged)).unwrap();
+        assert!(reader.read_chunk(0).is_ok());
+        assert!(matches!(
+            reader.read_chunk(1),
+            Err(RecordingError::Corrupt(_))
+        ));
+
+        // The header block starts right after the magic and version.
+        let mut damaged = bytes.clone();
+        damaged[MAGIC.len() + 2 + BLOCK_PREFIX_LEN as usize] ^= 0x01;
+        assert!(matches!(
+            RecordingReader::new(Cursor::new(damaged)),
+            Err(RecordingError::Corrupt(_))
+        ));
+    }
+
+    #[test]
+    fn detects_chunks_that_disagree_with_the_index() {
+        let bytes = write(TICKS);
+        let index_offset =
+            u64::from_le_bytes(bytes[bytes.len() - 16..bytes.len() - 8].try_into().unwrap());
+        let mut cursor = Cursor::new(&bytes);
+        cursor.seek(SeekFrom::Start(index_offset)).unwrap();
+        let (_, mut payload) = read_block(&mut cursor, bytes.len() as u64)
+            .unwrap()
+            .unwrap();
+
+        // Rewrite the index, with a valid checksum, claiming the last chunk
+        // holds more ticks than it does. Its tick count comes right before
+        // its elapsed time and offset.
+        let last_tick_count = payload.len() - 17;
+        assert_eq!(payload[last_tick_count], 88);
+        payload[last_tick_count] = 120;
+        let mut damaged = bytes[..index_offset as usize].to_vec();
+        write_block(&mut damaged, BLOCK_INDEX, &payload).unwrap();
+        damaged.extend_from_slice(&index_offset.to_le_bytes());
+        damaged.extend_from_slice(&INDEX_MAGIC);
+
+        let mut reader = RecordingReader::new(Cursor::new(damaged)).unwrap();
+        assert_eq!(reader.tick_count(), TICKS + 32);
+        let index = reader.find_chunk(TICKS + 20).unwrap();
+        assert!(matches!(
+            reader.read_chunk(index),
+            Err(RecordingError::Corrupt(_))
+        ));
+        assert!(reader.read_chunk(0).is_ok());
+    }
+
+    #[test]
+    fn rejects_a_zero_interval() {
+        let header = RecordingHeader {
+            interval: Duration::ZERO,
+            ..header()
+        };
+        let writer = RecordingWriter::new(Cursor::new(Vec::new()), &header).unwrap();
+        let bytes = writer.finish().unwrap().into_inner();
+        assert!(matches!(
+            RecordingReader::new(Cursor::new(bytes)),
+            Err(RecordingError::Corrupt("the sample interval is zero"))
+        ));
+    }
+
+    #[test]
+    fn rejects_block_lengths_past_the_end() {
+        let mut bytes = write(10);
+        let length = MAGIC.len() + 2 + 1;
+        bytes[length..length + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(
+            RecordingReader::new(Cursor::new(bytes)),
+            Err(RecordingError::Corrupt(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert!(matches!(
+            RecordingReader::new(Cursor::new(Vec::new())),
+            Err(RecordingError::NotARecording)
+        ));
+        assert!(matches!(
+            RecordingReader::new(Cursor::new(b"timestamp,elapsed,series".to_vec())),
+            Err(RecordingError::NotARecording)
+        ));
+        let mut bytes = write(1);
+        bytes[MAGIC.len()..MAGIC.len() + 2].copy_from_slice(&2u16.to_le_bytes());
+        assert!(matches!(
+            RecordingReader::new(Cursor::new(bytes)),
+            Err(RecordingError::UnsupportedVersion(2))
+        ));
+    }
+}
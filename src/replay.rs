@@ -0,0 +1,219 @@
+use std::{collections::VecDeque, fs::File, io::BufReader, path::Path};
+
+use crate::{
+    metric::{
+        self, MetricSource, Playback, PlaybackSpeed, Reading, Sample, SampleStatus, SeriesOrigin,
+        Unit,
+    },
+    perfmon_log::{PerfmonLog, PerfmonLogError},
+    recording_file::{RecordedTick, RecordingError, RecordingReader},
+};
+
+/// Opens `path` for playback: a perfmon log converted with `relog -f csv`
+/// or `-f tsv` if it ends in `.csv` or `.tsv`, and a chartfun recording
+/// otherwise.
+pub fn open(path: &Path, speed: PlaybackSpeed) -> metric::Result<Box<dyn MetricSource>> {
+    let is_perfmon_log = path.extension().is_some_and(|extension| {
+        extension.eq_ignore_ascii_case("csv") || extension.eq_ignore_ascii_case("tsv")
+    });
+    if is_perfmon_log {
+        Ok(Box::new(PerfmonLogSource::open(path, speed)?))
+    } else {
+        Ok(Box::new(ReplaySource::open(path, speed)?))
+    }
+}
+
+/// Plays back a recording, one recorded tick per sample.
+pub struct ReplaySource {
+    reader: RecordingReader<BufReader<File>>,
+    speed: PlaybackSpeed,
+    /// Ticks of the current chunk that haven't been played yet.
+    pending: VecDeque<RecordedTick>,
+    next_chunk: usize,
+}
+
+impl ReplaySource {
+    pub fn open(path: &Path, speed: PlaybackSpeed) -> Result<Self, RecordingError> {
+        let reader = RecordingReader::new(BufReader::new(File::open(path)?))?;
+        Ok(Self {
+            reader,
+            speed,
+            pending: VecDeque::new(),
+            next_chunk: 0,
+        })
+    }
+}
+
+impl MetricSource for ReplaySource {
+    fn name(&self) -> &str {
+        &self.reader.header().name
+    }
+
+    fn unit(&self) -> Unit {
+        self.reader.header().unit
+    }
+
+    fn start(&mut self) -> metric::Result<()> {
+        Ok(())
+    }
+
+    fn sample(&mut self) -> metric::Result<Vec<Reading>> {
+        if self.pending.is_empty() && self.next_chunk < self.reader.chunks().len() {
+            self.pending = self.reader.read_chunk(self.next_chunk)?.into();
+            self.next_chunk += 1;
+        }
+        Ok(self
+            .pending
+            .pop_front()
+            .map(|tick| tick.readings)
+            .unwrap_or_default())
+    }
+
+    fn close(self: Box<Self>) -> metric::Result<()> {
+        Ok(())
+    }
+
+    fn series_origin(&self, series: &str) -> SeriesOrigin {
+        match self.reader.series().iter().find(|info| info.name == series) {
+            Some(info) => info.origin.clone(),
+            None => SeriesOrigin {
+                source: self.name().to_owned(),
+                process: None,
+            },
+        }
+    }
+
+    fn playback(&self) -> Option<Playback> {
+        Some(Playback {
+            interval: self.reader.header().interval,
+            speed: self.speed,
+            length: self.reader.tick_count(),
+        })
+    }
+
+    fn is_finished(&self) -> bool {
+        self.pending.is_empty() && self.next_chunk == self.reader.chunks().len()
+    }
+
+    fn seek(&mut self, position: u64) -> metric::Result<()> {
+        self.pending.clear();
+        match self.reader.find_chunk(position) {
+            Some(index) => {
+                let mut ticks = self.reader.read_chunk(index)?;
+                let first_tick = self.reader.chunks()[index].first_tick;
+                ticks.drain(..(position - first_tick) as usize);
+                self.pending = ticks.into();
+                self.next_chunk = index + 1;
+            }
+            // Past the end, so there's nothing left to play.
+            None => self.next_chunk = self.reader.chunks().len(),
+        }
+        Ok(())
+    }
+}
+
+/// Plays back a perfmon log, one series per counter. Ticks where nothing
+/// was logged are played back as gaps.
+pub struct PerfmonLogSource {
+    log: PerfmonLog<BufReader<File>>,
+    name: String,
+    unit: Unit,
+    speed: PlaybackSpeed,
+    next_tick: u64,
+    /// The first row at or after `next_tick`.
+    next_row: usize,
+}
+
+impl PerfmonLogSource {
+    pub fn open(path: &Path, speed: PlaybackSpeed) -> Result<Self, PerfmonLogError> {
+        let log = PerfmonLog::new(BufReader::new(File::open(path)?))?;
+        let name = match log.columns() {
+            [column] => column.series.clone(),
+            _ => path
+                .file_name()
+                .map_or_else(String::new, |name| name.to_string_lossy().into_owned()),
+        };
+        // Same as charting the counters live.
+        let is_percentage = log.columns().iter().all(|column| {
+            column
+                .path
+                .as_ref()
+                .is_some_and(|path| path.is_percentage())
+        });
+        let unit = if is_percentage {
+            Unit::Percent
+        } else {
+            Unit::Count
+        };
+        Ok(Self {
+            log,
+            name,
+            unit,
+            speed,
+            next_tick: 0,
+            next_row: 0,
+        })
+    }
+}
+
+impl MetricSource for PerfmonLogSource {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn unit(&self) -> Unit {
+        self.unit
+    }
+
+    fn start(&mut self) -> metric::Result<()> {
+        Ok(())
+    }
+
+    fn sample(&mut self) -> metric::Result<Vec<Reading>> {
+        if self.is_finished() {
+            return Ok(Vec::new());
+        }
+        let tick = self.next_tick;
+        self.next_tick += 1;
+        if self.log.row_tick(self.next_row) != Some(tick) {
+            return Ok(Vec::new());
+        }
+        let values = self.log.read_row(self.next_row)?;
+        self.next_row += 1;
+        Ok(self
+            .log
+            .columns()
+            .iter()
+            .zip(values)
+            .map(|(column, value)| {
+                let sample = match value {
+                    Some(value) => Sample::valid(value),
+                    None => Sample::missing(SampleStatus::NoInstance),
+                };
+                Reading::new(column.series.clone(), sample)
+            })
+            .collect())
+    }
+
+    fn close(self: Box<Self>) -> metric::Result<()> {
+        Ok(())
+    }
+
+    fn playback(&self) -> Option<Playback> {
+        Some(Playback {
+            interval: self.log.interval(),
+            speed: self.speed,
+            length: self.log.tick_count(),
+        })
+    }
+
+    fn is_finished(&self) -> bool {
+        self.next_tick >= self.log.tick_count()
+    }
+
+    fn seek(&mut self, position: u64) -> metric::Result<()> {
+        self.next_tick = position.min(self.log.tick_count());
+        self.next_row = self.log.find_row(self.next_tick);
+        Ok(())
+    }
+}
@@ -0,0 +1,625 @@
+use std::time::Duration;
+
+use crate::{
+    chart_model::{
+        ChartModel, Color, DisplayList, DrawCommand, Point, Rect, TextAlign, LINE_HEIGHT,
+    },
+    metric::{headline_value, is_partial, MetricSource, Reading, Unit},
+    playback::PlaybackClock,
+};
+
+// Matches the font size of the Renderer's normal text format.
+pub const LABEL_FONT_SIZE: f32 = 14.0;
+const MARGIN: f32 = 16.0;
+const SWATCH_SIZE: f32 = 10.0;
+const SWATCH_GAP: f32 = 4.0;
+const LEGEND_SPACING: f32 = 12.0;
+// Space between the chart and its axis labels, and between time labels.
+pub const AXIS_LABEL_GAP: f32 = 4.0;
+// Shown in place of a value when a series has none.
+const NO_VALUE: &str = "--";
+
+const BACKGROUND_COLOR: Color = Color::new(1.0, 1.0, 1.0, 1.0);
+const TITLE_COLOR: Color = Color::new(0.0, 0.0, 0.0, 1.0);
+const VALUE_COLOR: Color = Color::new(0.4392, 0.4392, 0.4392, 1.0);
+const AXIS_LABEL_COLOR: Color = VALUE_COLOR;
+
+/// The text shown in the header rows above the chart.
+pub struct Labels {
+    pub title: String,
+    pub value: String,
+    pub legend: Vec<LegendEntry>,
+    pub axis: AxisLabels,
+}
+
+/// One series in the legend: a color swatch followed by the name and the
+/// latest value.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LegendEntry {
+    pub name: String,
+    pub color: Color,
+    pub value: String,
+}
+
+impl LegendEntry {
+    pub fn text(&self) -> String {
+        format!("{} {}", self.name, self.value)
+    }
+}
+
+/// The source's name, followed by where playback is for recordings and
+/// the source's warning, if any.
+pub fn title(source: &dyn MetricSource, playback: Option<&PlaybackClock>) -> String {
+    let title = match playback {
+        Some(clock) => format!("{} ({})", source.name(), clock.status()),
+        None => source.name().to_owned(),
+    };
+    match source.warning() {
+        Some(warning) => format!("{} - {}", title, warning),
+        None => title,
+    }
+}
+
+/// The text next to the title, marked when some of the series are missing
+/// or out of date.
+pub fn value_label(unit: Unit, readings: &[Reading]) -> String {
+    let value = match headline_value(readings) {
+        Some(value) => unit.format_value(value),
+        None => NO_VALUE.to_owned(),
+    };
+    if is_partial(readings) {
+        format!("{} (partial)", value)
+    } else {
+        value
+    }
+}
+
+/// The legend for every series in `chart`. A single series is already
+/// described by the title, so it doesn't get a legend.
+pub fn legend_entries(chart: &ChartModel, unit: Unit) -> Vec<LegendEntry> {
+    let series = chart.series();
+    if series.len() < 2 {
+        return Vec::new();
+    }
+    series
+        .iter()
+        .map(|series| LegendEntry {
+            name: series.name().to_owned(),
+            color: series.color(),
+            value: match series.points().back().copied().flatten() {
+                Some(value) => unit.format_value(value as f64),
+                None => NO_VALUE.to_owned(),
+            },
+        })
+        .collect()
+}
+
+/// Where one legend entry goes, relative to the top left of the legend.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LegendSlot {
+    pub swatch: Rect,
+    /// Top left of the entry's line box.
+    pub text: Point,
+}
+
+/// Lays out legend entries left to right, starting a new row whenever an
+/// entry would run past `max_width`. Returns a slot per entry and the
+/// height of the legend.
+pub fn layout_legend(
+    text_widths: &[f32],
+    max_width: f32,
+    line_height: f32,
+    scale: f32,
+) -> (Vec<LegendSlot>, f32) {
+    let swatch_size = SWATCH_SIZE * scale;
+    let swatch_gap = SWATCH_GAP * scale;
+    let spacing = LEGEND_SPACING * scale;
+
+    let mut slots = Vec::with_capacity(text_widths.len());
+    let mut x = 0.0;
+    let mut y = 0.0;
+    for text_width in text_widths {
+        let entry_width = swatch_size + swatch_gap + text_width;
+        // An entry wider than the legend still gets a row to itself.
+        if x > 0.0 && x + entry_width > max_width {
+            x = 0.0;
+            y += line_height;
+        }
+        let swatch_top = y + (line_height - swatch_size) / 2.0;
+        slots.push(LegendSlot {
+            swatch: Rect {
+                left: x,
+                top: swatch_top,
+                right: x + swatch_size,
+                bottom: swatch_top + swatch_size,
+            },
+            text: Point::new(x + swatch_size + swatch_gap, y),
+        });
+        x += entry_width + spacing;
+    }
+
+    let height = if slots.is_empty() {
+        0.0
+    } else {
+        y + line_height
+    };
+    (slots, height)
+}
+
+/// A tick label. `position` is the y of a value tick or the x of a time
+/// tick, relative to the top left of the chart.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AxisLabel {
+    pub text: String,
+    pub position: f32,
+}
+
+/// The labels along the left (values) and bottom (time) of the chart.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AxisLabels {
+    pub values: Vec<AxisLabel>,
+    pub times: Vec<AxisLabel>,
+}
+
+/// A label for every value tick, from the bottom up, and one for every
+/// vertical grid cell's worth of time, from now backwards.
+pub fn axis_labels(chart: &ChartModel, unit: Unit) -> AxisLabels {
+    let values = chart
+        .y_axis()
+        .ticks()
+        .values()
+        .into_iter()
+        .map(|value| AxisLabel {
+            text: unit.format_value(value),
+            position: chart.value_to_y(value as f32),
+        })
+        .collect();
+    let times = chart
+        .time_ticks()
+        .into_iter()
+        .map(|(age, x)| AxisLabel {
+            text: format_age(age),
+            position: x,
+        })
+        .collect();
+    AxisLabels { values, times }
+}
+
+/// A time label in the largest whole unit, e.g. `90s`, `5m` or `1h`.
+fn format_age(age: Duration) -> String {
+    let seconds = age.as_secs();
+    if seconds >= 3600 && seconds.is_multiple_of(3600) {
+        format!("{}h", seconds / 3600)
+    } else if seconds >= 60 && seconds.is_multiple_of(60) {
+        format!("{}m", seconds / 60)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+/// Where the axis labels go, relative to the top left of the chart. Labels
+/// that would overlap the one before them are left out (`None`).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AxisLayout {
+    /// Top left of each value label's line box.
+    pub values: Vec<Option<Point>>,
+    /// Top left of each time label's line box.
+    pub times: Vec<Option<Point>>,
+    /// How far the value labels reach left of the chart.
+    pub left: f32,
+    /// How far the time labels reach below the chart.
+    pub bottom: f32,
+}
+
+/// Places value labels right aligned to the left of the chart, centered on
+/// their tick, and time labels centered under their tick. Labels are kept
+/// inside the chart's extent along their axis. Earlier labels win when two
+/// would overlap, so the bottom value and the newest time always show.
+pub fn layout_axis_labels(
+    labels: &AxisLabels,
+    value_widths: &[f32],
+    time_widths: &[f32],
+    chart_width: f32,
+    chart_height: f32,
+    line_height: f32,
+    scale: f32,
+) -> AxisLayout {
+    let gap = AXIS_LABEL_GAP * scale;
+
+    let mut values = Vec::with_capacity(labels.values.len());
+    let mut left: f32 = 0.0;
+    let mut last_top = f32::INFINITY;
+    for (label, width) in labels.values.iter().zip(value_widths) {
+        let top =
+            (label.position - line_height / 2.0).clamp(0.0, (chart_height - line_height).max(0.0));
+        if top + line_height > last_top {
+            values.push(None);
+            continue;
+        }
+        last_top = top;
+        left = left.max(width + gap);
+        values.push(Some(Point::new(-gap - width, top)));
+    }
+
+    let mut times = Vec::with_capacity(labels.times.len());
+    let mut last_left = f32::INFINITY;
+    for (label, width) in labels.times.iter().zip(time_widths) {
+        let x = (label.position - width / 2.0).clamp(0.0, (chart_width - width).max(0.0));
+        if x + width + gap > last_left {
+            times.push(None);
+            continue;
+        }
+        last_left = x;
+        times.push(Some(Point::new(x, chart_height + gap)));
+    }
+    let bottom = if times.iter().any(Option::is_some) {
+        gap + line_height
+    } else {
+        0.0
+    };
+
+    AxisLayout {
+        values,
+        times,
+        left,
+        bottom,
+    }
+}
+
+/// Lays out a chart the same way the window does: a white background
+/// with the title and current value in a row above the chart, followed by
+/// the legend, and the axis labels along the left and bottom. Used by the
+/// frontends that have to draw the labels themselves, which supply
+/// `measure_text` for their own fonts.
+pub fn compose_frame(
+    chart: &DisplayList,
+    labels: &Labels,
+    scale: f32,
+    measure_text: fn(&str, f32) -> f32,
+) -> DisplayList {
+    let margin = MARGIN * scale;
+    let font_size = LABEL_FONT_SIZE * scale;
+    let line_height = (font_size * LINE_HEIGHT).ceil();
+    let legend_texts: Vec<String> = labels.legend.iter().map(LegendEntry::text).collect();
+    let text_widths: Vec<f32> = legend_texts
+        .iter()
+        .map(|text| measure_text(text, font_size))
+        .collect();
+    let (legend_slots, legend_height) =
+        layout_legend(&text_widths, chart.width, line_height, scale);
+    let header_height = line_height + legend_height;
+
+    let measure_all = |labels: &[AxisLabel]| -> Vec<f32> {
+        labels
+            .iter()
+            .map(|label| measure_text(&label.text, font_size))
+            .collect()
+    };
+    let axis_layout = layout_axis_labels(
+        &labels.axis,
+        &measure_all(&labels.axis.values),
+        &measure_all(&labels.axis.times),
+        chart.width,
+        chart.height,
+        line_height,
+        scale,
+    );
+    let chart_left = margin + axis_layout.left;
+    let chart_top = margin + header_height;
+    let width = chart_left + chart.width + margin;
+    let height = chart_top + chart.height + axis_layout.bottom + margin;
+
+    let mut commands = Vec::with_capacity(
+        chart.commands.len()
+            + 3
+            + legend_slots.len() * 2
+            + labels.axis.values.len()
+            + labels.axis.times.len(),
+    );
+    commands.push(DrawCommand::Fill {
+        points: vec![
+            Point::new(0.0, 0.0),
+            Point::new(width, 0.0),
+            Point::new(width, height),
+            Point::new(0.0, height),
+        ],
+        color: BACKGROUND_COLOR,
+    });
+    commands.push(DrawCommand::Text {
+        origin: Point::new(chart_left, margin),
+        text: labels.title.clone(),
+        color: TITLE_COLOR,
+        size: font_size,
+        align: TextAlign::Left,
+    });
+    commands.push(DrawCommand::Text {
+        origin: Point::new(chart_left + chart.width, margin),
+        text: labels.value.clone(),
+        color: VALUE_COLOR,
+        size: font_size,
+        align: TextAlign::Right,
+    });
+    let legend_top = margin + line_height;
+    for ((entry, text), slot) in labels.legend.iter().zip(legend_texts).zip(legend_slots) {
+        let swatch = slot.swatch;
+        commands.push(DrawCommand::Fill {
+            points: vec![
+                Point::new(chart_left + swatch.left, legend_top + swatch.top),
+                Point::new(chart_left + swatch.right, legend_top + swatch.top),
+                Point::new(chart_left + swatch.right, legend_top + swatch.bottom),
+                Point::new(chart_left + swatch.left, legend_top + swatch.bottom),
+            ],
+            color: entry.color,
+        });
+        commands.push(DrawCommand::Text {
+            origin: Point::new(chart_left + slot.text.x, legend_top + slot.text.y),
+            text,
+            color: TITLE_COLOR,
+            size: font_size,
+            align: TextAlign::Left,
+        });
+    }
+    let axis_labels = labels
+        .axis
+        .values
+        .iter()
+        .zip(&axis_layout.values)
+        .chain(labels.axis.times.iter().zip(&axis_layout.times));
+    for (label, origin) in axis_labels {
+        let Some(origin) = origin else {
+            continue;
+        };
+        commands.push(DrawCommand::Text {
+            origin: Point::new(chart_left + origin.x, chart_top + origin.y),
+            text: label.text.clone(),
+            color: AXIS_LABEL_COLOR,
+            size: font_size,
+            align: TextAlign::Left,
+        });
+    }
+    for command in &chart.commands {
+        let mut command = command.clone();
+        command.offset(chart_left, chart_top);
+        commands.push(command);
+    }
+
+    DisplayList {
+        width,
+        height,
+        commands,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WIDTH: f32 = 300.0;
+    const HEIGHT: f32 = 200.0;
+    const LINE_HEIGHT: f32 = 14.0;
+
+    fn labels(positions: &[f32]) -> Vec<AxisLabel> {
+        positions
+            .iter()
+            .map(|position| AxisLabel {
+                text: position.to_string(),
+                position: *position,
+            })
+            .collect()
+    }
+
+    fn layout(labels: &AxisLabels, height: f32) -> AxisLayout {
+        let value_widths: Vec<f32> = labels.values.iter().map(|_| 30.0).collect();
+        let time_widths: Vec<f32> = labels.times.iter().map(|_| 20.0).collect();
+        layout_axis_labels(
+            labels,
+            &value_widths,
+            &time_widths,
+            WIDTH,
+            height,
+            LINE_HEIGHT,
+            1.0,
+        )
+    }
+
+    /// The top of every value label that's shown, from the bottom up.
+    fn value_tops(layout: &AxisLayout) -> Vec<f32> {
+        layout
+            .values
+            .iter()
+            .flatten()
+            .map(|point| point.y)
+            .collect()
+    }
+
+    #[test]
+    fn centers_value_labels_on_their_ticks() {
+        // Tall enough for a label every 10%, even at the ends.
+        let height = 300.0;
+        let chart = ChartModel::new(WIDTH, height);
+        let labels = axis_labels(&chart, Unit::Percent);
+        assert_eq!(labels.values.len(), 11);
+        assert_eq!(labels.values.first().unwrap().text, "0%");
+        assert_eq!(labels.values.last().unwrap().text, "100%");
+
+        let layout = layout(&labels, height);
+        assert_eq!(layout.values.len(), labels.values.len());
+        assert_eq!(layout.left, 30.0 + AXIS_LABEL_GAP);
+        for (label, point) in labels.values.iter().zip(&layout.values) {
+            let point = point.expect("there's room for every label");
+            assert_eq!(point.x, -AXIS_LABEL_GAP - 30.0);
+            let center = point.y + LINE_HEIGHT / 2.0;
+            // The labels at the ends are kept inside the chart.
+            let expected = label
+                .position
+                .clamp(LINE_HEIGHT / 2.0, height - LINE_HEIGHT / 2.0);
+            assert!(
+                (center - expected).abs() < 1e-3,
+                "{} at {}",
+                label.text,
+                center
+            );
+        }
+    }
+
+    #[test]
+    fn drops_value_labels_that_would_overlap() {
+        // From the bottom up, 10 apart, which is less than a line.
+        let positions: Vec<f32> = (0..=20).map(|i| HEIGHT - i as f32 * 10.0).collect();
+        let labels = AxisLabels {
+            values: labels(&positions),
+            times: Vec::new(),
+        };
+        let layout = layout(&labels, HEIGHT);
+        // The bottom one always shows.
+        assert!(layout.values[0].is_some());
+        assert!(layout.values[1].is_none());
+        let tops = value_tops(&layout);
+        assert!(tops.len() > 5);
+        for pair in tops.windows(2) {
+            assert!(pair[1] + LINE_HEIGHT <= pair[0], "{:?}", tops);
+        }
+    }
+
+    #[test]
+    fn drops_value_labels_when_the_chart_is_too_short() {
+        let labels = AxisLabels {
+            values: labels(&[20.0, 10.0, 0.0]),
+            times: Vec::new(),
+        };
+        let layout = layout(&labels, 20.0);
+        assert_eq!(value_tops(&layout), [20.0 - LINE_HEIGHT]);
+        assert_eq!(layout.bottom, 0.0);
+
+        let layout = layout_axis_labels(&labels, &[30.0; 3], &[], WIDTH, 0.0, LINE_HEIGHT, 1.0);
+        assert_eq!(layout.values.iter().flatten().count(), 1);
+    }
+
+    #[test]
+    fn centers_time_labels_under_their_ticks() {
+        let chart = ChartModel::new(WIDTH, HEIGHT);
+        let labels = axis_labels(&chart, Unit::Percent);
+        let texts: Vec<&str> = labels
+            .times
+            .iter()
+            .map(|label| label.text.as_str())
+            .collect();
+        assert_eq!(texts, ["0s", "10s", "20s", "30s", "40s", "50s"]);
+
+        let layout = layout(&labels, HEIGHT);
+        assert_eq!(layout.bottom, AXIS_LABEL_GAP + LINE_HEIGHT);
+        for (label, point) in labels.times.iter().zip(&layout.times) {
+            let point = point.expect("there's room for every label");
+            assert_eq!(point.y, HEIGHT + AXIS_LABEL_GAP);
+            let expected = label.position.clamp(10.0, WIDTH - 10.0);
+            assert!((point.x + 10.0 - expected).abs() < 1e-3, "{}", label.text);
+        }
+    }
+
+    #[test]
+    fn drops_time_labels_that_would_overlap() {
+        // From the right edge leftwards, 15 apart, which is less than a
+        // label and a gap.
+        let positions: Vec<f32> = (0..10).map(|i| WIDTH - i as f32 * 15.0).collect();
+        let labels = AxisLabels {
+            values: Vec::new(),
+            times: labels(&positions),
+        };
+        let layout = layout(&labels, HEIGHT);
+        // The newest one always shows, against the right edge.
+        assert_eq!(
+            layout.times[0],
+            Some(Point::new(WIDTH - 20.0, HEIGHT + AXIS_LABEL_GAP))
+        );
+        let lefts: Vec<f32> = layout.times.iter().flatten().map(|point| point.x).collect();
+        assert_eq!(lefts.len(), 5);
+        for pair in lefts.windows(2) {
+            assert!(pair[1] + 20.0 + AXIS_LABEL_GAP <= pair[0], "{:?}", lefts);
+        }
+
+        // Too narrow for more than one.
+        let layout = layout_axis_labels(&labels, &[], &[20.0; 10], 30.0, HEIGHT, LINE_HEIGHT, 1.0);
+        assert_eq!(layout.times.iter().flatten().count(), 1);
+    }
+
+    #[test]
+    fn scales_the_gap() {
+        let labels = AxisLabels {
+            values: labels(&[100.0]),
+            times: labels(&[WIDTH]),
+        };
+        let layout = layout_axis_labels(&labels, &[30.0], &[20.0], WIDTH, HEIGHT, LINE_HEIGHT, 2.0);
+        assert_eq!(layout.left, 30.0 + 2.0 * AXIS_LABEL_GAP);
+        assert_eq!(layout.bottom, LINE_HEIGHT + 2.0 * AXIS_LABEL_GAP);
+    }
+
+    #[test]
+    fn formats_ages() {
+        assert_eq!(format_age(Duration::ZERO), "0s");
+        assert_eq!(format_age(Duration::from_secs(90)), "90s");
+        assert_eq!(format_age(Duration::from_secs(300)), "5m");
+        assert_eq!(format_age(Duration::from_secs(5400)), "90m");
+        assert_eq!(format_age(Duration::from_secs(7200)), "2h");
+    }
+
+    /// A live source that only has a name and maybe a warning.
+    struct WarningSource {
+        name: &'static str,
+        warning: Option<&'static str>,
+    }
+
+    impl MetricSource for WarningSource {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn unit(&self) -> Unit {
+            Unit::Percent
+        }
+
+        fn start(&mut self) -> crate::metric::Result<()> {
+            Ok(())
+        }
+
+        fn sample(&mut self) -> crate::metric::Result<Vec<Reading>> {
+            Ok(Vec::new())
+        }
+
+        fn close(self: Box<Self>) -> crate::metric::Result<()> {
+            Ok(())
+        }
+
+        fn warning(&self) -> Option<String> {
+            self.warning.map(str::to_owned)
+        }
+    }
+
+    #[test]
+    fn shows_warnings_in_the_title() {
+        let counters = WarningSource {
+            name: "Counters",
+            warning: Some("Copy matched twice"),
+        };
+        assert_eq!(title(&counters, None), "Counters - Copy matched twice");
+        let dwm = WarningSource {
+            name: "dwm.exe",
+            warning: None,
+        };
+        assert_eq!(title(&dwm, None), "dwm.exe");
+
+        // Combined sources say which source a warning is from.
+        let combined = crate::metric::CombinedSource::new(vec![
+            Box::new(dwm),
+            Box::new(counters),
+            Box::new(WarningSource {
+                name: "game.exe",
+                warning: Some("3D is gone"),
+            }),
+        ])
+        .unwrap();
+        assert_eq!(
+            title(&combined, None),
+            "dwm.exe, Counters, game.exe - Counters: Copy matched twice; game.exe: 3D is gone"
+        );
+    }
+}
@@ -0,0 +1,370 @@
+use std::{fs::File, io::BufWriter, path::Path};
+
+use crate::{
+    bitmap_font::{glyph, text_width, GLYPH_ADVANCE, GLYPH_HEIGHT, GLYPH_WIDTH},
+    chart_model::{Color, DisplayList, DrawCommand, Point, TextAlign, LINE_HEIGHT},
+};
+
+// Vertical samples per pixel row when computing coverage. Horizontal
+// coverage is computed exactly.
+const SUBSAMPLES: usize = 16;
+
+/// An RGBA8 (straight alpha) image that display lists can be rasterized
+/// into without a GPU.
+pub struct Canvas {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+impl Canvas {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![0; width as usize * height as usize * 4],
+        }
+    }
+
+    pub fn render(display_list: &DisplayList) -> Self {
+        let mut canvas = Self::new(
+            display_list.width.ceil() as u32,
+            display_list.height.ceil() as u32,
+        );
+        for command in &display_list.commands {
+            canvas.draw(command);
+        }
+        canvas
+    }
+
+    pub fn draw(&mut self, command: &DrawCommand) {
+        match command {
+            DrawCommand::GridLine {
+                from,
+                to,
+                color,
+                width,
+            } => {
+                let quad = stroke_segment(*from, *to, *width);
+                self.fill_polygons(&[quad], *color);
+            }
+            DrawCommand::Polyline {
+                points,
+                closed,
+                color,
+                width,
+            } => {
+                let mut quads: Vec<_> = points
+                    .windows(2)
+                    .map(|pair| stroke_segment(pair[0], pair[1], *width))
+                    .collect();
+                if *closed && points.len() > 2 {
+                    quads.push(stroke_segment(points[points.len() - 1], points[0], *width));
+                }
+                self.fill_polygons(&quads, *color);
+            }
+            DrawCommand::Fill { points, color } => {
+                self.fill_polygons(std::slice::from_ref(points), *color);
+            }
+            DrawCommand::Rect { rect, color, width } => {
+                let top_left = Point::new(rect.left, rect.top);
+                let top_right = Point::new(rect.right, rect.top);
+                let bottom_right = Point::new(rect.right, rect.bottom);
+                let bottom_left = Point::new(rect.left, rect.bottom);
+                let quads = [
+                    stroke_segment(top_left, top_right, *width),
+                    stroke_segment(top_right, bottom_right, *width),
+                    stroke_segment(bottom_right, bottom_left, *width),
+                    stroke_segment(bottom_left, top_left, *width),
+                ];
+                self.fill_polygons(&quads, *color);
+            }
+            DrawCommand::Text {
+                origin,
+                text,
+                color,
+                size,
+                align,
+            } => self.draw_text(*origin, text, *color, *size, *align),
+        }
+    }
+
+    pub fn save_png(&self, path: &Path) -> Result<(), png::EncodingError> {
+        let file = File::create(path)?;
+        let mut encoder = png::Encoder::new(BufWriter::new(file), self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.pixels)?;
+        writer.finish()
+    }
+
+    /// Fills the union of the polygons using the nonzero winding rule.
+    fn fill_polygons(&mut self, polygons: &[Vec<Point>], color: Color) {
+        let (min_y, max_y) = polygons
+            .iter()
+            .flatten()
+            .fold((f32::MAX, f32::MIN), |(min, max), point| {
+                (min.min(point.y), max.max(point.y))
+            });
+        if min_y > max_y {
+            return;
+        }
+        let first_row = min_y.floor().max(0.0) as u32;
+        let last_row = (max_y.ceil() as u32).min(self.height);
+
+        let mut coverage = vec![0.0f32; self.width as usize];
+        let mut crossings = Vec::new();
+        for row in first_row..last_row {
+            coverage.iter_mut().for_each(|value| *value = 0.0);
+            for sample in 0..SUBSAMPLES {
+                let y = row as f32 + (sample as f32 + 0.5) / SUBSAMPLES as f32;
+                crossings.clear();
+                for polygon in polygons {
+                    find_crossings(polygon, y, &mut crossings);
+                }
+                crossings.sort_by(|a: &(f32, i32), b| a.0.total_cmp(&b.0));
+
+                let mut winding = 0;
+                for pair in crossings.windows(2) {
+                    winding += pair[0].1;
+                    if winding != 0 {
+                        add_span(&mut coverage, pair[0].0, pair[1].0, 1.0 / SUBSAMPLES as f32);
+                    }
+                }
+            }
+            for (x, value) in coverage.iter().enumerate() {
+                if *value > 0.0 {
+                    self.blend(x as u32, row, color, value.min(1.0));
+                }
+            }
+        }
+    }
+
+    fn draw_text(&mut self, origin: Point, text: &str, color: Color, size: f32, align: TextAlign) {
+        let scale = glyph_scale(size);
+        let width = text_width(text, scale) as f32;
+        let left = match align {
+            TextAlign::Left => origin.x,
+            TextAlign::Right => origin.x - width,
+        }
+        .round() as i64;
+        // Center the glyph cell in the line box.
+        let top =
+            (origin.y + (size * LINE_HEIGHT - (GLYPH_HEIGHT * scale) as f32) / 2.0).round() as i64;
+
+        for (i, c) in text.chars().enumerate() {
+            let glyph_left = left + (i * GLYPH_ADVANCE * scale) as i64;
+            for (row, bits) in glyph(c).iter().enumerate() {
+                for column in 0..GLYPH_WIDTH {
+                    if bits & (1 << (GLYPH_WIDTH - 1 - column)) == 0 {
+                        continue;
+                    }
+                    let x = glyph_left + (column * scale) as i64;
+                    let y = top + (row * scale) as i64;
+                    self.fill_pixels(x, y, scale as i64, color);
+                }
+            }
+        }
+    }
+
+    fn fill_pixels(&mut self, left: i64, top: i64, size: i64, color: Color) {
+        for y in top.max(0)..(top + size).min(self.height as i64) {
+            for x in left.max(0)..(left + size).min(self.width as i64) {
+                self.blend(x as u32, y as u32, color, 1.0);
+            }
+        }
+    }
+
+    fn blend(&mut self, x: u32, y: u32, color: Color, coverage: f32) {
+        let index = (y as usize * self.width as usize + x as usize) * 4;
+        let pixel = &mut self.pixels[index..index + 4];
+
+        let source_alpha = color.a * coverage;
+        let dest_alpha = pixel[3] as f32 / 255.0;
+        let alpha = source_alpha + dest_alpha * (1.0 - source_alpha);
+        if alpha <= 0.0 {
+            return;
+        }
+        for (channel, source) in pixel.iter_mut().zip([color.r, color.g, color.b]) {
+            let dest = *channel as f32 / 255.0;
+            let value = (source * source_alpha + dest * dest_alpha * (1.0 - source_alpha)) / alpha;
+            *channel = (value * 255.0).round() as u8;
+        }
+        pixel[3] = (alpha * 255.0).round() as u8;
+    }
+}
+
+/// Width of `text` as drawn by `Canvas` at the given font size.
+pub fn measure_text(text: &str, size: f32) -> f32 {
+    text_width(text, glyph_scale(size)) as f32
+}
+
+fn glyph_scale(size: f32) -> usize {
+    // Segoe UI's capitals are roughly 0.7em tall, and ours are the top
+    // 7 rows of the glyph.
+    (size * 0.7 / (GLYPH_HEIGHT - 1) as f32).ceil().max(1.0) as usize
+}
+
+/// Builds the outline of a stroked line segment with square caps. Every
+/// segment is wound the same way so overlapping segments union cleanly.
+fn stroke_segment(from: Point, to: Point, width: f32) -> Vec<Point> {
+    let dx = to.x - from.x;
+    let dy = to.y - from.y;
+    let length = (dx * dx + dy * dy).sqrt();
+    if length == 0.0 {
+        return Vec::new();
+    }
+    let half_width = width / 2.0;
+    let (ux, uy) = (dx / length * half_width, dy / length * half_width);
+    let (nx, ny) = (-uy, ux);
+    let start = Point::new(from.x - ux, from.y - uy);
+    let end = Point::new(to.x + ux, to.y + uy);
+    vec![
+        Point::new(start.x + nx, start.y + ny),
+        Point::new(end.x + nx, end.y + ny),
+        Point::new(end.x - nx, end.y - ny),
+        Point::new(start.x - nx, start.y - ny),
+    ]
+}
+
+fn find_crossings(polygon: &[Point], y: f32, crossings: &mut Vec<(f32, i32)>) {
+    for (i, from) in polygon.iter().enumerate() {
+        let to = &polygon[(i + 1) % polygon.len()];
+        let direction = if from.y <= y && y < to.y {
+            1
+        } else if to.y <= y && y < from.y {
+            -1
+        } else {
+            continue;
+        };
+        let x = from.x + (y - from.y) * (to.x - from.x) / (to.y - from.y);
+        crossings.push((x, direction));
+    }
+}
+
+/// Adds `weight` times the horizontal overlap of [start, end) to each pixel.
+fn add_span(coverage: &mut [f32], start: f32, end: f32, weight: f32) {
+    let start = start.max(0.0);
+    let end = end.min(coverage.len() as f32);
+    if start >= end {
+        return;
+    }
+    for x in start.floor() as usize..(end.ceil() as usize).min(coverage.len()) {
+        let overlap = end.min((x + 1) as f32) - start.max(x as f32);
+        if overlap > 0.0 {
+            coverage[x] += overlap * weight;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRANSPARENT: [u8; 4] = [0, 0, 0, 0];
+
+    fn pixel(canvas: &Canvas, x: u32, y: u32) -> [u8; 4] {
+        let index = (y as usize * canvas.width as usize + x as usize) * 4;
+        canvas.pixels[index..index + 4].try_into().unwrap()
+    }
+
+    #[test]
+    fn renders_shapes() {
+        let canvas = Canvas::render(&DisplayList {
+            width: 8.0,
+            height: 7.5,
+            commands: vec![
+                DrawCommand::Fill {
+                    points: vec![
+                        Point::new(1.0, 1.0),
+                        Point::new(4.0, 1.0),
+                        Point::new(4.0, 4.0),
+                        Point::new(1.0, 4.0),
+                    ],
+                    color: Color::new(1.0, 0.0, 0.0, 1.0),
+                },
+                // Covers half of column 5.
+                DrawCommand::Fill {
+                    points: vec![
+                        Point::new(5.5, 1.0),
+                        Point::new(7.0, 1.0),
+                        Point::new(7.0, 3.0),
+                        Point::new(5.5, 3.0),
+                    ],
+                    color: Color::new(0.0, 0.0, 1.0, 1.0),
+                },
+                // Rows 5 and 6, past both edges because of the caps.
+                DrawCommand::GridLine {
+                    from: Point::new(0.0, 6.0),
+                    to: Point::new(8.0, 6.0),
+                    color: Color::new(0.0, 1.0, 0.0, 0.5),
+                    width: 2.0,
+                },
+            ],
+        });
+        assert_eq!((canvas.width, canvas.height), (8, 8));
+
+        assert_eq!(pixel(&canvas, 0, 0), TRANSPARENT);
+        assert_eq!(pixel(&canvas, 1, 1), [255, 0, 0, 255]);
+        assert_eq!(pixel(&canvas, 3, 3), [255, 0, 0, 255]);
+        assert_eq!(pixel(&canvas, 4, 3), TRANSPARENT);
+        assert_eq!(pixel(&canvas, 3, 4), TRANSPARENT);
+
+        assert_eq!(pixel(&canvas, 5, 1), [0, 0, 255, 128]);
+        assert_eq!(pixel(&canvas, 6, 2), [0, 0, 255, 255]);
+        assert_eq!(pixel(&canvas, 6, 3), TRANSPARENT);
+
+        assert_eq!(pixel(&canvas, 0, 5), [0, 255, 0, 128]);
+        assert_eq!(pixel(&canvas, 7, 6), [0, 255, 0, 128]);
+        assert_eq!(pixel(&canvas, 7, 7), TRANSPARENT);
+    }
+
+    #[test]
+    fn blends_over_what_is_drawn() {
+        let mut canvas = Canvas::new(1, 1);
+        canvas.blend(0, 0, Color::new(1.0, 0.0, 0.0, 1.0), 1.0);
+        canvas.blend(0, 0, Color::new(0.0, 0.0, 1.0, 0.5), 1.0);
+        assert_eq!(pixel(&canvas, 0, 0), [128, 0, 128, 255]);
+    }
+
+    #[test]
+    fn renders_text() {
+        // At size 10 glyphs are drawn unscaled, 3 rows down to center them
+        // in the line box.
+        let canvas = Canvas::render(&DisplayList {
+            width: 12.0,
+            height: 14.0,
+            commands: vec![
+                DrawCommand::Text {
+                    origin: Point::new(0.0, 0.0),
+                    text: "I".to_owned(),
+                    color: Color::new(1.0, 1.0, 1.0, 1.0),
+                    size: 10.0,
+                    align: TextAlign::Left,
+                },
+                DrawCommand::Text {
+                    origin: Point::new(12.0, 0.0),
+                    text: "I".to_owned(),
+                    color: Color::new(0.0, 1.0, 0.0, 1.0),
+                    size: 10.0,
+                    align: TextAlign::Right,
+                },
+            ],
+        });
+        let white = [255, 255, 255, 255];
+        let green = [0, 255, 0, 255];
+        // The serifs of the I.
+        let top: Vec<_> = (0..12).map(|x| pixel(&canvas, x, 3)).collect();
+        let t = TRANSPARENT;
+        assert_eq!(
+            top,
+            [t, white, white, white, t, t, t, t, green, green, green, t]
+        );
+        // The stem.
+        assert_eq!(pixel(&canvas, 1, 4), TRANSPARENT);
+        assert_eq!(pixel(&canvas, 2, 4), white);
+        assert_eq!(pixel(&canvas, 9, 4), green);
+        assert_eq!(pixel(&canvas, 2, 2), TRANSPARENT);
+    }
+}
@@ -0,0 +1,251 @@
+use std::{fmt::Write, fs, io, path::Path};
+
+use crate::chart_model::{Color, DisplayList, DrawCommand, Point, TextAlign};
+
+// Segoe UI's ascent, used to place the baseline within the line box.
+const FONT_ASCENT: f32 = 2210.0 / 2048.0;
+const FONT_FAMILY: &str = "'Segoe UI', sans-serif";
+// The viewer lays out the text, so widths can only be estimated. This is
+// a little wider than Segoe UI's average advance to err on the side of
+// leaving space.
+const AVERAGE_ADVANCE: f32 = 0.55;
+
+pub fn write_svg(display_list: &DisplayList, path: &Path) -> io::Result<()> {
+    fs::write(path, to_svg(display_list))
+}
+
+/// Estimated width of `text` at the given font size.
+pub fn measure_text(text: &str, size: f32) -> f32 {
+    text.chars().count() as f32 * size * AVERAGE_ADVANCE
+}
+
+/// Serializes a display list as a standalone SVG document.
+pub fn to_svg(display_list: &DisplayList) -> String {
+    let mut svg = String::new();
+    // Writing to a String can't fail.
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{1}" viewBox="0 0 {0} {1}">"#,
+        number(display_list.width),
+        number(display_list.height)
+    );
+    for command in &display_list.commands {
+        let _ = match command {
+            DrawCommand::GridLine {
+                from,
+                to,
+                color,
+                width,
+            } => writeln!(
+                svg,
+                r#"  <line x1="{}" y1="{}" x2="{}" y2="{}" {}/>"#,
+                number(from.x),
+                number(from.y),
+                number(to.x),
+                number(to.y),
+                stroke(color, *width)
+            ),
+            DrawCommand::Polyline {
+                points,
+                closed,
+                color,
+                width,
+            } => writeln!(
+                svg,
+                r#"  <{} points="{}" fill="none" {}/>"#,
+                if *closed { "polygon" } else { "polyline" },
+                point_list(points),
+                stroke(color, *width)
+            ),
+            DrawCommand::Fill { points, color } => writeln!(
+                svg,
+                r#"  <polygon points="{}" {}/>"#,
+                point_list(points),
+                fill(color)
+            ),
+            DrawCommand::Rect { rect, color, width } => writeln!(
+                svg,
+                r#"  <rect x="{}" y="{}" width="{}" height="{}" fill="none" {}/>"#,
+                number(rect.left),
+                number(rect.top),
+                number(rect.right - rect.left),
+                number(rect.bottom - rect.top),
+                stroke(color, *width)
+            ),
+            DrawCommand::Text {
+                origin,
+                text,
+                color,
+                size,
+                align,
+            } => writeln!(
+                svg,
+                r#"  <text x="{}" y="{}" font-family="{}" font-size="{}" text-anchor="{}" {}>{}</text>"#,
+                number(origin.x),
+                number(origin.y + size * FONT_ASCENT),
+                FONT_FAMILY,
+                number(*size),
+                match align {
+                    TextAlign::Left => "start",
+                    TextAlign::Right => "end",
+                },
+                fill(color),
+                escape(text)
+            ),
+        };
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+fn stroke(color: &Color, width: f32) -> String {
+    format!(
+        r#"stroke="{}" stroke-opacity="{}" stroke-width="{}""#,
+        hex_color(color),
+        number(color.a),
+        number(width)
+    )
+}
+
+fn fill(color: &Color) -> String {
+    format!(
+        r#"fill="{}" fill-opacity="{}""#,
+        hex_color(color),
+        number(color.a)
+    )
+}
+
+fn hex_color(color: &Color) -> String {
+    let channel = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+    format!(
+        "#{:02x}{:02x}{:02x}",
+        channel(color.r),
+        channel(color.g),
+        channel(color.b)
+    )
+}
+
+fn point_list(points: &[Point]) -> String {
+    points
+        .iter()
+        .map(|point| format!("{},{}", number(point.x), number(point.y)))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Formats a coordinate with at most two decimal places.
+fn number(value: f32) -> String {
+    let value = format!("{:.2}", value);
+    let value = value.trim_end_matches('0').trim_end_matches('.');
+    if value == "-0" {
+        "0".to_owned()
+    } else {
+        value.to_owned()
+    }
+}
+
+/// Escapes text for XML, replacing the characters XML 1.0 doesn't allow
+/// at all, not even as references.
+fn escape(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' => result.push_str("&quot;"),
+            '\'' => result.push_str("&apos;"),
+            '\0'..='\u{8}' | '\u{b}' | '\u{c}' | '\u{e}'..='\u{1f}' | '\u{fffe}' | '\u{ffff}' => {
+                result.push(char::REPLACEMENT_CHARACTER)
+            }
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chart_model::Rect;
+
+    const RED: Color = Color::new(1.0, 0.0, 0.0, 1.0);
+    const GRAY: Color = Color::new(0.5, 0.5, 0.5, 0.25);
+
+    #[test]
+    fn writes_every_command() {
+        let display_list = DisplayList {
+            width: 200.0,
+            height: 100.5,
+            commands: vec![
+                DrawCommand::GridLine {
+                    from: Point::new(0.0, 50.0),
+                    to: Point::new(200.0, 50.0),
+                    color: GRAY,
+                    width: 1.0,
+                },
+                DrawCommand::Fill {
+                    points: vec![Point::new(0.0, 100.0), Point::new(10.0, 20.0)],
+                    color: RED.with_alpha(0.4),
+                },
+                DrawCommand::Polyline {
+                    points: vec![Point::new(0.0, 100.0), Point::new(10.0, 20.126)],
+                    closed: false,
+                    color: RED,
+                    width: 2.0,
+                },
+                DrawCommand::Rect {
+                    rect: Rect {
+                        left: 0.5,
+                        top: 0.5,
+                        right: 199.5,
+                        bottom: 100.0,
+                    },
+                    color: GRAY,
+                    width: 1.0,
+                },
+                DrawCommand::Text {
+                    origin: Point::new(196.0, 0.0),
+                    text: "3D & Copy".to_owned(),
+                    color: RED,
+                    size: 10.0,
+                    align: TextAlign::Right,
+                },
+            ],
+        };
+        assert_eq!(
+            to_svg(&display_list).lines().collect::<Vec<_>>(),
+            [
+                r##"<svg xmlns="http://www.w3.org/2000/svg" width="200" height="100.5" viewBox="0 0 200 100.5">"##,
+                r##"  <line x1="0" y1="50" x2="200" y2="50" stroke="#808080" stroke-opacity="0.25" stroke-width="1"/>"##,
+                r##"  <polygon points="0,100 10,20" fill="#ff0000" fill-opacity="0.4"/>"##,
+                r##"  <polyline points="0,100 10,20.13" fill="none" stroke="#ff0000" stroke-opacity="1" stroke-width="2"/>"##,
+                r##"  <rect x="0.5" y="0.5" width="199" height="99.5" fill="none" stroke="#808080" stroke-opacity="0.25" stroke-width="1"/>"##,
+                r##"  <text x="196" y="10.79" font-family="'Segoe UI', sans-serif" font-size="10" text-anchor="end" fill="#ff0000" fill-opacity="1">3D &amp; Copy</text>"##,
+                "</svg>",
+            ]
+        );
+    }
+
+    #[test]
+    fn formats_numbers() {
+        assert_eq!(number(12.0), "12");
+        assert_eq!(number(12.5), "12.5");
+        assert_eq!(number(0.333), "0.33");
+        assert_eq!(number(-0.001), "0");
+    }
+
+    #[test]
+    fn escapes_text() {
+        assert_eq!(
+            escape(r#"<a href="x">'&'</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;&apos;&amp;&apos;&lt;/a&gt;"
+        );
+        assert_eq!(escape("tab\tand\nnewline"), "tab\tand\nnewline");
+        assert_eq!(
+            escape("nul\0 bell\u{7} esc\u{1b}"),
+            "nul\u{fffd} bell\u{fffd} esc\u{fffd}"
+        );
+        assert_eq!(escape("Übersicht \u{ffff}"), "Übersicht \u{fffd}");
+    }
+}
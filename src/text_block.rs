@@ -88,6 +88,10 @@ impl TextBlock {
         &self.root
     }
 
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
     pub fn set_text(&mut self, renderer: &Renderer, text: String) -> Result<()> {
         self.text = text;
         let text_layout = unsafe {
@@ -0,0 +1,259 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io::{self, Write},
+    time::Duration,
+};
+
+use crate::metric::{Reading, SeriesOrigin};
+
+// Every source that doesn't measure a process is shown under a made-up
+// pid of its own, named after the source. They count down from here, so
+// they don't clash with real processes.
+const FIRST_SOURCE_PROCESS_ID: u32 = u32::MAX;
+
+/// A counter track: the series of one source in one process.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct Track {
+    name: String,
+    process_id: u32,
+}
+
+/// Writes samples as Chrome trace events, which open in ui.perfetto.dev
+/// and chrome://tracing. Every source gets a counter (`"ph":"C"`) in the
+/// process it measures, with one value per series, and every process is
+/// named with a metadata event.
+///
+/// The trace is a JSON array of events. Trace viewers accept one without
+/// its closing bracket, so a trace cut short can still be opened.
+pub struct TraceWriter<W: Write> {
+    writer: W,
+    /// The track of every series seen so far, by name.
+    tracks: HashMap<String, Track>,
+    named_processes: HashSet<u32>,
+    /// The made-up pid of every source without a process, by name.
+    source_process_ids: HashMap<String, u32>,
+    is_first_event: bool,
+}
+
+impl<W: Write> TraceWriter<W> {
+    pub fn new(mut writer: W) -> io::Result<Self> {
+        writeln!(writer, "[")?;
+        Ok(Self {
+            writer,
+            tracks: HashMap::new(),
+            named_processes: HashSet::new(),
+            source_process_ids: HashMap::new(),
+            is_first_event: true,
+        })
+    }
+
+    /// Writes one tick worth of readings, `elapsed` after sampling
+    /// started. `origin` tells where a series first seen comes from.
+    /// Missing values are left out, so counters hold their last value.
+    pub fn write_tick<F: Fn(&str) -> SeriesOrigin>(
+        &mut self,
+        elapsed: Duration,
+        readings: &[Reading],
+        origin: F,
+    ) -> io::Result<()> {
+        // One event per track, in the order the tracks show up.
+        let mut events: Vec<(Track, Vec<(&str, f64)>)> = Vec::new();
+        for reading in readings {
+            let Some(value) = reading.sample.value.filter(|value| value.is_finite()) else {
+                continue;
+            };
+            if !self.tracks.contains_key(&reading.series) {
+                let track = self.add_track(origin(&reading.series))?;
+                self.tracks.insert(reading.series.clone(), track);
+            }
+            let track = &self.tracks[&reading.series];
+            match events.iter_mut().find(|(other, _)| other == track) {
+                Some((_, values)) => values.push((&reading.series, value)),
+                None => events.push((track.clone(), vec![(&reading.series, value)])),
+            }
+        }
+
+        let timestamp = elapsed.as_micros();
+        for (track, values) in events {
+            let args: Vec<String> = values
+                .iter()
+                .map(|(series, value)| format!("{}:{}", json_string(series), value))
+                .collect();
+            let event = format!(
+                r#"{{"name":{},"ph":"C","ts":{},"pid":{},"args":{{{}}}}}"#,
+                json_string(&track.name),
+                timestamp,
+                track.process_id,
+                args.join(",")
+            );
+            self.write_event(&event)?;
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        writeln!(self.writer, "\n]")?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    /// Names the process of a new track the first time it shows up.
+    fn add_track(&mut self, origin: SeriesOrigin) -> io::Result<Track> {
+        let (process_id, process_name) = match &origin.process {
+            Some(process) => (process.process_id, process.name.as_str()),
+            None => {
+                let next_id = FIRST_SOURCE_PROCESS_ID - self.source_process_ids.len() as u32;
+                let process_id = *self
+                    .source_process_ids
+                    .entry(origin.source.clone())
+                    .or_insert(next_id);
+                (process_id, origin.source.as_str())
+            }
+        };
+        if self.named_processes.insert(process_id) {
+            let event = format!(
+                r#"{{"name":"process_name","ph":"M","pid":{},"args":{{"name":{}}}}}"#,
+                process_id,
+                json_string(process_name)
+            );
+            self.write_event(&event)?;
+        }
+        Ok(Track {
+            name: origin.source,
+            process_id,
+        })
+    }
+
+    fn write_event(&mut self, event: &str) -> io::Result<()> {
+        if !self.is_first_event {
+            writeln!(self.writer, ",")?;
+        }
+        self.is_first_event = false;
+        write!(self.writer, "{}", event)
+    }
+}
+
+/// Quotes `text` as a JSON string.
+fn json_string(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for char in text.chars() {
+        match char {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            char if char.is_control() => quoted.push_str(&format!("\\u{:04x}", char as u32)),
+            char => quoted.push(char),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metric::{ProcessInfo, Sample, SampleStatus};
+
+    fn origin(series: &str) -> SeriesOrigin {
+        let (source, process) = match series {
+            "3D" | "Copy" => ("GPU", Some((1234, "dwm.exe"))),
+            "game 3D" => ("GPU", Some((5678, "game.exe"))),
+            "Total" => ("CPU", None),
+            _ => ("Counters", None),
+        };
+        SeriesOrigin {
+            source: source.to_owned(),
+            process: process.map(|(process_id, name)| ProcessInfo {
+                process_id,
+                name: name.to_owned(),
+            }),
+        }
+    }
+
+    fn trace(ticks: &[(u64, Vec<Reading>)]) -> Vec<String> {
+        let mut writer = TraceWriter::new(Vec::new()).unwrap();
+        for (millis, readings) in ticks {
+            writer
+                .write_tick(Duration::from_millis(*millis), readings, origin)
+                .unwrap();
+        }
+        let trace = String::from_utf8(writer.finish().unwrap()).unwrap();
+        trace
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(|line| line.trim_end_matches(',').to_owned())
+            .collect()
+    }
+
+    #[test]
+    fn writes_counter_events() {
+        let events = trace(&[
+            (
+                1500,
+                vec![
+                    Reading::new("3D", Sample::valid(12.5)),
+                    Reading::new("game 3D", Sample::valid(80.0)),
+                    Reading::new("Copy", Sample::valid(3.0)),
+                ],
+            ),
+            (2500, vec![Reading::new("3D", Sample::valid(14.0))]),
+        ]);
+        assert_eq!(
+            events,
+            [
+                "[",
+                r#"{"name":"process_name","ph":"M","pid":1234,"args":{"name":"dwm.exe"}}"#,
+                r#"{"name":"process_name","ph":"M","pid":5678,"args":{"name":"game.exe"}}"#,
+                r#"{"name":"GPU","ph":"C","ts":1500000,"pid":1234,"args":{"3D":12.5,"Copy":3}}"#,
+                r#"{"name":"GPU","ph":"C","ts":1500000,"pid":5678,"args":{"game 3D":80}}"#,
+                r#"{"name":"GPU","ph":"C","ts":2500000,"pid":1234,"args":{"3D":14}}"#,
+                "]",
+            ]
+        );
+    }
+
+    #[test]
+    fn leaves_out_missing_values() {
+        let events = trace(&[(
+            0,
+            vec![
+                Reading::new("3D", Sample::missing(SampleStatus::NoInstance)),
+                Reading::new("Copy", Sample::valid(f64::NAN)),
+                Reading::new("game 3D", Sample::valid(f64::INFINITY)),
+            ],
+        )]);
+        assert_eq!(events, ["[", "]"]);
+    }
+
+    #[test]
+    fn gives_every_source_without_a_process_its_own_pid() {
+        let events = trace(&[(
+            0,
+            vec![
+                Reading::new("Total", Sample::valid(50.0)),
+                Reading::new(r"Memory\Available Bytes", Sample::valid(8e9)),
+            ],
+        )]);
+        assert_eq!(
+            events[1..3],
+            [
+                r#"{"name":"process_name","ph":"M","pid":4294967295,"args":{"name":"CPU"}}"#,
+                r#"{"name":"process_name","ph":"M","pid":4294967294,"args":{"name":"Counters"}}"#,
+            ]
+        );
+        assert!(events[3].contains(r#""pid":4294967295"#));
+        assert!(events[4].contains(r#""pid":4294967294"#));
+    }
+
+    #[test]
+    fn escapes_json_strings() {
+        assert_eq!(json_string("3D"), r#""3D""#);
+        assert_eq!(json_string(r#"say "hi" \ bye"#), r#""say \"hi\" \\ bye""#);
+        assert_eq!(json_string("a\nb\r\tc"), r#""a\nb\r\tc""#);
+        assert_eq!(json_string("\u{0}\u{1b}\u{7f}"), r#""\u0000\u001b\u007f""#);
+        assert_eq!(json_string("Übersicht"), r#""Übersicht""#);
+    }
+}
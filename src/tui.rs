@@ -0,0 +1,474 @@
+use std::time::{Duration, Instant};
+
+use ratatui::{
+    buffer::Buffer,
+    crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Style, Stylize},
+    text::{Line, Span},
+    widgets::{Block, Widget},
+    DefaultTerminal, Frame,
+};
+
+use crate::{
+    axis::YAxis,
+    chart_model::{self, ChartModel, ChartOptions, DEFAULT_HEIGHT, DEFAULT_WIDTH},
+    metric::{self, MetricSource, Reading, Unit},
+    playback::{apply_action, PlaybackClock, PlaybackCommand},
+    scene::{
+        axis_labels, layout_axis_labels, legend_entries, title, value_label, AxisLabel, AxisLabels,
+        LegendEntry, AXIS_LABEL_GAP,
+    },
+};
+
+const OUTLINE_COLOR: Color = Color::Rgb(0x11, 0x7D, 0xBB);
+const VALUE_COLOR: Color = Color::Rgb(0x70, 0x70, 0x70);
+// How long to wait for keys while a playback is paused or finished.
+const IDLE_POLL_INTERVAL: Duration = Duration::from_secs(1);
+// Instant playback still waits a little for keys between frames.
+const MIN_TICK_INTERVAL: Duration = Duration::from_millis(1);
+// Leaves one cell between the chart and its axis labels, which is where
+// the border goes.
+const LABEL_SCALE: f32 = 1.0 / AXIS_LABEL_GAP;
+
+// Braille cells are 2 dots wide and 4 dots tall. Indexed by [y][x].
+const BRAILLE_DOTS: [[u8; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+/// Everything the terminal frontend draws, kept separate from the terminal
+/// so it can be rendered into any ratatui backend.
+pub struct TuiState {
+    pub title: String,
+    pub value: String,
+    pub legend: Vec<LegendEntry>,
+    /// Laid out for the chart's own size, which the terminal scales to
+    /// fit.
+    pub axis: AxisLabels,
+    pub chart: ChartModel,
+}
+
+impl TuiState {
+    pub fn new(title: String, value: String) -> Self {
+        Self {
+            title,
+            value,
+            legend: Vec::new(),
+            axis: AxisLabels::default(),
+            chart: ChartModel::new(DEFAULT_WIDTH as f32, DEFAULT_HEIGHT as f32),
+        }
+    }
+}
+
+/// Charts `source` in the terminal until the user presses q or Esc.
+pub fn run(source: Box<dyn MetricSource>, options: &ChartOptions) -> metric::Result<()> {
+    let mut terminal = ratatui::init();
+    let result = run_loop(&mut terminal, source, options);
+    ratatui::restore();
+    result
+}
+
+fn run_loop(
+    terminal: &mut DefaultTerminal,
+    mut source: Box<dyn MetricSource>,
+    options: &ChartOptions,
+) -> metric::Result<()> {
+    let unit = source.unit();
+    let mut state = TuiState::new(source.name().to_owned(), unit.format_value(0.0));
+    state.chart.set_options(options);
+    state.axis = axis_labels(&state.chart, unit);
+    let mut playback = source
+        .playback()
+        .map(|playback| PlaybackClock::new(playback, state.chart.point_count()));
+    // The arrow keys page through a recording a chart at a time.
+    let page = state.chart.point_count() as i64 - 1;
+
+    source.start()?;
+    let mut last_tick = Instant::now();
+    loop {
+        state.title = title(source.as_ref(), playback.as_ref());
+        terminal.draw(|frame| draw(frame, &state))?;
+
+        let interval = match &playback {
+            Some(clock) if clock.is_paused() || clock.is_finished() => IDLE_POLL_INTERVAL,
+            Some(clock) => clock.tick_interval().max(MIN_TICK_INTERVAL),
+            None => options.time_scale.interval,
+        };
+        if event::poll(interval.saturating_sub(last_tick.elapsed()))? {
+            match event::read()? {
+                Event::Key(key) if key.kind == KeyEventKind::Press => {
+                    let is_ctrl_c = key.code == KeyCode::Char('c')
+                        && key.modifiers.contains(KeyModifiers::CONTROL);
+                    if key.code == KeyCode::Char('q') || key.code == KeyCode::Esc || is_ctrl_c {
+                        break;
+                    }
+                    let command = playback_command(key.code, page);
+                    if let (Some(clock), Some(command)) = (playback.as_mut(), command) {
+                        let action = clock.apply(command);
+                        let readings = apply_action(action, source.as_mut(), &mut state.chart)?;
+                        show_readings(&mut state, unit, readings.as_deref());
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        match playback.as_mut() {
+            Some(clock) => {
+                let now = Instant::now();
+                let action = clock.advance(now - last_tick);
+                last_tick = now;
+                let readings = apply_action(action, source.as_mut(), &mut state.chart)?;
+                show_readings(&mut state, unit, readings.as_deref());
+            }
+            None if last_tick.elapsed() >= interval => {
+                last_tick = Instant::now();
+                let readings = source.sample()?;
+                state.chart.add_readings(&readings);
+                show_readings(&mut state, unit, Some(&readings));
+            }
+            None => {}
+        }
+    }
+    source.close()
+}
+
+/// Updates the text around the chart after new readings were charted, or
+/// after the chart was cleared if `readings` is `None`.
+fn show_readings(state: &mut TuiState, unit: Unit, readings: Option<&[Reading]>) {
+    if let Some(readings) = readings {
+        state.value = value_label(unit, readings);
+    }
+    state.legend = legend_entries(&state.chart, unit);
+    state.axis = axis_labels(&state.chart, unit);
+}
+
+fn playback_command(key: KeyCode, page: i64) -> Option<PlaybackCommand> {
+    let command = match key {
+        KeyCode::Char(' ') => PlaybackCommand::TogglePause,
+        KeyCode::Char('.') => PlaybackCommand::StepForward,
+        KeyCode::Char(',') => PlaybackCommand::StepBack,
+        KeyCode::Right => PlaybackCommand::SeekBy(page),
+        KeyCode::Left => PlaybackCommand::SeekBy(-page),
+        KeyCode::Home => PlaybackCommand::SeekTo(0),
+        KeyCode::End => PlaybackCommand::SeekTo(u64::MAX),
+        KeyCode::Up | KeyCode::Char('+') | KeyCode::Char('=') => PlaybackCommand::Faster,
+        KeyCode::Down | KeyCode::Char('-') => PlaybackCommand::Slower,
+        _ => return None,
+    };
+    Some(command)
+}
+
+pub fn draw(frame: &mut Frame, state: &TuiState) {
+    let legend_height = if state.legend.is_empty() { 0 } else { 1 };
+    let [header, legend, body] = Layout::vertical([
+        Constraint::Length(1),
+        Constraint::Length(legend_height),
+        Constraint::Min(0),
+    ])
+    .areas(frame.area());
+
+    // Mirrors the info row above the chart in the window.
+    let value_width = state.value.chars().count() as u16;
+    let [title_area, value_area] =
+        Layout::horizontal([Constraint::Min(0), Constraint::Length(value_width)]).areas(header);
+    frame.render_widget(Line::from(state.title.as_str()), title_area);
+    frame.render_widget(Line::from(state.value.as_str()).fg(VALUE_COLOR), value_area);
+
+    let legend_spans: Vec<Span> = state
+        .legend
+        .iter()
+        .flat_map(|entry| {
+            [
+                Span::from("■ ").fg(to_terminal_color(entry.color)),
+                Span::from(format!("{}  ", entry.text())),
+            ]
+        })
+        .collect();
+    frame.render_widget(Line::from(legend_spans), legend);
+
+    // Value labels go left of the chart and time labels below it, as in
+    // the window.
+    let label_width = state
+        .axis
+        .values
+        .iter()
+        .map(|label| label.text.chars().count())
+        .max()
+        .unwrap_or(0) as u16;
+    let time_label_height = if state.axis.times.is_empty() { 0 } else { 1 };
+    let [_, body] =
+        Layout::horizontal([Constraint::Length(label_width), Constraint::Min(0)]).areas(body);
+    let [body, _] =
+        Layout::vertical([Constraint::Min(0), Constraint::Length(time_label_height)]).areas(body);
+
+    let block = Block::bordered().border_style(Style::new().fg(OUTLINE_COLOR));
+    let chart_area = block.inner(body);
+    frame.render_widget(block, body);
+    render_axis_labels(&state.axis, chart_area, frame.buffer_mut());
+    frame.render_widget(
+        AreaChart {
+            bands: state
+                .chart
+                .bands()
+                .into_iter()
+                .map(|band| (band.points, to_terminal_color(band.series.color())))
+                .collect(),
+            point_count: state.chart.point_count(),
+            y_axis: *state.chart.y_axis(),
+        },
+        chart_area,
+    );
+}
+
+/// Draws `labels` around `chart_area`, scaled from the chart's size to the
+/// area's. Labels that don't fit in the terminal are left out.
+fn render_axis_labels(labels: &AxisLabels, chart_area: Rect, buf: &mut Buffer) {
+    let (width, height) = (chart_area.width as f32, chart_area.height as f32);
+    let scale = |labels: &[AxisLabel], factor: f32| -> Vec<AxisLabel> {
+        labels
+            .iter()
+            .map(|label| AxisLabel {
+                text: label.text.clone(),
+                position: label.position * factor,
+            })
+            .collect()
+    };
+    let scaled = AxisLabels {
+        values: scale(&labels.values, height / DEFAULT_HEIGHT as f32),
+        times: scale(&labels.times, width / DEFAULT_WIDTH as f32),
+    };
+    let text_width = |label: &AxisLabel| label.text.chars().count() as f32;
+    let value_widths: Vec<f32> = scaled.values.iter().map(text_width).collect();
+    let time_widths: Vec<f32> = scaled.times.iter().map(text_width).collect();
+    let layout = layout_axis_labels(
+        &scaled,
+        &value_widths,
+        &time_widths,
+        width,
+        height,
+        1.0,
+        LABEL_SCALE,
+    );
+
+    let placed = scaled
+        .values
+        .iter()
+        .zip(&layout.values)
+        .chain(scaled.times.iter().zip(&layout.times));
+    for (label, point) in placed {
+        let Some(point) = point else {
+            continue;
+        };
+        let x = chart_area.x as f32 + point.x.round();
+        let y = chart_area.y as f32 + point.y.round();
+        if x < 0.0 || y < 0.0 || !buf.area.contains((x as u16, y as u16).into()) {
+            continue;
+        }
+        buf.set_string(
+            x as u16,
+            y as u16,
+            &label.text,
+            Style::new().fg(VALUE_COLOR),
+        );
+    }
+}
+
+fn to_terminal_color(color: chart_model::Color) -> Color {
+    let channel = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+    Color::Rgb(channel(color.r), channel(color.g), channel(color.b))
+}
+
+/// The `(base, top)` of a band at each point, see `chart_model::Band`.
+type BandPoints = Vec<Option<(f32, f32)>>;
+
+/// Draws each series' band as a filled area using braille dots, laid out
+/// the same way as the chart: the newest point sits on the right edge. A
+/// cell takes the color of the last band that reaches it.
+struct AreaChart {
+    bands: Vec<(BandPoints, Color)>,
+    point_count: usize,
+    y_axis: YAxis,
+}
+
+impl Widget for AreaChart {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        if area.is_empty() {
+            return;
+        }
+        for (points, color) in &self.bands {
+            render_area(points, *color, self.point_count, &self.y_axis, area, buf);
+        }
+    }
+}
+
+fn render_area(
+    points: &[Option<(f32, f32)>],
+    color: Color,
+    point_count: usize,
+    y_axis: &YAxis,
+    area: Rect,
+    buf: &mut Buffer,
+) {
+    if points.is_empty() {
+        return;
+    }
+    let dots_wide = area.width as usize * 2;
+    let dots_tall = area.height as usize * 4;
+    // Index of the first slot that has data.
+    let first_slot = (point_count - points.len()) as f32;
+
+    for column in 0..dots_wide {
+        let slot = if dots_wide > 1 {
+            column as f32 / (dots_wide - 1) as f32 * (point_count - 1) as f32
+        } else {
+            (point_count - 1) as f32
+        };
+        let position = slot - first_slot;
+        if position < 0.0 {
+            continue;
+        }
+        let Some((base, top)) = interpolate(points, position) else {
+            continue;
+        };
+        let to_dots =
+            |value: f32| (y_axis.fraction(value as f64) * dots_tall as f64).round() as usize;
+
+        for dot in to_dots(base)..to_dots(top) {
+            // Dots are counted up from the bottom of the area.
+            let y = dots_tall - 1 - dot;
+            let cell = (area.x + (column / 2) as u16, area.y + (y / 4) as u16);
+            if let Some(cell) = buf.cell_mut(cell) {
+                let bits = cell.symbol().chars().next().map_or(0, braille_bits);
+                let bits = bits | BRAILLE_DOTS[y % 4][column % 2];
+                cell.set_char(braille(bits)).set_fg(color);
+            }
+        }
+    }
+}
+
+fn interpolate(points: &[Option<(f32, f32)>], position: f32) -> Option<(f32, f32)> {
+    let index = position.floor() as usize;
+    let fraction = position - index as f32;
+    let lerp = |a: f32, b: f32| a + (b - a) * fraction;
+    match (points.get(index).copied().flatten(), points.get(index + 1)) {
+        (Some(a), Some(Some(b))) => Some((lerp(a.0, b.0), lerp(a.1, b.1))),
+        // The newest point.
+        (Some(a), None) => Some(a),
+        // The area stops right at the start of a gap.
+        (Some(a), Some(None)) if fraction == 0.0 => Some(a),
+        _ => None,
+    }
+}
+
+fn braille(bits: u8) -> char {
+    char::from_u32(0x2800 + bits as u32).unwrap()
+}
+
+fn braille_bits(c: char) -> u8 {
+    match c as u32 {
+        code @ 0x2800..=0x28FF => (code - 0x2800) as u8,
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::{backend::TestBackend, Terminal};
+
+    use super::*;
+
+    /// Draws `state` into a terminal `width` by `height` cells and returns
+    /// the text of every row along with the cells.
+    fn render(state: &TuiState, width: u16, height: u16) -> (Vec<String>, Buffer) {
+        let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+        terminal.draw(|frame| draw(frame, state)).unwrap();
+        let buffer = terminal.backend().buffer().clone();
+        let rows = (0..height)
+            .map(|y| (0..width).map(|x| buffer[(x, y)].symbol()).collect())
+            .collect();
+        (rows, buffer)
+    }
+
+    /// Two series that have been charted for a while.
+    fn state() -> TuiState {
+        let mut state = TuiState::new("dwm.exe".to_owned(), "60%".to_owned());
+        for i in 0..state.chart.point_count() {
+            let copy = if i == 5 { None } else { Some(20.0) };
+            state.chart.add_values([("3D", Some(60.0)), ("Copy", copy)]);
+        }
+        show_readings(&mut state, Unit::Percent, None);
+        state
+    }
+
+    #[test]
+    fn draws_the_header_and_legend() {
+        let (rows, buffer) = render(&state(), 60, 20);
+        assert!(rows[0].starts_with("dwm.exe "), "{:#?}", rows);
+        assert!(rows[0].ends_with(" 60%"), "{:#?}", rows);
+        assert_eq!(buffer[(57, 0)].fg, VALUE_COLOR);
+
+        assert!(rows[1].starts_with("■ 3D 60%  ■ Copy 20%"), "{:#?}", rows);
+        let colors = state().legend;
+        assert_eq!(buffer[(0, 1)].fg, to_terminal_color(colors[0].color));
+        assert_eq!(buffer[(10, 1)].fg, to_terminal_color(colors[1].color));
+    }
+
+    #[test]
+    fn draws_axis_labels_around_the_chart() {
+        let (rows, buffer) = render(&state(), 60, 20);
+        // The chart's border starts right after the widest value label,
+        // and ends above the time labels.
+        assert!(rows[2].starts_with("    ┌"), "{:#?}", rows);
+        assert!(rows[3].starts_with("100%│"), "{:#?}", rows);
+        assert!(rows[10].starts_with(" 50%│"), "{:#?}", rows);
+        assert!(rows[17].starts_with("  0%│"), "{:#?}", rows);
+        assert!(rows[18].starts_with("    └"), "{:#?}", rows);
+        assert!(rows[18].ends_with('┘'), "{:#?}", rows);
+        assert_eq!(buffer[(0, 3)].fg, VALUE_COLOR);
+        assert_eq!(buffer[(4, 3)].fg, OUTLINE_COLOR);
+
+        // The newest time is at the right edge, and older ones to its left.
+        let times = &rows[19];
+        assert!(times.trim_end().ends_with("0s"), "{:#?}", rows);
+        let newest = times.rfind("0s").unwrap();
+        let oldest = times.find("50s").unwrap();
+        assert!(oldest < newest);
+    }
+
+    #[test]
+    fn draws_the_areas() {
+        let (rows, buffer) = render(&state(), 60, 20);
+        // 60% of 15 rows fills the bottom 9 of them. The series overlap,
+        // so the bottom 3 take the color of the one drawn last.
+        let column = 40;
+        let filled: Vec<u16> = (3..18)
+            .filter(|y| buffer[(column, *y)].symbol() != " ")
+            .collect();
+        assert_eq!(filled, (9..18).collect::<Vec<_>>(), "{:#?}", rows);
+        let legend = state().legend;
+        assert_eq!(buffer[(column, 9)].fg, to_terminal_color(legend[0].color));
+        assert_eq!(buffer[(column, 17)].fg, to_terminal_color(legend[1].color));
+    }
+
+    #[test]
+    fn leaves_out_labels_that_dont_fit() {
+        let (rows, _) = render(&state(), 12, 6);
+        assert!(rows[3].starts_with("  0%│"), "{:#?}", rows);
+        assert!(!rows.iter().any(|row| row.contains("50%")), "{:#?}", rows);
+        // Nothing panics in a terminal too small for the chart.
+        render(&state(), 3, 2);
+        render(&TuiState::new(String::new(), String::new()), 1, 1);
+    }
+
+    #[test]
+    fn maps_keys_to_commands() {
+        assert_eq!(
+            playback_command(KeyCode::Char(' '), 60),
+            Some(PlaybackCommand::TogglePause)
+        );
+        assert_eq!(
+            playback_command(KeyCode::Left, 60),
+            Some(PlaybackCommand::SeekBy(-60))
+        );
+        assert_eq!(playback_command(KeyCode::Char('x'), 60), None);
+    }
+}
@@ -9,13 +9,14 @@ use windows::{
         System::{LibraryLoader::GetModuleHandleW, WindowsProgramming::MulDiv},
         UI::{
             HiDpi::{AdjustWindowRectExForDpi, GetDpiForWindow},
+            Input::KeyboardAndMouse::VIRTUAL_KEY,
             WindowsAndMessaging::{
                 CreateWindowExW, DefWindowProcW, DestroyWindow, GetClientRect, GetWindowLongPtrW,
                 LoadCursorW, PostQuitMessage, RegisterClassW, SetWindowLongPtrW, SetWindowPos,
                 ShowWindow, CREATESTRUCTW, CW_USEDEFAULT, GWLP_USERDATA, IDC_ARROW, SWP_NOACTIVATE,
-                SWP_NOMOVE, SWP_NOZORDER, SW_SHOW, WM_DESTROY, WM_DPICHANGED, WM_LBUTTONDOWN,
-                WM_MOUSEMOVE, WM_NCCREATE, WM_RBUTTONDOWN, WM_SIZE, WM_SIZING, WNDCLASSW,
-                WS_EX_NOREDIRECTIONBITMAP, WS_OVERLAPPEDWINDOW,
+                SWP_NOMOVE, SWP_NOZORDER, SW_SHOW, WM_DESTROY, WM_DPICHANGED, WM_KEYDOWN,
+                WM_LBUTTONDOWN, WM_MOUSEMOVE, WM_NCCREATE, WM_RBUTTONDOWN, WM_SIZE, WM_SIZING,
+                WNDCLASSW, WS_EX_NOREDIRECTIONBITMAP, WS_OVERLAPPEDWINDOW,
             },
         },
     },
@@ -162,6 +163,12 @@ impl Window {
             WM_RBUTTONDOWN => {
                 //self.game.on_pointer_pressed(true, false).unwrap();
             }
+            WM_KEYDOWN => {
+                if let Some(app) = self.app.as_mut() {
+                    app.on_key_down(VIRTUAL_KEY(wparam.0 as u16));
+                }
+                return LRESULT(0);
+            }
             WM_DPICHANGED => {
                 let rect: *const RECT = unsafe { std::mem::transmute(lparam) };
                 let rect = unsafe { rect.as_ref().unwrap() };
@@ -0,0 +1,37 @@
+use windows::{
+    core::Result,
+    Win32::{
+        Foundation::LUID,
+        Graphics::Dxgi::{
+            CreateDXGIFactory1, IDXGIFactory1, DXGI_ADAPTER_DESC1, DXGI_ERROR_NOT_FOUND,
+        },
+    },
+};
+
+/// Returns the LUID and description of every adapter on the system.
+pub fn enumerate_adapters() -> Result<Vec<(LUID, String)>> {
+    let factory: IDXGIFactory1 = unsafe { CreateDXGIFactory1()? };
+    let mut adapters = Vec::new();
+    let mut index = 0;
+    loop {
+        let adapter = match unsafe { factory.EnumAdapters1(index) } {
+            Ok(adapter) => adapter,
+            Err(error) if error.code() == DXGI_ERROR_NOT_FOUND => break,
+            Err(error) => return Err(error),
+        };
+        let desc = unsafe {
+            let mut desc = DXGI_ADAPTER_DESC1::default();
+            adapter.GetDesc1(&mut desc)?;
+            desc
+        };
+        let length = desc
+            .Description
+            .iter()
+            .position(|c| *c == 0)
+            .unwrap_or(desc.Description.len());
+        let name = String::from_utf16_lossy(&desc.Description[..length]);
+        adapters.push((desc.AdapterLuid, name));
+        index += 1;
+    }
+    Ok(adapters)
+}
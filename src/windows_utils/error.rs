@@ -0,0 +1,10 @@
+use windows::{core::Error, Win32::Foundation::E_FAIL};
+
+/// Converts an error from a non-Windows specific module, preserving the
+/// original error if it came from a Windows API.
+pub fn to_windows_error(error: Box<dyn std::error::Error>) -> Error {
+    match error.downcast::<Error>() {
+        Ok(error) => *error,
+        Err(error) => Error::new(E_FAIL, error.to_string()),
+    }
+}
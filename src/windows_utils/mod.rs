@@ -3,5 +3,7 @@ pub mod d2d;
 pub mod d3d;
 pub mod dispatcher_queue;
 pub mod dwrite;
+pub mod dxgi;
+pub mod error;
 pub mod handle;
 pub mod numerics;